@@ -23,6 +23,14 @@ pub fn to_string(x: *const c_char) -> String {
     }
 }
 
+// Like to_string, but reports invalid UTF-8 instead of mapping it to an empty string.
+pub fn try_to_string(x: *const c_char) -> Result<String, std::str::Utf8Error> {
+    if x.is_null() {
+        return Ok("".to_string());
+    }
+    unsafe { CStr::from_ptr(x) }.to_str().map(|s| s.to_string())
+}
+
 pub fn to_c_char_ptr(x: &str) -> *const c_char {
     CString::new(x)
         .map(|s| s.into_raw())
@@ -233,7 +241,7 @@ impl Drop for MapStringString {
                     let _unused = unsafe { CString::from_raw(*item as *mut c_char) };
                 }
             }
-            let _unused = unsafe { Box::from_raw(self.key as *mut *const c_char) };
+            let _unused = unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(self.key as *mut *const c_char, self.len)) };
         }
         if !self.value.is_null() {
             let slice = unsafe { std::slice::from_raw_parts(self.value, self.len) };
@@ -242,7 +250,7 @@ impl Drop for MapStringString {
                     let _unused = unsafe { CString::from_raw(*item as *mut c_char) };
                 }
             }
-            let _unused = unsafe { Box::from_raw(self.value as *mut *const c_char) };
+            let _unused = unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(self.value as *mut *const c_char, self.len)) };
         }
     }
 }
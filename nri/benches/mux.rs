@@ -0,0 +1,191 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// Compares the reactor based mux with the thread per connection one it
+// replaced: for a number of plugins, each with two connections like in
+// plugin::connect, data written to the connections goes through the mux,
+// is echoed back by the plugin side of the trunk and read back from the
+// connections. Reports the throughput and the threads the muxes use.
+//
+// Run with: cargo bench --bench mux
+
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use isula_nri::nri::mux::Mux;
+
+const CONNS_PER_PLUGIN: u32 = 2;
+const CHUNK_SIZE: usize = 16 * 1024;
+const BYTES_PER_CONN: usize = 16 * 1024 * 1024;
+
+// The thread per connection mux, with its 1 KiB buffer, kept as baseline.
+mod legacy {
+    use std::collections::HashMap;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    pub fn start(trunk: UnixStream, conns: Vec<(u32, UnixStream)>) {
+        let write_lock = Arc::new(Mutex::new(()));
+        let mut writers = HashMap::new();
+        for (id, mut stream) in conns {
+            writers.insert(id, stream.try_clone().unwrap());
+            let mut trunk = trunk.try_clone().unwrap();
+            let write_lock = write_lock.clone();
+            thread::spawn(move || {
+                let mut buffer = [0; 1024];
+                loop {
+                    let cnt = match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(cnt) => cnt,
+                    };
+                    let mut hdr = [0; 8];
+                    hdr[0..4].copy_from_slice(&id.to_be_bytes());
+                    hdr[4..8].copy_from_slice(&(cnt as u32).to_be_bytes());
+                    let _unused = write_lock.lock().unwrap();
+                    if trunk.write_all(&hdr).and_then(|_| trunk.write_all(&buffer[..cnt])).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let mut trunk = trunk;
+        thread::spawn(move || loop {
+            let mut hdr = [0; 8];
+            if trunk.read_exact(&mut hdr).is_err() {
+                break;
+            }
+            let cid = u32::from_be_bytes(hdr[0..4].try_into().unwrap());
+            let cnt = u32::from_be_bytes(hdr[4..8].try_into().unwrap());
+            let mut buffer = vec![0; cnt as usize];
+            if trunk.read_exact(&mut buffer).is_err() {
+                break;
+            }
+            if let Some(stream) = writers.get_mut(&cid) {
+                if stream.write_all(&buffer).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+fn thread_count() -> usize {
+    fs::read_to_string("/proc/self/status").unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .map_or(0, |cnt| cnt.trim().parse().unwrap())
+}
+
+// Set up the muxes of the plugins, returning the plugin side of the trunks
+// and the runtime side of the connections.
+type Setup = fn(usize) -> (Vec<UnixStream>, Vec<UnixStream>, Vec<Arc<Mux>>);
+
+fn setup_reactor(plugins: usize) -> (Vec<UnixStream>, Vec<UnixStream>, Vec<Arc<Mux>>) {
+    let (mut trunks, mut conns, mut muxes) = (Vec::new(), Vec::new(), Vec::new());
+    for _ in 0..plugins {
+        let (local, peer) = UnixStream::pair().unwrap();
+        let mux = Mux::new(local).unwrap();
+        for id in 1..=CONNS_PER_PLUGIN {
+            let (inner, outer) = UnixStream::pair().unwrap();
+            mux.add_conn(id, inner).unwrap();
+            conns.push(outer);
+        }
+        mux.start();
+        trunks.push(peer);
+        muxes.push(mux);
+    }
+    (trunks, conns, muxes)
+}
+
+fn setup_legacy(plugins: usize) -> (Vec<UnixStream>, Vec<UnixStream>, Vec<Arc<Mux>>) {
+    let (mut trunks, mut conns) = (Vec::new(), Vec::new());
+    for _ in 0..plugins {
+        let (local, peer) = UnixStream::pair().unwrap();
+        let mut inners = Vec::new();
+        for id in 1..=CONNS_PER_PLUGIN {
+            let (inner, outer) = UnixStream::pair().unwrap();
+            inners.push((id, inner));
+            conns.push(outer);
+        }
+        legacy::start(local, inners);
+        trunks.push(peer);
+    }
+    (trunks, conns, Vec::new())
+}
+
+// shared is the number of threads serving all the muxes.
+fn run(name: &str, setup: Setup, shared: usize, plugins: usize) {
+    let before = thread_count();
+    let (trunks, conns, _muxes) = setup(plugins);
+    // Let the threads of the legacy mux start.
+    thread::sleep(Duration::from_millis(50));
+    let mux_threads = thread_count() - before + shared;
+
+    // The plugin side echoes the frames back as is.
+    for mut trunk in trunks {
+        thread::spawn(move || {
+            let mut back = trunk.try_clone().unwrap();
+            let _unused = std::io::copy(&mut trunk, &mut back);
+        });
+    }
+
+    let barrier = Arc::new(Barrier::new(conns.len() + 1));
+    let mut readers = Vec::new();
+    for conn in conns {
+        let mut writer = conn.try_clone().unwrap();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            let chunk = vec![0x5a; CHUNK_SIZE];
+            barrier.wait();
+            for _ in 0..BYTES_PER_CONN / CHUNK_SIZE {
+                writer.write_all(&chunk).unwrap();
+            }
+        });
+        let mut reader = conn;
+        readers.push(thread::spawn(move || {
+            let mut buffer = vec![0; CHUNK_SIZE];
+            let mut total = 0;
+            while total < BYTES_PER_CONN {
+                total += reader.read(&mut buffer).unwrap();
+            }
+        }));
+    }
+
+    let start = Instant::now();
+    barrier.wait();
+    let cnt = readers.len();
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    let elapsed = start.elapsed();
+    let mib = (cnt * BYTES_PER_CONN) as f64 / (1024.0 * 1024.0);
+    println!("{:<8} plugins {:>3}: {:>8.1} MiB/s, {:>3} mux threads",
+        name, plugins, mib / elapsed.as_secs_f64(), mux_threads);
+}
+
+fn main() {
+    // Start the reactor before counting threads.
+    let before = thread_count();
+    drop(Mux::new(UnixStream::pair().unwrap().0));
+    let reactor_threads = thread_count() - before;
+    for plugins in [1, 8, 32] {
+        run("legacy", setup_legacy, 0, plugins);
+        run("reactor", setup_reactor, reactor_threads, plugins);
+    }
+}
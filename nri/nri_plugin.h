@@ -13,6 +13,8 @@
 #ifndef LIB_NRI_PLUGIN_H
 #define LIB_NRI_PLUGIN_H
 
+#include <sys/types.h>
+
 #include <isula_libutils/nri_create_container_request.h>
 #include <isula_libutils/nri_create_container_response.h>
 #include <isula_libutils/nri_configure_request.h>
@@ -27,11 +29,45 @@
 #include <isula_libutils/nri_update_container_response.h>
 #include <isula_libutils/nri_update_containers_request.h>
 #include <isula_libutils/nri_update_containers_response.h>
+#include <isula_libutils/nri_update_pod_sandbox_request.h>
+#include <isula_libutils/nri_update_pod_sandbox_response.h>
+#include <isula_libutils/nri_validate_container_adjustment_request.h>
+#include <isula_libutils/nri_validate_container_adjustment_response.h>
 
 #ifdef __cplusplus
 extern "C" {
 #endif
 
+/*
+ * The nri_* structs are generated by isula_libutils and passed to and from
+ * this library as is, so both must agree on their layout byte for byte.
+ * This library needs the NRI schemas of isula_libutils at the version
+ * below, which follow the containerd/nri API with UpdatePodSandbox and
+ * ValidateContainerAdjustment. The fields checked below are missing from
+ * older schemas, which then fail to compile instead of corrupting memory.
+ */
+#define NRI_LIBUTILS_SCHEMA_VERSION 2
+
+#define NRI_LIBUTILS_REQUIRE_FIELD(type, field) \
+  typedef char nri_libutils_requires_##type##_##field[sizeof(((type *)0)->field) > 0 ? 1 : -1]
+
+NRI_LIBUTILS_REQUIRE_FIELD(nri_linux_resources, pids);
+NRI_LIBUTILS_REQUIRE_FIELD(nri_linux_pod_sandbox, ips);
+NRI_LIBUTILS_REQUIRE_FIELD(nri_linux_container, seccomp_policy);
+NRI_LIBUTILS_REQUIRE_FIELD(nri_container, cdi_devices);
+NRI_LIBUTILS_REQUIRE_FIELD(nri_linux_container_adjustment, namespaces);
+NRI_LIBUTILS_REQUIRE_FIELD(nri_container_adjustment, args);
+NRI_LIBUTILS_REQUIRE_FIELD(nri_configure_request, request_timeout);
+NRI_LIBUTILS_REQUIRE_FIELD(nri_synchronize_request, more);
+NRI_LIBUTILS_REQUIRE_FIELD(nri_synchronize_response, more);
+
+/**
+ * @brief Get the NRI_LIBUTILS_SCHEMA_VERSION this library was built for. The
+ *        runtime should refuse to enable NRI if it differs from the one it was
+ *        compiled with.
+ */
+uint32_t nri_libutils_schema_version(void);
+
 typedef int (*nri_runtime_register_plugin_callback)(
   const char *plugin_id,
   const nri_register_plugin_request *request
@@ -43,26 +79,122 @@ typedef int (*nri_runtime_update_containers_callback)(
   nri_update_containers_response **response
 );
 
+/**
+ * @brief Called when a plugin connection goes away without the runtime asking
+ *        for it: the plugin exited or crashed, or did not register in time.
+ *        The plugin has already been dropped, later calls for it fail.
+ */
+typedef void (*nri_runtime_plugin_closed_callback)(
+  const char *plugin_id,
+  const char *reason
+);
+
+/**
+ * @brief Called with the response update_containers returned, once it has
+ *        been read. The response is allocated by the runtime, typically with
+ *        the isula_libutils allocators, and is freed by it here, e.g. with
+ *        free_nri_update_containers_response. Without it the response is not
+ *        freed.
+ */
+typedef void (*nri_runtime_free_update_containers_response_callback)(
+  nri_update_containers_response *response
+);
+
 typedef struct nri_runtime_callbasks {
   nri_runtime_register_plugin_callback register_plugin;
   nri_runtime_update_containers_callback update_containers;
+  nri_runtime_plugin_closed_callback plugin_closed;
+  nri_runtime_free_update_containers_response_callback free_update_containers_response;
 } nri_runtime_callbacks;
 
 int nri_runtime_service_init(nri_runtime_callbacks callbacks);
 
 void nri_runtime_service_destroy();
 
+/**
+ * @brief Shut the runtime service down in order: stop accepting external
+ *        plugins, send Shutdown to every plugin in parallel, wait for the
+ *        calls in flight, then close all the plugin connections, all within
+ *        timeout_ms. nri_runtime_service_destroy does the same with a 2s
+ *        timeout. Returns the number of plugins which did not acknowledge
+ *        Shutdown in time; if any, their ids are returned comma separated in
+ *        unacked, to be freed with nri_free_string.
+ */
+int nri_runtime_service_shutdown(uint64_t timeout_ms, char **unacked);
+
+/* called with the peer pid and uid of an external plugin connection */
 typedef int (*nri_external_connect_callback)(
-  int fd
+  int fd,
+  pid_t pid,
+  uid_t uid
 );
 
 int nri_external_service_start(const char *socket_addr,
                                nri_external_connect_callback callback);
 
+typedef struct {
+  const uid_t *uids;
+  size_t uids_len;
+  const gid_t *gids;
+  size_t gids_len;
+  const char **exe_paths;
+  size_t exe_paths_len;
+} nri_external_allow_list;
+
+/**
+ * @brief Restrict the external plugins allowed to connect, by their socket
+ *        peer credentials: a peer is accepted if its uid or primary gid is
+ *        listed, any peer if neither uids nor gids are, and only if it runs
+ *        one of exe_paths, read from /proc/<pid>/exe, if set. Rejected peers
+ *        are closed without calling the connect callback. The socket file
+ *        mode still applies.
+ */
+int nri_external_service_allow_list_set(const nri_external_allow_list *allow_list);
+
 void nri_external_service_shutdown();
 
+/**
+ * @brief Connect a plugin, with timeout, in ns, applying to every call made
+ *        to it.
+ */
 int nri_plugin_connect(const char *plugin_id, int fd, int64_t timeout);
 
+/* Timeouts of the calls to a plugin, per RPC, in ns. 0 waits forever. */
+typedef struct {
+  int64_t configure;
+  int64_t synchronize;
+  int64_t shutdown;
+  int64_t create_container;
+  int64_t update_container;
+  int64_t stop_container;
+  int64_t update_pod_sandbox;
+  int64_t state_change;
+  int64_t validate_container_adjustment;
+} nri_rpc_timeouts;
+
+/**
+ * @brief Get the default timeouts: 30s for synchronize, 2s for the others.
+ */
+void nri_rpc_timeouts_default(nri_rpc_timeouts *timeouts);
+
+/**
+ * @brief Connect a plugin with timeouts per RPC, the defaults if timeouts
+ *        is NULL.
+ */
+int nri_plugin_connect_with_timeouts(const char *plugin_id, int fd, const nri_rpc_timeouts *timeouts);
+
+/**
+ * @brief Change the timeouts of the calls made to a connected plugin from
+ *        now on.
+ */
+int nri_plugin_rpc_timeouts_set(const char *plugin_id, const nri_rpc_timeouts *timeouts);
+
+/*
+ * The calls to a plugin below return NRI_ERR_TIMEOUT when the plugin does
+ * not answer in time, -1 on other errors.
+ */
+#define NRI_ERR_TIMEOUT (-2)
+
 int nri_plugin_disconnect(const char *plugin_id);
 
 int nri_plugin_configure(const char *plugin_id,
@@ -90,6 +222,233 @@ int nri_plugin_stop_container(const char *plugin_id,
 int nri_plugin_state_change(const char *plugin_id,
                             const nri_state_change_event *event);
 
+int nri_plugin_update_pod_sandbox(const char *plugin_id,
+                                  const nri_update_pod_sandbox_request *request,
+                                  nri_update_pod_sandbox_response **response);
+
+int nri_plugin_post_update_pod_sandbox(const char *plugin_id,
+                                       const nri_pod_sandbox *pod);
+
+int nri_plugin_validate_container_adjustment(const char *plugin_id,
+                                             const nri_validate_container_adjustment_request *request,
+                                             nri_validate_container_adjustment_response **response);
+
+/**
+ * @brief Get the events a plugin subscribed for in its configure response,
+ *        one bit per nri event as upstream NRI defines them (1 << (event - 1)).
+ *        events is 0 until the plugin has been configured. Plugins are only
+ *        called for the events they subscribed for once configured.
+ */
+int nri_plugin_subscribed_events(const char *plugin_id, int32_t *events);
+
+/**
+ * @brief Check whether a plugin subscribed for an event.
+ * @return 1 if subscribed, 0 if not, -1 on error.
+ */
+int nri_plugin_is_subscribed(const char *plugin_id, int32_t event);
+
+/**
+ * @brief Dispatch a request to all registered plugins in ascending index order
+ *        and merge their replies like the upstream NRI adaptation does. Each
+ *        plugin sees the container as adjusted by the plugins before it.
+ */
+int nri_dispatch_create_container(const nri_create_container_request *request,
+                                  nri_create_container_response **response);
+
+int nri_dispatch_update_container(const nri_update_container_request *request,
+                                  nri_update_container_response **response);
+
+int nri_dispatch_stop_container(const nri_stop_container_request *request,
+                                nri_stop_container_response **response);
+
+typedef enum {
+  NRI_PLUGIN_RESULT_OK = 0,
+  NRI_PLUGIN_RESULT_FAILED = 1,
+  NRI_PLUGIN_RESULT_TIMED_OUT = 2,
+} nri_plugin_result_t;
+
+typedef struct {
+  const char *plugin_id;
+  const char *plugin_name;
+  const char *plugin_idx;
+  int result;
+  /* set if result is NRI_PLUGIN_RESULT_FAILED */
+  const char *error;
+  void *residual;
+} nri_plugin_result;
+
+typedef struct {
+  nri_plugin_result **results;
+  size_t results_len;
+  void *residual;
+} nri_broadcast_response;
+
+/**
+ * @brief Send a state change event, like RUN_POD_SANDBOX or REMOVE_CONTAINER,
+ *        to all the registered plugins subscribed for it at once, and wait
+ *        until they all answer or timeout_ms expires, 0 waiting for each
+ *        plugin's own timeout. Each plugin gets its events in order, and
+ *        events to a plugin with 16 events queued already fail right away.
+ *        The response holds the result of each plugin, in index order.
+ *        Returns the number of plugins which failed or timed out, -1 on error.
+ */
+int nri_broadcast_state_change(const nri_state_change_event *event, uint64_t timeout_ms,
+                               nri_broadcast_response **response);
+
+/**
+ * @brief Get the plugins owning each field adjusted by nri_dispatch_create_container
+ *        for a container, one "<container> <field>[<key>]: <plugin>" per line.
+ *        Two plugins setting the same field make the dispatch fail with an error
+ *        naming both. Ownership only spans one dispatch: updates and stops are
+ *        not checked against the owners recorded at creation. The record is
+ *        dropped once a REMOVE_CONTAINER event for the container is sent. The
+ *        returned string is freed with nri_free_string.
+ */
+int nri_container_owners(const char *container_id, char **owners);
+
+/**
+ * @brief Drop the owners recorded for a container, for a container whose
+ *        creation failed after nri_dispatch_create_container succeeded.
+ */
+void nri_container_owners_remove(const char *container_id);
+
+typedef enum {
+  NRI_PLUGIN_CONNECTED = 0,
+  NRI_PLUGIN_REGISTERED = 1,
+  NRI_PLUGIN_CONFIGURED = 2,
+  NRI_PLUGIN_SYNCHRONIZED = 3,
+  NRI_PLUGIN_CLOSED = 4,
+} nri_plugin_state_t;
+
+/**
+ * @brief Get the lifecycle state of a plugin, or -1 if it is unknown. A plugin
+ *        must register, then be configured and synchronized, before it gets
+ *        any event; calls made in another state fail.
+ */
+int nri_plugin_state(const char *plugin_id);
+
+typedef struct {
+  const char *plugin_id;
+  /* empty until the plugin registers */
+  const char *plugin_name;
+  const char *plugin_idx;
+  /* nri_plugin_state_t */
+  int state;
+  /* subscribed events, 0 until configured */
+  int32_t events;
+  nri_rpc_timeouts timeouts;
+  uint8_t mux_closed;
+  /* nanoseconds since the epoch */
+  int64_t connected_at;
+  void *residual;
+} nri_plugin_status;
+
+typedef struct {
+  nri_plugin_status **plugins;
+  size_t plugins_len;
+  void *residual;
+} nri_plugin_status_list;
+
+/**
+ * @brief List the connected plugins, sorted by id. Returns the number of
+ *        plugins, -1 on error.
+ */
+int nri_plugin_list(nri_plugin_status_list **list);
+
+/**
+ * @brief Set how long a connected plugin may take to register before it is
+ *        disconnected, 5000ms by default. 0 waits forever. Applies to plugins
+ *        connected afterwards.
+ */
+void nri_plugin_registration_timeout_set(uint64_t timeout_ms);
+
+/**
+ * @brief Start the pre-installed plugins of plugin_dir, the executables named
+ *        NN-name, and connect each one as plugin NN-name. A plugin gets fd 3
+ *        and NRI_PLUGIN_NAME, NRI_PLUGIN_IDX and NRI_PLUGIN_SOCKET like with
+ *        upstream NRI. Returns the number of plugins started, -1 on error.
+ */
+int nri_plugins_launch(const char *plugin_dir, int64_t timeout);
+
+/**
+ * @brief Disconnect and terminate the launched plugins, killing the ones
+ *        still running after grace_ms, and reap them. Also done by
+ *        nri_runtime_service_destroy.
+ */
+void nri_plugins_launched_stop(uint64_t grace_ms);
+
+/**
+ * @brief Set the drop-in config directory of the plugins. A plugin configured
+ *        with an empty config gets <idx>-<name>.conf, else <name>.conf, from
+ *        it. An empty directory disables the lookup.
+ */
+int nri_plugin_config_dir_set(const char *config_dir);
+
+/**
+ * @brief Reread the plugin configurations from the config directory, for the
+ *        plugins configured afterwards.
+ */
+int nri_plugin_config_reload(void);
+
+/**
+ * @brief Set the max size of the data of a mux frame, 8MiB by default. A
+ *        plugin sending a larger frame, or a malformed header, is
+ *        disconnected.
+ */
+int nri_mux_max_frame_size_set(uint32_t size);
+
+typedef struct {
+  uint64_t frames_received;
+  uint64_t frames_sent;
+  /* frames for unknown conns, and the frame with a bad header */
+  uint64_t dropped_frames;
+  uint64_t unknown_conn_frames;
+  /* bytes on the trunk, frame headers included */
+  uint64_t bytes_received;
+  uint64_t bytes_sent;
+} nri_mux_stats;
+
+/**
+ * @brief Get the frame and byte counters of the mux of a plugin.
+ */
+int nri_plugin_mux_stats(const char *plugin_id, nri_mux_stats *stats);
+
+#define NRI_STATS_FORMAT_TEXT 0
+#define NRI_STATS_FORMAT_JSON 1
+
+/**
+ * @brief Dump the stats of all the connected plugins, in text or json: the
+ *        mux counters, and the calls, errors, timeouts and latency histogram
+ *        of each RPC. out is freed with nri_free_string.
+ */
+int nri_dump_stats(int format, char **out);
+
+/*
+ * The responses, lists and strings returned above are allocated by the
+ * library, with everything they point to, and must be freed with the
+ * matching function below rather than free(). NULL is ignored.
+ */
+void nri_free_configure_response(nri_configure_response *response);
+
+void nri_free_synchronize_response(nri_synchronize_response *response);
+
+void nri_free_create_container_response(nri_create_container_response *response);
+
+void nri_free_update_container_response(nri_update_container_response *response);
+
+void nri_free_stop_container_response(nri_stop_container_response *response);
+
+void nri_free_update_pod_sandbox_response(nri_update_pod_sandbox_response *response);
+
+void nri_free_validate_container_adjustment_response(nri_validate_container_adjustment_response *response);
+
+void nri_free_broadcast_response(nri_broadcast_response *response);
+
+void nri_free_plugin_status_list(nri_plugin_status_list *list);
+
+/* frees the strings returned by nri_runtime_service_shutdown, nri_container_owners and nri_dump_stats */
+void nri_free_string(char *s);
+
 #ifdef __cplusplus
 }
 #endif
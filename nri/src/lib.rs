@@ -14,9 +14,25 @@
 pub mod protocols;
 pub mod nri;
 
-use nri::{c_transfer, plugin};
+use nri::{adaptation, auth, c_transfer, config, launcher, mux, plugin, stats};
 use std::os::raw::{c_char, c_int};
-use isula_common::isula_data_types::to_string;
+use isula_common::isula_data_types::{to_c_char_ptr, to_string};
+use protobuf::Enum;
+
+// Returned by the calls to plugins that time out, other errors return -1.
+pub const NRI_ERR_TIMEOUT: c_int = -2;
+
+fn error_code(e: &nri::error::Error) -> c_int {
+    match e {
+        nri::error::Error::Timeout(_) => NRI_ERR_TIMEOUT,
+        _ => -1,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nri_libutils_schema_version() -> u32 {
+    c_transfer::NRI_LIBUTILS_SCHEMA_VERSION
+}
 
 #[no_mangle]
 pub extern "C" fn nri_runtime_service_init(callbacks: c_transfer::NriRuntimeCallbacks) -> c_int {
@@ -34,11 +50,39 @@ pub extern "C" fn nri_runtime_service_init(callbacks: c_transfer::NriRuntimeCall
 pub extern "C" fn nri_runtime_service_destroy() {
     println!("isula-rust-extensions::nri_runtime_service_destroy");
 
-    plugin::runtime_service_destroy();
+    let unacked = plugin::runtime_service_destroy(plugin::DEFAULT_SHUTDOWN_TIMEOUT);
+    if !unacked.is_empty() {
+        println!("isula-rust-extensions::nri_runtime_service_destroy plugins not acknowledging shutdown: {}",
+            unacked.join(","));
+    }
+    launcher::stop_launched_plugins(std::time::Duration::from_secs(1));
 
     println!("isula-rust-extensions::nri_runtime_service_destroy success");
 }
 
+/// # Safety
+///
+/// unacked must be NULL or valid for writing a string pointer.
+#[no_mangle]
+pub unsafe extern "C" fn nri_runtime_service_shutdown(timeout_ms: u64, unacked: *mut *const c_char) -> c_int {
+    println!("isula-rust-extensions::nri_runtime_service_shutdown");
+
+    let r_unacked = plugin::runtime_service_destroy(std::time::Duration::from_millis(timeout_ms));
+    launcher::stop_launched_plugins(std::time::Duration::from_secs(1));
+    if !r_unacked.is_empty() {
+        println!("isula-rust-extensions::nri_runtime_service_shutdown plugins not acknowledging shutdown: {}",
+            r_unacked.join(","));
+        if !unacked.is_null() {
+            unsafe {
+                *unacked = to_c_char_ptr(r_unacked.join(",").as_str());
+            }
+        }
+    }
+
+    println!("isula-rust-extensions::nri_runtime_service_shutdown success");
+    r_unacked.len() as c_int
+}
+
 #[no_mangle]
 pub extern "C" fn nri_plugin_connect(plugin_id: *const c_char, local_fd: c_int, timeout: i64) -> c_int {
     if plugin_id.is_null() {
@@ -46,7 +90,7 @@ pub extern "C" fn nri_plugin_connect(plugin_id: *const c_char, local_fd: c_int,
     }
     let r_plugin_id = to_string(plugin_id);
     println!("isula-rust-extensions::nri_plugin_connect with::{}", r_plugin_id);
-    if let Err(e) = plugin::connect(&r_plugin_id, local_fd, timeout) {
+    if let Err(e) = plugin::connect(&r_plugin_id, local_fd, plugin::RpcTimeouts::uniform(timeout)) {
         println!("isula-rust-extensions::nri_plugin_connect failed: {}", e);
         return -1;
     }
@@ -55,6 +99,62 @@ pub extern "C" fn nri_plugin_connect(plugin_id: *const c_char, local_fd: c_int,
     0
 }
 
+/// # Safety
+///
+/// timeouts must be NULL or valid for writing an nri_rpc_timeouts.
+#[no_mangle]
+pub unsafe extern "C" fn nri_rpc_timeouts_default(timeouts: *mut plugin::RpcTimeouts) {
+    if timeouts.is_null() {
+        return;
+    }
+    unsafe {
+        *timeouts = plugin::DEFAULT_RPC_TIMEOUTS;
+    }
+}
+
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, and timeouts NULL or a valid
+/// nri_rpc_timeouts. local_fd is owned by the plugin connection from here on.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_connect_with_timeouts(plugin_id: *const c_char, local_fd: c_int,
+    timeouts: *const plugin::RpcTimeouts
+) -> c_int {
+    if plugin_id.is_null() {
+        return -1;
+    }
+    let r_plugin_id = to_string(plugin_id);
+    let r_timeouts = unsafe { timeouts.as_ref() }.copied().unwrap_or_default();
+    println!("isula-rust-extensions::nri_plugin_connect_with_timeouts with::{} {:?}", r_plugin_id, r_timeouts);
+    if let Err(e) = plugin::connect(&r_plugin_id, local_fd, r_timeouts) {
+        println!("isula-rust-extensions::nri_plugin_connect_with_timeouts failed: {}", e);
+        return -1;
+    }
+
+    println!("isula-rust-extensions::nri_plugin_connect_with_timeouts success");
+    0
+}
+
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, and timeouts NULL or a valid
+/// nri_rpc_timeouts.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_rpc_timeouts_set(plugin_id: *const c_char, timeouts: *const plugin::RpcTimeouts) -> c_int {
+    if plugin_id.is_null() || timeouts.is_null() {
+        return -1;
+    }
+    let r_plugin_id = to_string(plugin_id);
+    let r_timeouts = *unsafe { timeouts.as_ref() }.unwrap();
+    println!("isula-rust-extensions::nri_plugin_rpc_timeouts_set with::{} {:?}", r_plugin_id, r_timeouts);
+
+    if let Err(e) = plugin::set_rpc_timeouts(&r_plugin_id, r_timeouts) {
+        println!("isula-rust-extensions::nri_plugin_rpc_timeouts_set failed: {}", e);
+        return -1;
+    }
+    0
+}
+
 #[no_mangle]
 pub extern "C" fn nri_plugin_disconnect(plugin_id: *const c_char) -> c_int {
     if plugin_id.is_null() {
@@ -89,6 +189,22 @@ pub extern "C" fn nri_external_service_start(socket_addr: *const c_char,
     0
 }
 
+/// # Safety
+///
+/// allow_list must be NULL or a valid nri_external_allow_list, whose arrays
+/// hold at least as many items as their lengths say.
+#[no_mangle]
+pub unsafe extern "C" fn nri_external_service_allow_list_set(allow_list: *const c_transfer::NriExternalAllowList) -> c_int {
+    if allow_list.is_null() {
+        return -1;
+    }
+    let c_allow_list = unsafe { allow_list.as_ref() }.unwrap();
+    let r_allow_list = auth::AllowList::from(c_allow_list);
+    println!("isula-rust-extensions::nri_external_service_allow_list_set with::{:?}", r_allow_list);
+    auth::set_allow_list(r_allow_list);
+    0
+}
+
 #[no_mangle]
 pub extern "C" fn nri_external_service_shutdown() {
     println!("isula-rust-extensions::nri_external_service_shutdown");
@@ -98,8 +214,12 @@ pub extern "C" fn nri_external_service_shutdown() {
     println!("isula-rust-extensions::nri_external_service_shutdown success");
 }
 
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, req NULL or a valid request, and
+/// resp NULL or valid for writing the response pointer.
 #[no_mangle]
-pub extern "C" fn nri_plugin_configure(plugin_id: *const c_char,
+pub unsafe extern "C" fn nri_plugin_configure(plugin_id: *const c_char,
     req: *const c_transfer::NriConfigureRequest,
     resp: *mut *const c_transfer::NriConfigureResponse
 ) -> c_int {
@@ -120,14 +240,18 @@ pub extern "C" fn nri_plugin_configure(plugin_id: *const c_char,
         },
         Err(e) => {
             println!("isula-rust-extensions::nri_plugin_configure failed: {}", e);
-            return -1;
+            return error_code(&e);
         }
     }
     0
 }
 
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, req NULL or a valid request, and
+/// resp NULL or valid for writing the response pointer.
 #[no_mangle]
-pub extern "C" fn nri_plugin_synchronize(plugin_id: *const c_char,
+pub unsafe extern "C" fn nri_plugin_synchronize(plugin_id: *const c_char,
     req: *const c_transfer::NriSynchronizeRequest,
     resp: *mut *const c_transfer::NriSynchronizeResponse
 ) -> c_int {
@@ -148,14 +272,17 @@ pub extern "C" fn nri_plugin_synchronize(plugin_id: *const c_char,
         },
         Err(e) => {
             println!("isula-rust-extensions::nri_plugin_synchronize failed: {}", e);
-            return -1;
+            return error_code(&e);
         }
     }
     0
 }
 
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string.
 #[no_mangle]
-pub extern "C" fn nri_plugin_shutdown(plugin_id: *const c_char
+pub unsafe extern "C" fn nri_plugin_shutdown(plugin_id: *const c_char
 ) -> c_int {
     if plugin_id.is_null() {
         return -1;
@@ -167,14 +294,18 @@ pub extern "C" fn nri_plugin_shutdown(plugin_id: *const c_char
         Ok(_) => {},
         Err(e) => {
             println!("isula-rust-extensions::nri_plugin_shutdown failed: {}", e);
-            return -1;
+            return error_code(&e);
         }
     }
     0
 }
 
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, req NULL or a valid request, and
+/// resp NULL or valid for writing the response pointer.
 #[no_mangle]
-pub extern  "C" fn nri_plugin_create_container(plugin_id: *const c_char,
+pub unsafe extern "C" fn nri_plugin_create_container(plugin_id: *const c_char,
     req: *const c_transfer::NriCreateContainerRequest,
     resp: *mut *const c_transfer::NriCreateContainerResponse
 ) -> c_int {
@@ -195,14 +326,18 @@ pub extern  "C" fn nri_plugin_create_container(plugin_id: *const c_char,
         },
         Err(e) => {
             println!("isula-rust-extensions::nri_plugin_create_container failed: {}", e);
-            return -1;
+            return error_code(&e);
         }
     }
     0
 }
 
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, req NULL or a valid request, and
+/// resp NULL or valid for writing the response pointer.
 #[no_mangle]
-pub extern "C" fn nri_plugin_update_container(plugin_id: *const c_char,
+pub unsafe extern "C" fn nri_plugin_update_container(plugin_id: *const c_char,
     req: *const c_transfer::NriUpdateContainerRequest,
     resp: *mut *const c_transfer::NriUpdateContainerResponse
 ) -> c_int {
@@ -223,14 +358,18 @@ pub extern "C" fn nri_plugin_update_container(plugin_id: *const c_char,
         },
         Err(e) => {
             println!("isula-rust-extensions::nri_plugin_update_container failed: {}", e);
-            return -1;
+            return error_code(&e);
         }
     }
     0
 }
 
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, req NULL or a valid request, and
+/// resp NULL or valid for writing the response pointer.
 #[no_mangle]
-pub extern "C" fn nri_plugin_stop_container(plugin_id: *const c_char,
+pub unsafe extern "C" fn nri_plugin_stop_container(plugin_id: *const c_char,
     req: *const c_transfer::NriStopContainerRequest,
     resp: *mut *const c_transfer::NriStopContainerResponse
 ) -> c_int {
@@ -251,14 +390,17 @@ pub extern "C" fn nri_plugin_stop_container(plugin_id: *const c_char,
         },
         Err(e) => {
             println!("isula-rust-extensions::nri_plugin_stop_container failed: {}", e);
-            return -1;
+            return error_code(&e);
         }
     }
     0
 }
 
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, and req NULL or a valid event.
 #[no_mangle]
-pub extern "C" fn nri_plugin_state_change(plugin_id: *const c_char,
+pub unsafe extern "C" fn nri_plugin_state_change(plugin_id: *const c_char,
     req: *const c_transfer::NriStateChangeEvent
 ) -> c_int {
     if plugin_id.is_null() || req.is_null() {
@@ -273,8 +415,564 @@ pub extern "C" fn nri_plugin_state_change(plugin_id: *const c_char,
         Ok(_) => {},
         Err(e) => {
             println!("isula-rust-extensions::nri_plugin_state_change failed: {}", e);
+            return error_code(&e);
+        }
+    }
+    0
+}
+
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, req NULL or a valid request, and
+/// resp NULL or valid for writing the response pointer.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_update_pod_sandbox(plugin_id: *const c_char,
+    req: *const c_transfer::NriUpdatePodSandboxRequest,
+    resp: *mut *const c_transfer::NriUpdatePodSandboxResponse
+) -> c_int {
+    if plugin_id.is_null() || req.is_null() || resp.is_null() {
+        return -1;
+    }
+    let r_plugin_id = to_string(plugin_id);
+    let c_req = unsafe { req.as_ref() }.unwrap();
+    let r_req: protocols::nri::UpdatePodSandboxRequest = protocols::nri::UpdatePodSandboxRequest::from(c_req);
+    println!("isula-rust-extensions::nri_plugin_update_pod_sandbox with::{}", r_plugin_id);
+
+    match plugin::update_pod_sandbox(&r_plugin_id, &r_req) {
+        Ok(r_resp) => {
+            let c_resp = c_transfer::NriUpdatePodSandboxResponse::from(&r_resp);
+            unsafe {
+                *resp = Box::into_raw(Box::new(c_resp));
+            }
+        },
+        Err(e) => {
+            println!("isula-rust-extensions::nri_plugin_update_pod_sandbox failed: {}", e);
+            return error_code(&e);
+        }
+    }
+    0
+}
+
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, and pod NULL or a valid pod.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_post_update_pod_sandbox(plugin_id: *const c_char,
+    pod: *const c_transfer::NriPodSandbox
+) -> c_int {
+    if plugin_id.is_null() || pod.is_null() {
+        return -1;
+    }
+    let r_plugin_id = to_string(plugin_id);
+    let c_pod = unsafe { pod.as_ref() }.unwrap();
+    let r_pod: protocols::nri::PodSandbox = protocols::nri::PodSandbox::from(c_pod);
+    println!("isula-rust-extensions::nri_plugin_post_update_pod_sandbox with::{}", r_plugin_id);
+
+    match plugin::post_update_pod_sandbox(&r_plugin_id, r_pod) {
+        Ok(_) => {},
+        Err(e) => {
+            println!("isula-rust-extensions::nri_plugin_post_update_pod_sandbox failed: {}", e);
+            return error_code(&e);
+        }
+    }
+    0
+}
+
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, req NULL or a valid request, and
+/// resp NULL or valid for writing the response pointer.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_validate_container_adjustment(plugin_id: *const c_char,
+    req: *const c_transfer::NriValidateContainerAdjustmentRequest,
+    resp: *mut *const c_transfer::NriValidateContainerAdjustmentResponse
+) -> c_int {
+    if plugin_id.is_null() || req.is_null() || resp.is_null() {
+        return -1;
+    }
+    let r_plugin_id = to_string(plugin_id);
+    let c_req = unsafe { req.as_ref() }.unwrap();
+    let r_req: protocols::nri::ValidateContainerAdjustmentRequest = protocols::nri::ValidateContainerAdjustmentRequest::from(c_req);
+    println!("isula-rust-extensions::nri_plugin_validate_container_adjustment with::{}", r_plugin_id);
+
+    match plugin::validate_container_adjustment(&r_plugin_id, &r_req) {
+        Ok(r_resp) => {
+            let c_resp = c_transfer::NriValidateContainerAdjustmentResponse::from(&r_resp);
+            unsafe {
+                *resp = Box::into_raw(Box::new(c_resp));
+            }
+        },
+        Err(e) => {
+            println!("isula-rust-extensions::nri_plugin_validate_container_adjustment failed: {}", e);
+            return error_code(&e);
+        }
+    }
+    0
+}
+
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, and events NULL or valid for
+/// writing an int32_t.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_subscribed_events(plugin_id: *const c_char, events: *mut i32) -> c_int {
+    if plugin_id.is_null() || events.is_null() {
+        return -1;
+    }
+    let r_plugin_id = to_string(plugin_id);
+
+    match plugin::subscribed_events(&r_plugin_id) {
+        Ok(r_events) => {
+            unsafe {
+                *events = r_events;
+            }
+        },
+        Err(e) => {
+            println!("isula-rust-extensions::nri_plugin_subscribed_events failed: {}", e);
+            return -1;
+        }
+    }
+    0
+}
+
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_is_subscribed(plugin_id: *const c_char, event: i32) -> c_int {
+    if plugin_id.is_null() {
+        return -1;
+    }
+    let r_plugin_id = to_string(plugin_id);
+    let r_event = match protocols::nri::Event::from_i32(event) {
+        Some(r_event) => r_event,
+        None => {
+            println!("isula-rust-extensions::nri_plugin_is_subscribed unknown event: {}", event);
+            return -1;
+        }
+    };
+
+    match plugin::is_subscribed(&r_plugin_id, r_event) {
+        Ok(subscribed) => subscribed as c_int,
+        Err(e) => {
+            println!("isula-rust-extensions::nri_plugin_is_subscribed failed: {}", e);
+            -1
+        }
+    }
+}
+
+/// # Safety
+///
+/// req must be NULL or a valid request, and resp NULL or valid for writing the
+/// response pointer.
+#[no_mangle]
+pub unsafe extern "C" fn nri_dispatch_create_container(req: *const c_transfer::NriCreateContainerRequest,
+    resp: *mut *const c_transfer::NriCreateContainerResponse
+) -> c_int {
+    if req.is_null() || resp.is_null() {
+        return -1;
+    }
+    let c_req = unsafe { req.as_ref() }.unwrap();
+    let r_req: protocols::nri::CreateContainerRequest = protocols::nri::CreateContainerRequest::from(c_req);
+    println!("isula-rust-extensions::nri_dispatch_create_container");
+
+    match adaptation::create_container(&r_req) {
+        Ok(r_resp) => {
+            let c_resp = c_transfer::NriCreateContainerResponse::from(&r_resp);
+            unsafe {
+                *resp = Box::into_raw(Box::new(c_resp));
+            }
+        },
+        Err(e) => {
+            println!("isula-rust-extensions::nri_dispatch_create_container failed: {}", e);
+            return -1;
+        }
+    }
+    0
+}
+
+/// # Safety
+///
+/// req must be NULL or a valid request, and resp NULL or valid for writing the
+/// response pointer.
+#[no_mangle]
+pub unsafe extern "C" fn nri_dispatch_update_container(req: *const c_transfer::NriUpdateContainerRequest,
+    resp: *mut *const c_transfer::NriUpdateContainerResponse
+) -> c_int {
+    if req.is_null() || resp.is_null() {
+        return -1;
+    }
+    let c_req = unsafe { req.as_ref() }.unwrap();
+    let r_req: protocols::nri::UpdateContainerRequest = protocols::nri::UpdateContainerRequest::from(c_req);
+    println!("isula-rust-extensions::nri_dispatch_update_container");
+
+    match adaptation::update_container(&r_req) {
+        Ok(r_resp) => {
+            let c_resp = c_transfer::NriUpdateContainerResponse::from(&r_resp);
+            unsafe {
+                *resp = Box::into_raw(Box::new(c_resp));
+            }
+        },
+        Err(e) => {
+            println!("isula-rust-extensions::nri_dispatch_update_container failed: {}", e);
+            return -1;
+        }
+    }
+    0
+}
+
+/// # Safety
+///
+/// req must be NULL or a valid request, and resp NULL or valid for writing the
+/// response pointer.
+#[no_mangle]
+pub unsafe extern "C" fn nri_dispatch_stop_container(req: *const c_transfer::NriStopContainerRequest,
+    resp: *mut *const c_transfer::NriStopContainerResponse
+) -> c_int {
+    if req.is_null() || resp.is_null() {
+        return -1;
+    }
+    let c_req = unsafe { req.as_ref() }.unwrap();
+    let r_req: protocols::nri::StopContainerRequest = protocols::nri::StopContainerRequest::from(c_req);
+    println!("isula-rust-extensions::nri_dispatch_stop_container");
+
+    match adaptation::stop_container(&r_req) {
+        Ok(r_resp) => {
+            let c_resp = c_transfer::NriStopContainerResponse::from(&r_resp);
+            unsafe {
+                *resp = Box::into_raw(Box::new(c_resp));
+            }
+        },
+        Err(e) => {
+            println!("isula-rust-extensions::nri_dispatch_stop_container failed: {}", e);
+            return -1;
+        }
+    }
+    0
+}
+
+/// # Safety
+///
+/// req must be NULL or a valid event, and resp NULL or valid for writing the
+/// response pointer.
+#[no_mangle]
+pub unsafe extern "C" fn nri_broadcast_state_change(req: *const c_transfer::NriStateChangeEvent,
+    timeout_ms: u64,
+    resp: *mut *const c_transfer::NriBroadcastResponse
+) -> c_int {
+    if req.is_null() || resp.is_null() {
+        return -1;
+    }
+    let c_req = unsafe { req.as_ref() }.unwrap();
+    let r_req: protocols::nri::StateChangeEvent = protocols::nri::StateChangeEvent::from(c_req);
+    println!("isula-rust-extensions::nri_broadcast_state_change");
+
+    match adaptation::broadcast_state_change(&r_req, std::time::Duration::from_millis(timeout_ms)) {
+        Ok(r_results) => {
+            let failed = r_results.iter().filter(|r| r.outcome != adaptation::Outcome::Ok).count();
+            let c_resp = c_transfer::NriBroadcastResponse::from(&r_results);
+            unsafe {
+                *resp = Box::into_raw(Box::new(c_resp));
+            }
+            failed as c_int
+        },
+        Err(e) => {
+            println!("isula-rust-extensions::nri_broadcast_state_change failed: {}", e);
+            -1
+        }
+    }
+}
+
+/// # Safety
+///
+/// container_id must be NULL or a valid C string, and owners NULL or valid for
+/// writing a string pointer.
+#[no_mangle]
+pub unsafe extern "C" fn nri_container_owners(container_id: *const c_char, owners: *mut *const c_char) -> c_int {
+    if container_id.is_null() || owners.is_null() {
+        return -1;
+    }
+    let r_container_id = to_string(container_id);
+
+    match adaptation::container_owners(&r_container_id) {
+        Some(r_owners) => {
+            unsafe {
+                *owners = to_c_char_ptr(r_owners.dump().as_str());
+            }
+        },
+        None => {
+            println!("isula-rust-extensions::nri_container_owners no record for {}", r_container_id);
             return -1;
         }
     }
     0
 }
+
+/// # Safety
+///
+/// container_id must be NULL or a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn nri_container_owners_remove(container_id: *const c_char) {
+    if container_id.is_null() {
+        return;
+    }
+    adaptation::forget_container(&to_string(container_id));
+}
+
+/// # Safety
+///
+/// list must be NULL or valid for writing the list pointer.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_list(list: *mut *const c_transfer::NriPluginStatusList) -> c_int {
+    if list.is_null() {
+        return -1;
+    }
+
+    match plugin::list() {
+        Ok(r_list) => {
+            let c_list = c_transfer::NriPluginStatusList::from(&r_list);
+            unsafe {
+                *list = Box::into_raw(Box::new(c_list));
+            }
+            r_list.len() as c_int
+        },
+        Err(e) => {
+            println!("isula-rust-extensions::nri_plugin_list failed: {}", e);
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nri_plugin_registration_timeout_set(timeout_ms: u64) {
+    plugin::set_registration_timeout(std::time::Duration::from_millis(timeout_ms));
+}
+
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_state(plugin_id: *const c_char) -> c_int {
+    if plugin_id.is_null() {
+        return -1;
+    }
+    let r_plugin_id = to_string(plugin_id);
+
+    match plugin::state(&r_plugin_id) {
+        Ok(state) => state as c_int,
+        Err(e) => {
+            println!("isula-rust-extensions::nri_plugin_state failed: {}", e);
+            -1
+        }
+    }
+}
+
+/// # Safety
+///
+/// plugin_dir must be NULL or a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugins_launch(plugin_dir: *const c_char, timeout: i64) -> c_int {
+    if plugin_dir.is_null() {
+        return -1;
+    }
+    let r_plugin_dir = to_string(plugin_dir);
+    println!("isula-rust-extensions::nri_plugins_launch from::{}", r_plugin_dir);
+
+    match launcher::launch_plugins(&r_plugin_dir, timeout) {
+        Ok(launched) => {
+            println!("isula-rust-extensions::nri_plugins_launch launched: {:?}", launched);
+            launched.len() as c_int
+        }
+        Err(e) => {
+            println!("isula-rust-extensions::nri_plugins_launch failed: {}", e);
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn nri_plugins_launched_stop(grace_ms: u64) {
+    println!("isula-rust-extensions::nri_plugins_launched_stop");
+    launcher::stop_launched_plugins(std::time::Duration::from_millis(grace_ms));
+}
+
+/// # Safety
+///
+/// config_dir must be NULL or a valid C string.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_config_dir_set(config_dir: *const c_char) -> c_int {
+    if config_dir.is_null() {
+        return -1;
+    }
+    let r_config_dir = to_string(config_dir);
+    println!("isula-rust-extensions::nri_plugin_config_dir_set with::{}", r_config_dir);
+    config::set_config_dir(&r_config_dir);
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn nri_plugin_config_reload() -> c_int {
+    println!("isula-rust-extensions::nri_plugin_config_reload");
+    if let Err(e) = config::reload() {
+        println!("isula-rust-extensions::nri_plugin_config_reload failed: {}", e);
+        return -1;
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn nri_mux_max_frame_size_set(size: u32) -> c_int {
+    if let Err(e) = mux::set_max_frame_size(size as usize) {
+        println!("isula-rust-extensions::nri_mux_max_frame_size_set failed: {}", e);
+        return -1;
+    }
+    0
+}
+
+/// # Safety
+///
+/// plugin_id must be NULL or a valid C string, and stats NULL or valid for
+/// writing an nri_mux_stats.
+#[no_mangle]
+pub unsafe extern "C" fn nri_plugin_mux_stats(plugin_id: *const c_char, stats: *mut mux::MuxStats) -> c_int {
+    if plugin_id.is_null() || stats.is_null() {
+        return -1;
+    }
+    let r_plugin_id = to_string(plugin_id);
+
+    match plugin::mux_stats(&r_plugin_id) {
+        Ok(r_stats) => {
+            unsafe {
+                *stats = r_stats;
+            }
+        },
+        Err(e) => {
+            println!("isula-rust-extensions::nri_plugin_mux_stats failed: {}", e);
+            return -1;
+        }
+    }
+    0
+}
+
+/// # Safety
+///
+/// out must be NULL or valid for writing a string pointer.
+#[no_mangle]
+pub unsafe extern "C" fn nri_dump_stats(format: c_int, out: *mut *const c_char) -> c_int {
+    if out.is_null() {
+        return -1;
+    }
+
+    let r_out = stats::DumpFormat::try_from(format)
+        .and_then(|format| plugin::stats().and_then(|plugins| stats::dump(&plugins, format)));
+    match r_out {
+        Ok(r_out) => {
+            unsafe {
+                *out = to_c_char_ptr(r_out.as_str());
+            }
+        },
+        Err(e) => {
+            println!("isula-rust-extensions::nri_dump_stats failed: {}", e);
+            return -1;
+        }
+    }
+    0
+}
+
+fn free_boxed<T>(p: *const T) {
+    if !p.is_null() {
+        let _unused = unsafe { Box::from_raw(p as *mut T) };
+    }
+}
+
+/// # Safety
+///
+/// resp must be NULL or a response returned by nri_plugin_configure, not freed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn nri_free_configure_response(resp: *const c_transfer::NriConfigureResponse) {
+    free_boxed(resp);
+}
+
+/// # Safety
+///
+/// resp must be NULL or a response returned by nri_plugin_synchronize, not
+/// freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nri_free_synchronize_response(resp: *const c_transfer::NriSynchronizeResponse) {
+    free_boxed(resp);
+}
+
+/// # Safety
+///
+/// resp must be NULL or a response returned by nri_plugin_create_container or
+/// nri_dispatch_create_container, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nri_free_create_container_response(resp: *const c_transfer::NriCreateContainerResponse) {
+    free_boxed(resp);
+}
+
+/// # Safety
+///
+/// resp must be NULL or a response returned by nri_plugin_update_container or
+/// nri_dispatch_update_container, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nri_free_update_container_response(resp: *const c_transfer::NriUpdateContainerResponse) {
+    free_boxed(resp);
+}
+
+/// # Safety
+///
+/// resp must be NULL or a response returned by nri_plugin_stop_container or
+/// nri_dispatch_stop_container, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nri_free_stop_container_response(resp: *const c_transfer::NriStopContainerResponse) {
+    free_boxed(resp);
+}
+
+/// # Safety
+///
+/// resp must be NULL or a response returned by nri_plugin_update_pod_sandbox,
+/// not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nri_free_update_pod_sandbox_response(resp: *const c_transfer::NriUpdatePodSandboxResponse) {
+    free_boxed(resp);
+}
+
+/// # Safety
+///
+/// resp must be NULL or a response returned by
+/// nri_plugin_validate_container_adjustment, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nri_free_validate_container_adjustment_response(
+    resp: *const c_transfer::NriValidateContainerAdjustmentResponse
+) {
+    free_boxed(resp);
+}
+
+/// # Safety
+///
+/// resp must be NULL or a response returned by nri_broadcast_state_change, not
+/// freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nri_free_broadcast_response(resp: *const c_transfer::NriBroadcastResponse) {
+    free_boxed(resp);
+}
+
+/// # Safety
+///
+/// list must be NULL or a list returned by nri_plugin_list, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nri_free_plugin_status_list(list: *const c_transfer::NriPluginStatusList) {
+    free_boxed(list);
+}
+
+/// # Safety
+///
+/// s must be NULL or a string returned by this library, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn nri_free_string(s: *const c_char) {
+    if !s.is_null() {
+        let _unused = unsafe { std::ffi::CString::from_raw(s as *mut c_char) };
+    }
+}
@@ -0,0 +1,737 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// adaptation dispatches container lifecycle requests to all registered
+// plugins and merges their replies, following the semantics of the upstream
+// NRI adaptation (https://github.com/containerd/nri/tree/main/pkg/adaptation):
+//   - plugins are invoked one after the other in ascending index order, and
+//     each plugin sees the container as adjusted by the plugins before it;
+//   - keyed entries (annotations, mounts, env, devices, namespaces, rlimits,
+//     CDI devices) replace earlier entries with the same key, and a key with
+//     a leading '-' removes the entry;
+//   - hooks are appended, args and scalar fields replace earlier values and
+//     resources are merged field by field;
+//   - updates to the container being created or updated are folded into its
+//     adjustment, updates to other containers are merged per container;
+//   - a field set or removed by one plugin cannot be touched by another one,
+//     such a conflict fails the whole request. Ownership only spans a single
+//     request: the owners recorded when a container is created are kept for
+//     inspection until it is removed, but later updates and stops are not
+//     checked against them.
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::{mpsc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use protobuf::MessageField;
+
+use crate::nri::error::{Error, Result};
+use crate::nri::owners::Owners;
+use crate::nri::plugin::{self, PluginInfo};
+use crate::protocols::nri::{self, Field};
+
+lazy_static! {
+    // Field owners of the last adjustment of each created container.
+    static ref CONTAINER_OWNERS: RwLock<HashMap<String, Owners>> = RwLock::new(HashMap::new());
+    // Queue of the state change events broadcast to each plugin.
+    static ref EVENT_QUEUES: Mutex<HashMap<String, crossbeam_channel::Sender<EventJob>>> = Mutex::new(HashMap::new());
+}
+
+const REMOVAL_PREFIX: &str = "-";
+
+fn marked_for_removal(key: &str) -> Option<&str> {
+    key.strip_prefix(REMOVAL_PREFIX)
+}
+
+fn claim_key(owners: &mut Owners, id: &str, field: Field, key: &str, plugin: &str) -> Result<()> {
+    owners.claim_key(id, field, marked_for_removal(key).unwrap_or(key), plugin)
+}
+
+fn env_key(env: &str) -> &str {
+    env.split_once('=').map_or(env, |(key, _)| key)
+}
+
+fn replace_or_push<T>(entries: &mut Vec<T>, entry: T, key: fn(&T) -> &str) {
+    match entries.iter_mut().find(|e| key(e) == key(&entry)) {
+        Some(existing) => *existing = entry,
+        None => entries.push(entry),
+    }
+}
+
+// merge_keyed merges the keyed entries of one plugin into the adjustment and
+// the container. A removal is kept in the adjustment, so that the runtime
+// removes the entry from the container it creates as well.
+fn merge_keyed<T: Clone>(adjusted: &mut Vec<T>, current: &mut Vec<T>, entries: &[T], key: fn(&T) -> &str) {
+    for entry in entries {
+        match marked_for_removal(key(entry)) {
+            Some(removed) => {
+                adjusted.retain(|e| key(e) != removed);
+                current.retain(|e| key(e) != removed);
+                replace_or_push(adjusted, entry.clone(), key);
+            }
+            None => {
+                replace_or_push(adjusted, entry.clone(), key);
+                replace_or_push(current, entry.clone(), key);
+            }
+        }
+    }
+}
+
+macro_rules! merge_optional {
+    ($dst: expr, $src: expr, $($field: ident),+) => {
+        $(
+            if $src.$field.is_some() {
+                $dst.$field = $src.$field.clone();
+            }
+        )+
+    };
+}
+
+macro_rules! claim_optional {
+    ($owners: expr, $id: expr, $plugin: expr, $src: expr, $($field: ident => $owned: expr),+) => {
+        $(
+            if $src.$field.is_some() {
+                $owners.claim($id, $owned, $plugin)?;
+            }
+        )+
+    };
+}
+
+fn claim_resources(owners: &mut Owners, id: &str, res: &nri::LinuxResources, plugin: &str) -> Result<()> {
+    if let Some(memory) = res.memory.as_ref() {
+        claim_optional!(owners, id, plugin, memory,
+            limit => Field::MemLimit,
+            reservation => Field::MemReservation,
+            swap => Field::MemSwapLimit,
+            kernel => Field::MemKernelLimit,
+            kernel_tcp => Field::MemTCPLimit,
+            swappiness => Field::MemSwappiness,
+            disable_oom_killer => Field::MemDisableOomKiller,
+            use_hierarchy => Field::MemUseHierarchy);
+    }
+    if let Some(cpu) = res.cpu.as_ref() {
+        claim_optional!(owners, id, plugin, cpu,
+            shares => Field::CPUShares,
+            quota => Field::CPUQuota,
+            period => Field::CPUPeriod,
+            realtime_runtime => Field::CPURealtimeRuntime,
+            realtime_period => Field::CPURealtimePeriod);
+        if !cpu.cpus.is_empty() {
+            owners.claim(id, Field::CPUSetCPUs, plugin)?;
+        }
+        if !cpu.mems.is_empty() {
+            owners.claim(id, Field::CPUSetMems, plugin)?;
+        }
+    }
+    for limit in &res.hugepage_limits {
+        owners.claim_key(id, Field::HugepageLimits, &limit.page_size, plugin)?;
+    }
+    claim_optional!(owners, id, plugin, res,
+        blockio_class => Field::BlockioClass,
+        rdt_class => Field::RdtClass,
+        pids => Field::PidsLimit);
+    for key in res.unified.keys() {
+        owners.claim_key(id, Field::CgroupsUnified, key, plugin)?;
+    }
+    Ok(())
+}
+
+pub fn merge_resources(dst: &mut nri::LinuxResources, src: &nri::LinuxResources) {
+    if let Some(memory) = src.memory.as_ref() {
+        let dst_memory = dst.memory.mut_or_insert_default();
+        merge_optional!(dst_memory, memory, limit, reservation, swap, kernel, kernel_tcp, swappiness,
+            disable_oom_killer, use_hierarchy);
+    }
+    if let Some(cpu) = src.cpu.as_ref() {
+        let dst_cpu = dst.cpu.mut_or_insert_default();
+        merge_optional!(dst_cpu, cpu, shares, quota, period, realtime_runtime, realtime_period);
+        if !cpu.cpus.is_empty() {
+            dst_cpu.cpus = cpu.cpus.clone();
+        }
+        if !cpu.mems.is_empty() {
+            dst_cpu.mems = cpu.mems.clone();
+        }
+    }
+    for limit in &src.hugepage_limits {
+        replace_or_push(&mut dst.hugepage_limits, limit.clone(), |l| l.page_size.as_str());
+    }
+    merge_optional!(dst, src, blockio_class, rdt_class, pids);
+    dst.unified.extend(src.unified.iter().map(|(k, v)| (k.clone(), v.clone())));
+    dst.devices.extend(src.devices.iter().cloned());
+}
+
+fn merge_update(updates: &mut Vec<nri::ContainerUpdate>, update: &nri::ContainerUpdate,
+                owners: &mut Owners, plugin: &str) -> Result<()> {
+    if let Some(resources) = update_resources(update) {
+        claim_resources(owners, &update.container_id, resources, plugin)?;
+    }
+    match updates.iter_mut().find(|u| u.container_id == update.container_id) {
+        Some(existing) => {
+            if let Some(resources) = update.linux.as_ref().and_then(|l| l.resources.as_ref()) {
+                merge_resources(existing.linux.mut_or_insert_default().resources.mut_or_insert_default(), resources);
+            }
+            existing.ignore_failure = existing.ignore_failure && update.ignore_failure;
+        }
+        None => updates.push(update.clone()),
+    }
+    Ok(())
+}
+
+fn update_resources(update: &nri::ContainerUpdate) -> Option<&nri::LinuxResources> {
+    update.linux.as_ref().and_then(|l| l.resources.as_ref())
+}
+
+struct CreateResult {
+    request: nri::CreateContainerRequest,
+    adjust: nri::ContainerAdjustment,
+    update: Vec<nri::ContainerUpdate>,
+    evict: Vec<nri::ContainerEviction>,
+    owners: Owners,
+}
+
+impl CreateResult {
+    fn new(req: &nri::CreateContainerRequest) -> Self {
+        let mut request = req.clone();
+        request.container.mut_or_insert_default();
+        CreateResult {
+            request,
+            adjust: nri::ContainerAdjustment::new(),
+            update: Vec::new(),
+            evict: Vec::new(),
+            owners: Owners::new(),
+        }
+    }
+
+    fn container_id(&self) -> String {
+        self.request.container.as_ref().map_or(String::new(), |c| c.id.clone())
+    }
+
+    fn apply(&mut self, rpl: &nri::CreateContainerResponse, plugin: &str) -> Result<()> {
+        if let Some(adjust) = rpl.adjust.as_ref() {
+            self.adjust_annotations(adjust, plugin)?;
+            self.adjust_mounts(adjust, plugin)?;
+            self.adjust_env(adjust, plugin)?;
+            self.adjust_hooks(adjust, plugin);
+            if let Some(linux) = adjust.linux.as_ref() {
+                self.adjust_linux(linux, plugin)?;
+            }
+            self.adjust_rlimits(adjust, plugin)?;
+            self.adjust_cdi_devices(adjust, plugin)?;
+            self.adjust_args(adjust, plugin)?;
+        }
+
+        let id = self.container_id();
+        for update in &rpl.update {
+            if update.container_id != id {
+                merge_update(&mut self.update, update, &mut self.owners, plugin)?;
+                continue;
+            }
+            if let Some(resources) = update_resources(update) {
+                self.adjust_resources(resources, plugin)?;
+            }
+        }
+        self.evict.extend(rpl.evict.iter().cloned());
+        Ok(())
+    }
+
+    fn adjust_annotations(&mut self, adjust: &nri::ContainerAdjustment, plugin: &str) -> Result<()> {
+        let id = self.container_id();
+        for key in adjust.annotations.keys() {
+            claim_key(&mut self.owners, &id, Field::Annotations, key, plugin)?;
+        }
+        let container = self.request.container.mut_or_insert_default();
+        let adjusted = &mut self.adjust.annotations;
+        for key in adjust.annotations.keys() {
+            if let Some(removed) = marked_for_removal(key) {
+                adjusted.remove(removed);
+                container.annotations.remove(removed);
+                adjusted.insert(key.clone(), String::new());
+            }
+        }
+        for (key, value) in &adjust.annotations {
+            if marked_for_removal(key).is_none() {
+                adjusted.insert(key.clone(), value.clone());
+                container.annotations.insert(key.clone(), value.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn adjust_mounts(&mut self, adjust: &nri::ContainerAdjustment, plugin: &str) -> Result<()> {
+        let id = self.container_id();
+        for mount in &adjust.mounts {
+            claim_key(&mut self.owners, &id, Field::Mounts, &mount.destination, plugin)?;
+        }
+        let container = self.request.container.mut_or_insert_default();
+        merge_keyed(&mut self.adjust.mounts, &mut container.mounts, &adjust.mounts, |m| m.destination.as_str());
+        Ok(())
+    }
+
+    fn adjust_env(&mut self, adjust: &nri::ContainerAdjustment, plugin: &str) -> Result<()> {
+        let id = self.container_id();
+        for env in &adjust.env {
+            claim_key(&mut self.owners, &id, Field::Env, &env.key, plugin)?;
+        }
+        let container = self.request.container.mut_or_insert_default();
+        let adjusted = &mut self.adjust.env;
+        for env in &adjust.env {
+            match marked_for_removal(&env.key) {
+                Some(removed) => {
+                    adjusted.retain(|e| e.key != removed);
+                    container.env.retain(|e| env_key(e) != removed);
+                    replace_or_push(adjusted, env.clone(), |e| e.key.as_str());
+                }
+                None => {
+                    replace_or_push(adjusted, env.clone(), |e| e.key.as_str());
+                    replace_or_push(&mut container.env, format!("{}={}", env.key, env.value), |e| env_key(e));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn adjust_hooks(&mut self, adjust: &nri::ContainerAdjustment, plugin: &str) {
+        let hooks = match adjust.hooks.as_ref() {
+            Some(hooks) => hooks,
+            None => return,
+        };
+        let id = self.container_id();
+        self.owners.accumulate(&id, Field::OciHooks, plugin);
+        let container = self.request.container.mut_or_insert_default();
+        for dst in [self.adjust.hooks.mut_or_insert_default(), container.hooks.mut_or_insert_default()] {
+            dst.prestart.extend(hooks.prestart.iter().cloned());
+            dst.create_runtime.extend(hooks.create_runtime.iter().cloned());
+            dst.create_container.extend(hooks.create_container.iter().cloned());
+            dst.start_container.extend(hooks.start_container.iter().cloned());
+            dst.poststart.extend(hooks.poststart.iter().cloned());
+            dst.poststop.extend(hooks.poststop.iter().cloned());
+        }
+    }
+
+    fn adjust_linux(&mut self, linux: &nri::LinuxContainerAdjustment, plugin: &str) -> Result<()> {
+        let id = self.container_id();
+        for device in &linux.devices {
+            claim_key(&mut self.owners, &id, Field::Devices, &device.path, plugin)?;
+        }
+        for namespace in &linux.namespaces {
+            claim_key(&mut self.owners, &id, Field::Namespace, &namespace.type_, plugin)?;
+        }
+        if !linux.cgroups_path.is_empty() {
+            self.owners.claim(&id, Field::CgroupsPath, plugin)?;
+        }
+        claim_optional!(self.owners, &id, plugin, linux,
+            oom_score_adj => Field::OomScoreAdj,
+            io_priority => Field::IoPriority,
+            seccomp_policy => Field::SeccompPolicy);
+        if let Some(resources) = linux.resources.as_ref() {
+            self.adjust_resources(resources, plugin)?;
+        }
+        let adjusted = self.adjust.linux.mut_or_insert_default();
+        let current = self.request.container.mut_or_insert_default().linux.mut_or_insert_default();
+        merge_keyed(&mut adjusted.devices, &mut current.devices, &linux.devices, |d| d.path.as_str());
+        merge_keyed(&mut adjusted.namespaces, &mut current.namespaces, &linux.namespaces, |n| n.type_.as_str());
+        if !linux.cgroups_path.is_empty() {
+            adjusted.cgroups_path = linux.cgroups_path.clone();
+            current.cgroups_path = linux.cgroups_path.clone();
+        }
+        merge_optional!(adjusted, linux, oom_score_adj, io_priority, seccomp_policy);
+        merge_optional!(current, linux, oom_score_adj, io_priority, seccomp_policy);
+        Ok(())
+    }
+
+    fn adjust_resources(&mut self, resources: &nri::LinuxResources, plugin: &str) -> Result<()> {
+        let id = self.container_id();
+        claim_resources(&mut self.owners, &id, resources, plugin)?;
+        let adjusted = self.adjust.linux.mut_or_insert_default().resources.mut_or_insert_default();
+        merge_resources(adjusted, resources);
+        let current = self.request.container.mut_or_insert_default().linux.mut_or_insert_default();
+        merge_resources(current.resources.mut_or_insert_default(), resources);
+        Ok(())
+    }
+
+    fn adjust_rlimits(&mut self, adjust: &nri::ContainerAdjustment, plugin: &str) -> Result<()> {
+        let id = self.container_id();
+        for rlimit in &adjust.rlimits {
+            self.owners.claim_key(&id, Field::Rlimits, &rlimit.type_, plugin)?;
+        }
+        let container = self.request.container.mut_or_insert_default();
+        merge_keyed(&mut self.adjust.rlimits, &mut container.rlimits, &adjust.rlimits, |r| r.type_.as_str());
+        Ok(())
+    }
+
+    fn adjust_cdi_devices(&mut self, adjust: &nri::ContainerAdjustment, plugin: &str) -> Result<()> {
+        let id = self.container_id();
+        for device in &adjust.CDI_devices {
+            claim_key(&mut self.owners, &id, Field::CdiDevices, &device.name, plugin)?;
+        }
+        let container = self.request.container.mut_or_insert_default();
+        merge_keyed(&mut self.adjust.CDI_devices, &mut container.CDI_devices, &adjust.CDI_devices, |d| d.name.as_str());
+        Ok(())
+    }
+
+    fn adjust_args(&mut self, adjust: &nri::ContainerAdjustment, plugin: &str) -> Result<()> {
+        if adjust.args.is_empty() {
+            return Ok(());
+        }
+        let id = self.container_id();
+        self.owners.claim(&id, Field::Args, plugin)?;
+        self.adjust.args = adjust.args.clone();
+        self.request.container.mut_or_insert_default().args = adjust.args.clone();
+        Ok(())
+    }
+
+    fn into_response(self) -> nri::CreateContainerResponse {
+        let mut resp = nri::CreateContainerResponse::new();
+        resp.adjust = MessageField::some(self.adjust);
+        resp.update = self.update;
+        resp.evict = self.evict;
+        resp
+    }
+}
+
+// create_container asks every registered plugin to adjust the container
+// being created and returns the combined adjustment, updates and evictions.
+pub fn create_container(req: &nri::CreateContainerRequest) -> Result<nri::CreateContainerResponse> {
+    let mut result = CreateResult::new(req);
+    // Whatever is left of an earlier container of the same id, whose creation
+    // failed after the dispatch for instance, is not about this one.
+    forget_container(&result.container_id());
+    for info in plugin::registered_plugins()? {
+        let rpl = plugin::create_container(&info.id, &result.request)
+            .map_err(|e| Error::Other(format!("plugin {} failed to create container: {}", info.full_name(), e)))?;
+        result.apply(&rpl, &info.full_name())?;
+    }
+    CONTAINER_OWNERS.write().unwrap().insert(result.container_id(), result.owners.clone());
+    Ok(result.into_response())
+}
+
+// container_owners returns the field owners recorded when the container was
+// created through create_container, until the container is removed.
+pub fn container_owners(container_id: &str) -> Option<Owners> {
+    CONTAINER_OWNERS.read().unwrap().get(container_id).cloned()
+}
+
+pub fn forget_container(container_id: &str) {
+    CONTAINER_OWNERS.write().unwrap().remove(container_id);
+}
+
+// forget_removed_container drops the owners of the container a
+// REMOVE_CONTAINER event is about.
+pub fn forget_removed_container(req: &nri::StateChangeEvent) {
+    if req.event.enum_value() != Ok(nri::Event::REMOVE_CONTAINER) {
+        return;
+    }
+    if let Some(container) = req.container.as_ref() {
+        forget_container(&container.id);
+    }
+}
+
+// update_container asks every registered plugin to alter the update of a
+// container. The resulting resources of the container are returned as the
+// first update if any plugin changed them.
+pub fn update_container(req: &nri::UpdateContainerRequest) -> Result<nri::UpdateContainerResponse> {
+    let mut request = req.clone();
+    let id = req.container.as_ref().map_or(String::new(), |c| c.id.clone());
+    let mut resp = nri::UpdateContainerResponse::new();
+    let mut owners = Owners::new();
+    let mut changed = false;
+    for info in plugin::registered_plugins()? {
+        let rpl = plugin::update_container(&info.id, &request)
+            .map_err(|e| Error::Other(format!("plugin {} failed to update container: {}", info.full_name(), e)))?;
+        let name = info.full_name();
+        for update in &rpl.update {
+            if update.container_id != id {
+                merge_update(&mut resp.update, update, &mut owners, &name)?;
+                continue;
+            }
+            if let Some(resources) = update_resources(update) {
+                claim_resources(&mut owners, &id, resources, &name)?;
+                merge_resources(request.linux_resources.mut_or_insert_default(), resources);
+                changed = true;
+            }
+        }
+        resp.evict.extend(rpl.evict.iter().cloned());
+    }
+    if changed {
+        let mut update = nri::ContainerUpdate::new();
+        update.container_id = id;
+        update.linux.mut_or_insert_default().resources = request.linux_resources;
+        resp.update.insert(0, update);
+    }
+    Ok(resp)
+}
+
+// stop_container notifies every registered plugin about the container being
+// stopped and returns the updates they request to the remaining containers.
+pub fn stop_container(req: &nri::StopContainerRequest) -> Result<nri::StopContainerResponse> {
+    let mut resp = nri::StopContainerResponse::new();
+    let mut owners = Owners::new();
+    for info in plugin::registered_plugins()? {
+        let rpl = plugin::stop_container(&info.id, req)
+            .map_err(|e| Error::Other(format!("plugin {} failed to stop container: {}", info.full_name(), e)))?;
+        for update in &rpl.update {
+            merge_update(&mut resp.update, update, &mut owners, &info.full_name())?;
+        }
+    }
+    Ok(resp)
+}
+
+// Outcome of an event sent to one plugin.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Failed(String),
+    TimedOut,
+}
+
+#[derive(Clone, Debug)]
+pub struct PluginResult {
+    pub plugin: PluginInfo,
+    pub outcome: Outcome,
+}
+
+// Events waiting for a plugin still handling an earlier one. Past that the
+// plugin is not keeping up, and further events to it fail right away.
+pub const EVENT_QUEUE_LEN: usize = 16;
+
+// An event to send to a plugin, with where to report the result to.
+struct EventJob {
+    req: nri::StateChangeEvent,
+    idx: usize,
+    res_tx: mpsc::Sender<(usize, Result<()>)>,
+}
+
+// event_queue starts the worker sending the events of the queue to a plugin
+// one after the other, in the order they are broadcast. It exits once the
+// queue is dropped and drained.
+fn event_queue(plugin_id: &str) -> crossbeam_channel::Sender<EventJob> {
+    let (job_tx, job_rx) = crossbeam_channel::bounded::<EventJob>(EVENT_QUEUE_LEN);
+    let plugin_id = plugin_id.to_string();
+    thread::spawn(move || {
+        for job in job_rx {
+            let _unused = job.res_tx.send((job.idx, plugin::state_change(&plugin_id, &job.req)));
+        }
+    });
+    job_tx
+}
+
+// broadcast_state_change sends a state change event to every registered
+// plugin subscribed for it, all at once, and returns the outcome for each of
+// them in index order. Plugins which have not answered when timeout expires
+// are reported as timed out, a zero timeout waits for all of them. Unlike
+// the container requests the event is a mere notification: a failing plugin
+// does not fail the others.
+pub fn broadcast_state_change(req: &nri::StateChangeEvent, timeout: Duration) -> Result<Vec<PluginResult>> {
+    forget_removed_container(req);
+    let event = req.event.enum_value()
+        .map_err(|e| Error::InvalidArgument(format!("unknown event {}", e)))?;
+    let deadline = Instant::now() + timeout;
+    let registered = plugin::registered_plugins()?;
+    let plugins: Vec<PluginInfo> = registered.iter()
+        .filter(|info| plugin::is_subscribed(&info.id, event).unwrap_or(false))
+        .cloned()
+        .collect();
+
+    let mut outcomes = vec![Outcome::TimedOut; plugins.len()];
+    let (res_tx, res_rx) = mpsc::channel();
+    {
+        let mut queues = EVENT_QUEUES.lock().unwrap();
+        // The workers of the plugins gone away exit.
+        queues.retain(|id, _| registered.iter().any(|info| &info.id == id));
+        for (idx, info) in plugins.iter().enumerate() {
+            // Left to finish on its own past the deadline, within the plugin timeout.
+            let job = EventJob { req: req.clone(), idx, res_tx: res_tx.clone() };
+            let queue = queues.entry(info.id.clone()).or_insert_with(|| event_queue(&info.id));
+            if queue.try_send(job).is_err() {
+                outcomes[idx] = Outcome::Failed(format!("{} events queued for the plugin already", EVENT_QUEUE_LEN));
+            }
+        }
+    }
+    drop(res_tx);
+
+    loop {
+        let received = if timeout.is_zero() {
+            res_rx.recv().ok()
+        } else {
+            res_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok()
+        };
+        let (i, res) = match received {
+            Some(received) => received,
+            None => break,
+        };
+        outcomes[i] = match res {
+            Ok(_) => Outcome::Ok,
+            Err(Error::Timeout(_)) => Outcome::TimedOut,
+            Err(e) => Outcome::Failed(e.to_string()),
+        };
+    }
+
+    Ok(plugins.into_iter().zip(outcomes)
+        .map(|(plugin, outcome)| PluginResult { plugin, outcome })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn key_value(key: &str, value: &str) -> nri::KeyValue {
+        nri::KeyValue { key: key.to_string(), value: value.to_string(), ..Default::default() }
+    }
+
+    fn reply(adjust: nri::ContainerAdjustment) -> nri::CreateContainerResponse {
+        nri::CreateContainerResponse { adjust: MessageField::some(adjust), ..Default::default() }
+    }
+
+    fn adjusting(annotations: &[(&str, &str)], env: &[(&str, &str)]) -> nri::CreateContainerResponse {
+        reply(nri::ContainerAdjustment {
+            annotations: annotations.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            env: env.iter().map(|(k, v)| key_value(k, v)).collect(),
+            ..Default::default()
+        })
+    }
+
+    fn mount(destination: &str) -> nri::CreateContainerResponse {
+        let mut adjust = nri::ContainerAdjustment::new();
+        adjust.mounts.push(nri::Mount { destination: destination.to_string(), ..Default::default() });
+        reply(adjust)
+    }
+
+    fn cpuset(cpus: &str) -> nri::CreateContainerResponse {
+        let mut adjust = nri::ContainerAdjustment::new();
+        adjust.linux.mut_or_insert_default().resources.mut_or_insert_default().cpu.mut_or_insert_default().cpus =
+            cpus.to_string();
+        reply(adjust)
+    }
+
+    fn hook(path: &str) -> nri::CreateContainerResponse {
+        let mut adjust = nri::ContainerAdjustment::new();
+        adjust.hooks.mut_or_insert_default().prestart.push(nri::Hook { path: path.to_string(), ..Default::default() });
+        reply(adjust)
+    }
+
+    fn updating(id: &str, resources: nri::LinuxResources) -> nri::ContainerUpdate {
+        let mut update = nri::ContainerUpdate::new();
+        update.container_id = id.to_string();
+        update.linux.mut_or_insert_default().resources = MessageField::some(resources);
+        update
+    }
+
+    fn resources(shares: u64, limit: i64) -> nri::LinuxResources {
+        let mut resources = nri::LinuxResources::new();
+        if shares != 0 {
+            resources.cpu.mut_or_insert_default().shares =
+                MessageField::some(nri::OptionalUInt64 { value: shares, ..Default::default() });
+        }
+        if limit != 0 {
+            resources.memory.mut_or_insert_default().limit =
+                MessageField::some(nri::OptionalInt64 { value: limit, ..Default::default() });
+        }
+        resources
+    }
+
+    fn create_request(id: &str) -> nri::CreateContainerRequest {
+        let mut req = nri::CreateContainerRequest::new();
+        let container = req.container.mut_or_insert_default();
+        container.id = id.to_string();
+        container.annotations.insert("keep".to_string(), "1".to_string());
+        container.annotations.insert("drop".to_string(), "1".to_string());
+        container.env = vec!["PATH=/bin".to_string(), "DEBUG=1".to_string()];
+        req
+    }
+
+    fn sorted(map: &HashMap<String, String>) -> BTreeMap<&str, &str> {
+        map.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
+    }
+
+    #[test]
+    fn create_merges_adjustments_in_order() {
+        let mut result = CreateResult::new(&create_request("ctr-0"));
+        result.apply(&adjusting(&[("zeta", "1")], &[("ZETA", "1")]), "05-zeta").unwrap();
+        result.apply(&adjusting(&[("alpha", "1"), ("-drop", "")], &[("-DEBUG", "")]), "10-alpha").unwrap();
+
+        // The next plugin sees the container as adjusted so far.
+        let container = result.request.container.as_ref().unwrap();
+        assert_eq!(sorted(&container.annotations), BTreeMap::from([("alpha", "1"), ("keep", "1"), ("zeta", "1")]));
+        assert_eq!(container.env, vec!["PATH=/bin".to_string(), "ZETA=1".to_string()]);
+
+        // The removal markers are kept for the runtime to remove the entries
+        // too.
+        let adjust = result.into_response().adjust.unwrap();
+        assert_eq!(sorted(&adjust.annotations), BTreeMap::from([("-drop", ""), ("alpha", "1"), ("zeta", "1")]));
+        assert_eq!(adjust.env, vec![key_value("ZETA", "1"), key_value("-DEBUG", "")]);
+    }
+
+    #[test]
+    fn create_folds_updates_of_the_container() {
+        let mut result = CreateResult::new(&create_request("ctr-0"));
+        result.apply(&nri::CreateContainerResponse {
+            update: vec![updating("ctr-0", resources(512, 0)), updating("ctr-9", resources(256, 0))],
+            evict: vec![nri::ContainerEviction { container_id: "ctr-8".to_string(), ..Default::default() }],
+            ..Default::default()
+        }, "10-cpu").unwrap();
+        result.apply(&nri::CreateContainerResponse {
+            update: vec![updating("ctr-0", resources(0, 1 << 30)), updating("ctr-9", resources(0, 1 << 20))],
+            ..Default::default()
+        }, "20-memory").unwrap();
+
+        let resp = result.into_response();
+        assert_eq!(resp.adjust.linux.resources.clone().into_option(), Some(resources(512, 1 << 30)));
+        assert_eq!(resp.update, vec![updating("ctr-9", resources(256, 1 << 20))]);
+        assert_eq!(resp.evict.len(), 1);
+    }
+
+    #[test]
+    fn create_conflicts() {
+        let conflict = |first: nri::CreateContainerResponse, second: nri::CreateContainerResponse, what: &str| {
+            let mut result = CreateResult::new(&create_request("ctr-0"));
+            result.apply(&first, "10-first").unwrap();
+            match result.apply(&second, "20-second") {
+                Err(Error::Conflict(msg)) => {
+                    assert!(msg.contains("\"10-first\"") && msg.contains("\"20-second\""), "{}", msg);
+                    assert!(msg.contains(what), "{}", msg);
+                }
+                res => panic!("{} set by both plugins: {:?}", what, res),
+            }
+        };
+        conflict(adjusting(&[], &[("FOO", "1")]), adjusting(&[], &[("FOO", "2")]), "Env \"FOO\"");
+        // Removing an entry owns it as well.
+        conflict(mount("/data"), mount("-/data"), "Mounts \"/data\"");
+        conflict(cpuset("0-1"), cpuset("2-3"), "CPUSetCPUs");
+
+        // Hooks are accumulated rather than owned.
+        let mut result = CreateResult::new(&create_request("ctr-0"));
+        result.apply(&hook("/bin/first"), "10-first").unwrap();
+        result.apply(&hook("/bin/second"), "20-second").unwrap();
+        result.apply(&adjusting(&[], &[("FOO", "1")]), "20-second").unwrap();
+        let owners = result.owners.dump();
+        assert_eq!(owners, "ctr-0 Env[FOO]: 20-second\nctr-0 OciHooks: 10-first,20-second\n");
+    }
+
+    #[test]
+    fn owners_dropped_on_removal() {
+        let event = |event: nri::Event| {
+            let mut req = nri::StateChangeEvent::new();
+            req.event = event.into();
+            req.container.mut_or_insert_default().id = "ctr-removed".to_string();
+            req
+        };
+        CONTAINER_OWNERS.write().unwrap().insert("ctr-removed".to_string(), Owners::new());
+        forget_removed_container(&event(nri::Event::STOP_CONTAINER));
+        assert!(container_owners("ctr-removed").is_some());
+        forget_removed_container(&event(nri::Event::REMOVE_CONTAINER));
+        assert!(container_owners("ctr-removed").is_none());
+    }
+}
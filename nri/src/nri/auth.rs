@@ -0,0 +1,87 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// auth decides which peers may connect to the external plugin socket, from
+// their SO_PEERCRED credentials. The socket file mode still applies, the
+// allow-list narrows it down.
+
+use lazy_static::lazy_static;
+use std::fs;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::nri::error::{Error, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerCred {
+    pub pid: libc::pid_t,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+}
+
+// AllowList lets a peer in if its uid or its primary gid is listed, any
+// peer if neither uids nor gids are, and, if exe_paths are set, only if it
+// runs one of them.
+#[derive(Clone, Debug, Default)]
+pub struct AllowList {
+    pub uids: Vec<libc::uid_t>,
+    pub gids: Vec<libc::gid_t>,
+    pub exe_paths: Vec<PathBuf>,
+}
+
+lazy_static! {
+    static ref ALLOW_LIST: RwLock<AllowList> = RwLock::new(AllowList::default());
+}
+
+pub fn set_allow_list(allow_list: AllowList) {
+    *ALLOW_LIST.write().unwrap() = allow_list;
+}
+
+pub fn peer_cred(stream: &UnixStream) -> Result<PeerCred> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void, &mut len)
+    };
+    if ret != 0 {
+        return Err(Error::IOError(format!("get peer credentials error: {}", std::io::Error::last_os_error())));
+    }
+    Ok(PeerCred { pid: cred.pid, uid: cred.uid, gid: cred.gid })
+}
+
+// authorize returns the credentials of the peer if the allow-list lets it
+// in.
+pub fn authorize(stream: &UnixStream) -> Result<PeerCred> {
+    let cred = peer_cred(stream)?;
+    let allow_list = ALLOW_LIST.read().unwrap().clone();
+
+    let allowed = (allow_list.uids.is_empty() && allow_list.gids.is_empty())
+        || allow_list.uids.contains(&cred.uid)
+        || allow_list.gids.contains(&cred.gid);
+    if !allowed {
+        return Err(Error::InvalidArgument(format!("peer pid {} uid {} gid {} not allowed",
+            cred.pid, cred.uid, cred.gid)));
+    }
+
+    if !allow_list.exe_paths.is_empty() {
+        let exe = fs::read_link(format!("/proc/{}/exe", cred.pid))
+            .map_err(|e| Error::IOError(format!("read exe of peer pid {} error: {}", cred.pid, e)))?;
+        if !allow_list.exe_paths.contains(&exe) {
+            return Err(Error::InvalidArgument(format!("peer pid {} exe {} not allowed",
+                cred.pid, exe.display())));
+        }
+    }
+    Ok(cred)
+}
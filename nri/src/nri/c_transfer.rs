@@ -15,6 +15,7 @@ use std::os::raw::{c_char, c_int, c_void};
 
 use protobuf::{EnumOrUnknown, MessageField};
 
+use crate::nri::{adaptation, auth, plugin};
 use crate::protocols::nri::{self, OptionalBool, OptionalFileMode, OptionalInt, OptionalInt64, OptionalString, OptionalUInt32, OptionalUInt64};
 
 use isula_common::isula_data_types::{to_c_char_ptr, to_string};
@@ -24,6 +25,49 @@ use isula_common::isula_data_types::c_char_ptr_ptr_to_vec;
 use isula_common::isula_data_types::vec_to_c_char_ptr_ptr;
 use isula_common::isula_data_types::MapStringString;
 
+// The version of the isula_libutils NRI schemas the structs below are laid
+// out for, NRI_LIBUTILS_SCHEMA_VERSION in nri_plugin.h.
+pub const NRI_LIBUTILS_SCHEMA_VERSION: u32 = 2;
+
+// The output structs own everything they point to, and free it on drop: the
+// strings made by to_c_char_ptr, the boxed values and structs, and the arrays
+// made by vec_to_double_ptr and vec_to_c_char_ptr_ptr. C hands them back to
+// the nri_free_* functions.
+
+fn free_string(s: *const c_char) {
+    if !s.is_null() {
+        let _unused = unsafe { CString::from_raw(s as *mut c_char) };
+    }
+}
+
+fn free_box<T>(p: *const T) {
+    if !p.is_null() {
+        let _unused = unsafe { Box::from_raw(p as *mut T) };
+    }
+}
+
+// free_array frees an array of boxed items, as made by vec_to_double_ptr.
+fn free_array<T>(array: *const *const T, len: usize) {
+    if array.is_null() {
+        return;
+    }
+    let array = unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(array as *mut *const T, len)) };
+    for item in array.iter() {
+        free_box(*item);
+    }
+}
+
+// free_strings frees an array of strings, as made by vec_to_c_char_ptr_ptr.
+fn free_strings(array: *const *const c_char, len: usize) {
+    if array.is_null() {
+        return;
+    }
+    let array = unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(array as *mut *const c_char, len)) };
+    for item in array.iter() {
+        free_string(*item);
+    }
+}
+
 #[repr(C)]
 pub struct NriLinuxMemory {
     limit: *const i64,
@@ -316,6 +360,30 @@ impl Drop for NriLinuxDeviceCgroup {
     }
 
 }
+
+#[repr(C)]
+pub struct NriLinuxPids {
+    limit: i64,
+    residual: *const c_void,
+}
+
+impl From<&NriLinuxPids> for nri::LinuxPids {
+    fn from(req: &NriLinuxPids) -> Self {
+        let mut r_req = nri::LinuxPids::new();
+        r_req.limit = req.limit;
+        r_req
+    }
+}
+
+impl From<&nri::LinuxPids> for NriLinuxPids {
+    fn from(req: &nri::LinuxPids) -> Self {
+        NriLinuxPids {
+            limit: req.limit,
+            residual: std::ptr::null(),
+        }
+    }
+}
+
 #[repr(C)]
 pub struct NriLinuxResources {
     memory: *const NriLinuxMemory,
@@ -327,6 +395,7 @@ pub struct NriLinuxResources {
     unified: *const MapStringString,
     devices: *const *const NriLinuxDeviceCgroup,
     devices_len: usize,
+    pids: *const NriLinuxPids,
     residual: *const c_void,
 }
 
@@ -355,6 +424,9 @@ impl From<&NriLinuxResources> for nri::LinuxResources {
             false => unsafe { <std::collections::HashMap<String, String>>::from(&*req.unified) },
         };
         r_req.devices = double_ptr_to_vec(req.devices, req.devices_len);
+        if !req.pids.is_null() {
+            r_req.pids = MessageField::some(nri::LinuxPids::from(unsafe { req.pids.as_ref() }.unwrap()));
+        }
         r_req
     }
 }
@@ -366,13 +438,14 @@ impl From<&nri::LinuxResources> for NriLinuxResources {
         let r_req = NriLinuxResources {
             memory: req.memory.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(NriLinuxMemory::from(x)))),
             cpu: req.cpu.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(NriLinuxCpu::from(x)))),
-            hugepage_limits: hugepage_limits,
-            hugepage_limits_len: hugepage_limits_len,
+            hugepage_limits,
+            hugepage_limits_len,
             blockio_class: req.blockio_class.as_ref().map_or(std::ptr::null(), |x| to_c_char_ptr(x.value.as_str())),
             rdt_class: req.rdt_class.as_ref().map_or(std::ptr::null(), |x| to_c_char_ptr(x.value.as_str())),
             unified: Box::into_raw(Box::new(MapStringString::from(&req.unified))),
-            devices: devices,
-            devices_len: devices_len,
+            devices,
+            devices_len,
+            pids: req.pids.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(NriLinuxPids::from(x)))),
             residual: std::ptr::null(),
         };
         r_req
@@ -387,15 +460,7 @@ impl Drop for NriLinuxResources {
         if !self.cpu.is_null() {
             let _unused = unsafe { Box::from_raw(self.cpu as *mut NriLinuxCpu) };
         }
-        if !self.hugepage_limits.is_null() {
-            let slice = unsafe { std::slice::from_raw_parts(self.hugepage_limits, self.hugepage_limits_len) };
-            for item in slice {
-                if !item.is_null() {
-                    let _unused = unsafe { Box::from_raw(*item as *mut NriHugepageLimit) };
-                }
-            }
-            let _unused = unsafe { Box::from_raw(self.hugepage_limits as *mut *const NriHugepageLimit) };
-        }
+        free_array(self.hugepage_limits, self.hugepage_limits_len);
         if !self.blockio_class.is_null() {
             let _unused = unsafe { CString::from_raw(self.blockio_class as *mut c_char) };
         }
@@ -405,14 +470,9 @@ impl Drop for NriLinuxResources {
         if !self.unified.is_null() {
             let _unused = unsafe { Box::from_raw(self.unified as *mut MapStringString) };
         }
-        if !self.devices.is_null() {
-            let slice = unsafe { std::slice::from_raw_parts(self.devices, self.devices_len) };
-            for item in slice {
-                if !item.is_null() {
-                    let _unused = unsafe { Box::from_raw(*item as *mut NriLinuxDeviceCgroup) };
-                }
-            }
-            let _unused = unsafe { Box::from_raw(self.devices as *mut *const NriLinuxDeviceCgroup) };
+        free_array(self.devices, self.devices_len);
+        if !self.pids.is_null() {
+            let _unused = unsafe { Box::from_raw(self.pids as *mut NriLinuxPids) };
         }
     }
 }
@@ -433,6 +493,27 @@ impl From<&NriLinuxNamespace> for nri::LinuxNamespace {
     }
 }
 
+impl From<&nri::LinuxNamespace> for NriLinuxNamespace {
+    fn from(req: &nri::LinuxNamespace) -> Self {
+        NriLinuxNamespace {
+            type_: to_c_char_ptr(req.type_.as_str()),
+            path: to_c_char_ptr(req.path.as_str()),
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriLinuxNamespace {
+    fn drop(&mut self) {
+        if !self.type_.is_null() {
+            let _unused = unsafe { CString::from_raw(self.type_ as *mut c_char) };
+        }
+        if !self.path.is_null() {
+            let _unused = unsafe { CString::from_raw(self.path as *mut c_char) };
+        }
+    }
+}
+
 #[repr(C)]
 pub struct NriLinuxPodSandbox {
     pod_overhead: *const NriLinuxResources,
@@ -475,6 +556,8 @@ pub struct NriPodSandbox {
     runtime_handler: *const c_char,
     linux: *const NriLinuxPodSandbox,
     pid: u32,
+    ips: *const *const c_char,
+    ips_len: usize,
     residual: *const c_void,
 }
 
@@ -498,6 +581,7 @@ impl From<&NriPodSandbox> for nri::PodSandbox {
             r_req.linux = MessageField::some(nri::LinuxPodSandbox::from(unsafe { req.linux.as_ref() }.unwrap()));
         }
         r_req.pid = req.pid;
+        r_req.ips = c_char_ptr_ptr_to_vec(req.ips, req.ips_len);
         r_req
     }
 }
@@ -530,13 +614,23 @@ impl From<&nri::Mount> for NriMount {
             destination: to_c_char_ptr(req.destination.as_str()),
             type_: to_c_char_ptr(req.type_.as_str()),
             source: to_c_char_ptr(req.source.as_str()),
-            options: options,
-            options_len: options_len,
+            options,
+            options_len,
             residual: std::ptr::null(),
         };
         r_req
     }
 }
+
+impl Drop for NriMount {
+    fn drop(&mut self) {
+        free_string(self.destination);
+        free_string(self.type_);
+        free_string(self.source);
+        free_strings(self.options, self.options_len);
+    }
+}
+
 #[repr(C)]
 pub struct NriHook {
     path: *const c_char,
@@ -569,10 +663,10 @@ impl From<&nri::Hook> for NriHook {
         let (env, env_len) = vec_to_c_char_ptr_ptr(&req.env);
         let r_req = NriHook {
             path: to_c_char_ptr(req.path.as_str()),
-            args: args,
-            args_len: args_len,
-            env: env,
-            env_len: env_len,
+            args,
+            args_len,
+            env,
+            env_len,
             timeout: req.timeout.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(x.value))),
             residual: std::ptr::null(),
         };
@@ -580,6 +674,15 @@ impl From<&nri::Hook> for NriHook {
     }
 }
 
+impl Drop for NriHook {
+    fn drop(&mut self) {
+        free_string(self.path);
+        free_strings(self.args, self.args_len);
+        free_strings(self.env, self.env_len);
+        free_box(self.timeout);
+    }
+}
+
 #[repr(C)]
 pub struct NriHooks {
     prestart: *const *const NriHook,
@@ -619,24 +722,36 @@ impl From<&nri::Hooks> for NriHooks {
         let (poststart, poststart_len) = vec_to_double_ptr(&req.poststart);
         let (poststop, poststop_len) = vec_to_double_ptr(&req.poststop);
         let r_req = NriHooks {
-            prestart: prestart,
-            prestart_len: prestart_len,
-            create_runtime: create_runtime,
-            create_runtime_len: create_runtime_len,
-            create_container: create_container,
-            create_container_len: create_container_len,
-            start_container: start_container,
-            start_container_len: start_container_len,
-            poststart: poststart,
-            poststart_len: poststart_len,
-            poststop: poststop,
-            poststop_len: poststop_len,
+            prestart,
+            prestart_len,
+            create_runtime,
+            create_runtime_len,
+            create_container,
+            create_container_len,
+            start_container,
+            start_container_len,
+            poststart,
+            poststart_len,
+            poststop,
+            poststop_len,
             residual: std::ptr::null(),
         };
         r_req
     }
 
 }
+
+impl Drop for NriHooks {
+    fn drop(&mut self) {
+        free_array(self.prestart, self.prestart_len);
+        free_array(self.create_runtime, self.create_runtime_len);
+        free_array(self.create_container, self.create_container_len);
+        free_array(self.start_container, self.start_container_len);
+        free_array(self.poststart, self.poststart_len);
+        free_array(self.poststop, self.poststop_len);
+    }
+}
+
 #[repr(C)]
 pub struct NriPosixRlimit {
     type_: *const c_char,
@@ -667,6 +782,270 @@ impl From<&nri::POSIXRlimit> for NriPosixRlimit {
     }
 }
 
+impl Drop for NriPosixRlimit {
+    fn drop(&mut self) {
+        free_string(self.type_);
+    }
+}
+
+#[repr(C)]
+pub struct NriLinuxIoPriority {
+    class: i32,
+    priority: i32,
+    residual: *const c_void,
+}
+
+impl From<&NriLinuxIoPriority> for nri::LinuxIOPriority {
+    fn from(req: &NriLinuxIoPriority) -> Self {
+        let mut r_req = nri::LinuxIOPriority::new();
+        r_req.class = EnumOrUnknown::from_i32(req.class);
+        r_req.priority = req.priority;
+        r_req
+    }
+}
+
+impl From<&nri::LinuxIOPriority> for NriLinuxIoPriority {
+    fn from(req: &nri::LinuxIOPriority) -> Self {
+        NriLinuxIoPriority {
+            class: req.class.value(),
+            priority: req.priority,
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+#[repr(C)]
+pub struct NriSecurityProfile {
+    profile_type: i32,
+    localhost_ref: *const c_char,
+    residual: *const c_void,
+}
+
+impl From<&NriSecurityProfile> for nri::SecurityProfile {
+    fn from(req: &NriSecurityProfile) -> Self {
+        let mut r_req = nri::SecurityProfile::new();
+        r_req.profile_type = EnumOrUnknown::from_i32(req.profile_type);
+        r_req.localhost_ref = to_string(req.localhost_ref);
+        r_req
+    }
+}
+
+impl From<&nri::SecurityProfile> for NriSecurityProfile {
+    fn from(req: &nri::SecurityProfile) -> Self {
+        NriSecurityProfile {
+            profile_type: req.profile_type.value(),
+            localhost_ref: to_c_char_ptr(req.localhost_ref.as_str()),
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriSecurityProfile {
+    fn drop(&mut self) {
+        free_string(self.localhost_ref);
+    }
+}
+
+#[repr(C)]
+pub struct NriLinuxSeccompArg {
+    index: u32,
+    value: u64,
+    value_two: u64,
+    op: *const c_char,
+    residual: *const c_void,
+}
+
+impl From<&NriLinuxSeccompArg> for nri::LinuxSeccompArg {
+    fn from(req: &NriLinuxSeccompArg) -> Self {
+        let mut r_req = nri::LinuxSeccompArg::new();
+        r_req.index = req.index;
+        r_req.value = req.value;
+        r_req.value_two = req.value_two;
+        r_req.op = to_string(req.op);
+        r_req
+    }
+}
+
+impl From<&nri::LinuxSeccompArg> for NriLinuxSeccompArg {
+    fn from(req: &nri::LinuxSeccompArg) -> Self {
+        NriLinuxSeccompArg {
+            index: req.index,
+            value: req.value,
+            value_two: req.value_two,
+            op: to_c_char_ptr(req.op.as_str()),
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriLinuxSeccompArg {
+    fn drop(&mut self) {
+        if !self.op.is_null() {
+            let _unused = unsafe { CString::from_raw(self.op as *mut c_char) };
+        }
+    }
+}
+
+#[repr(C)]
+pub struct NriLinuxSyscall {
+    names: *const *const c_char,
+    names_len: usize,
+    action: *const c_char,
+    errno_ret: *const u32,
+    args: *const *const NriLinuxSeccompArg,
+    args_len: usize,
+    residual: *const c_void,
+}
+
+impl From<&NriLinuxSyscall> for nri::LinuxSyscall {
+    fn from(req: &NriLinuxSyscall) -> Self {
+        let mut r_req = nri::LinuxSyscall::new();
+        r_req.names = c_char_ptr_ptr_to_vec(req.names, req.names_len);
+        r_req.action = to_string(req.action);
+        if !req.errno_ret.is_null() {
+            let mut errno_ret = OptionalUInt32::new();
+            errno_ret.value = unsafe { *req.errno_ret };
+            r_req.errno_ret = MessageField::some(errno_ret);
+        }
+        r_req.args = double_ptr_to_vec(req.args, req.args_len);
+        r_req
+    }
+}
+
+impl From<&nri::LinuxSyscall> for NriLinuxSyscall {
+    fn from(req: &nri::LinuxSyscall) -> Self {
+        let (names, names_len) = vec_to_c_char_ptr_ptr(&req.names);
+        let (args, args_len) = vec_to_double_ptr(&req.args);
+        NriLinuxSyscall {
+            names,
+            names_len,
+            action: to_c_char_ptr(req.action.as_str()),
+            errno_ret: req.errno_ret.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(x.value))),
+            args,
+            args_len,
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriLinuxSyscall {
+    fn drop(&mut self) {
+        free_strings(self.names, self.names_len);
+        if !self.action.is_null() {
+            let _unused = unsafe { CString::from_raw(self.action as *mut c_char) };
+        }
+        if !self.errno_ret.is_null() {
+            let _unused = unsafe { Box::from_raw(self.errno_ret as *mut u32) };
+        }
+        free_array(self.args, self.args_len);
+    }
+}
+
+#[repr(C)]
+pub struct NriLinuxSeccomp {
+    default_action: *const c_char,
+    default_errno: *const u32,
+    architectures: *const *const c_char,
+    architectures_len: usize,
+    flags: *const *const c_char,
+    flags_len: usize,
+    listener_path: *const c_char,
+    listener_metadata: *const c_char,
+    syscalls: *const *const NriLinuxSyscall,
+    syscalls_len: usize,
+    residual: *const c_void,
+}
+
+impl From<&NriLinuxSeccomp> for nri::LinuxSeccomp {
+    fn from(req: &NriLinuxSeccomp) -> Self {
+        let mut r_req = nri::LinuxSeccomp::new();
+        r_req.default_action = to_string(req.default_action);
+        if !req.default_errno.is_null() {
+            let mut default_errno = OptionalUInt32::new();
+            default_errno.value = unsafe { *req.default_errno };
+            r_req.default_errno = MessageField::some(default_errno);
+        }
+        r_req.architectures = c_char_ptr_ptr_to_vec(req.architectures, req.architectures_len);
+        r_req.flags = c_char_ptr_ptr_to_vec(req.flags, req.flags_len);
+        r_req.listener_path = to_string(req.listener_path);
+        r_req.listener_metadata = to_string(req.listener_metadata);
+        r_req.syscalls = double_ptr_to_vec(req.syscalls, req.syscalls_len);
+        r_req
+    }
+}
+
+impl From<&nri::LinuxSeccomp> for NriLinuxSeccomp {
+    fn from(req: &nri::LinuxSeccomp) -> Self {
+        let (architectures, architectures_len) = vec_to_c_char_ptr_ptr(&req.architectures);
+        let (flags, flags_len) = vec_to_c_char_ptr_ptr(&req.flags);
+        let (syscalls, syscalls_len) = vec_to_double_ptr(&req.syscalls);
+        NriLinuxSeccomp {
+            default_action: to_c_char_ptr(req.default_action.as_str()),
+            default_errno: req.default_errno.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(x.value))),
+            architectures,
+            architectures_len,
+            flags,
+            flags_len,
+            listener_path: to_c_char_ptr(req.listener_path.as_str()),
+            listener_metadata: to_c_char_ptr(req.listener_metadata.as_str()),
+            syscalls,
+            syscalls_len,
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriLinuxSeccomp {
+    fn drop(&mut self) {
+        if !self.default_action.is_null() {
+            let _unused = unsafe { CString::from_raw(self.default_action as *mut c_char) };
+        }
+        if !self.default_errno.is_null() {
+            let _unused = unsafe { Box::from_raw(self.default_errno as *mut u32) };
+        }
+        free_strings(self.architectures, self.architectures_len);
+        free_strings(self.flags, self.flags_len);
+        if !self.listener_path.is_null() {
+            let _unused = unsafe { CString::from_raw(self.listener_path as *mut c_char) };
+        }
+        if !self.listener_metadata.is_null() {
+            let _unused = unsafe { CString::from_raw(self.listener_metadata as *mut c_char) };
+        }
+        free_array(self.syscalls, self.syscalls_len);
+    }
+}
+
+#[repr(C)]
+pub struct NriCdiDevice {
+    name: *const c_char,
+    residual: *const c_void,
+}
+
+impl From<&NriCdiDevice> for nri::CDIDevice {
+    fn from(req: &NriCdiDevice) -> Self {
+        let mut r_req = nri::CDIDevice::new();
+        r_req.name = to_string(req.name);
+        r_req
+    }
+}
+
+impl From<&nri::CDIDevice> for NriCdiDevice {
+    fn from(req: &nri::CDIDevice) -> Self {
+        NriCdiDevice {
+            name: to_c_char_ptr(req.name.as_str()),
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriCdiDevice {
+    fn drop(&mut self) {
+        if !self.name.is_null() {
+            let _unused = unsafe { CString::from_raw(self.name as *mut c_char) };
+        }
+    }
+}
+
 #[repr(C)]
 pub struct NriLinuxContainer {
     namespaces: *const *const NriLinuxNamespace,
@@ -676,6 +1055,9 @@ pub struct NriLinuxContainer {
     resources: *const NriLinuxResources,
     oom_score_adj: *const i64,
     cgroups_path: *const c_char,
+    io_priority: *const NriLinuxIoPriority,
+    seccomp_profile: *const NriSecurityProfile,
+    seccomp_policy: *const NriLinuxSeccomp,
     residual: *const c_void,
 }
 
@@ -693,6 +1075,15 @@ impl From<&NriLinuxContainer> for nri::LinuxContainer {
             r_req.oom_score_adj = MessageField::some(oom_score_adj);
         }
         r_req.cgroups_path = to_string(req.cgroups_path);
+        if !req.io_priority.is_null() {
+            r_req.io_priority = MessageField::some(nri::LinuxIOPriority::from(unsafe { req.io_priority.as_ref() }.unwrap()));
+        }
+        if !req.seccomp_profile.is_null() {
+            r_req.seccomp_profile = MessageField::some(nri::SecurityProfile::from(unsafe { req.seccomp_profile.as_ref() }.unwrap()));
+        }
+        if !req.seccomp_policy.is_null() {
+            r_req.seccomp_policy = MessageField::some(nri::LinuxSeccomp::from(unsafe { req.seccomp_policy.as_ref() }.unwrap()));
+        }
         r_req
     }
 }
@@ -716,6 +1107,14 @@ pub struct NriContainer {
     pid: u32,
     rlimits: *const *const NriPosixRlimit,
     rlimits_len: usize,
+    created_at: i64,
+    started_at: i64,
+    finished_at: i64,
+    exit_code: i32,
+    status_reason: *const c_char,
+    status_message: *const c_char,
+    cdi_devices: *const *const NriCdiDevice,
+    cdi_devices_len: usize,
     residual: *const c_void,
 }
 
@@ -745,6 +1144,13 @@ impl From<&NriContainer> for nri::Container {
         }
         r_req.pid = req.pid;
         r_req.rlimits = double_ptr_to_vec(req.rlimits, req.rlimits_len);
+        r_req.created_at = req.created_at;
+        r_req.started_at = req.started_at;
+        r_req.finished_at = req.finished_at;
+        r_req.exit_code = req.exit_code;
+        r_req.status_reason = to_string(req.status_reason);
+        r_req.status_message = to_string(req.status_message);
+        r_req.CDI_devices = double_ptr_to_vec(req.cdi_devices, req.cdi_devices_len);
         r_req
     }
 }
@@ -832,6 +1238,15 @@ pub struct NriKeyValue {
     residual: *const c_void,
 }
 
+impl From<&NriKeyValue> for nri::KeyValue {
+    fn from(req: &NriKeyValue) -> Self {
+        let mut r_req = nri::KeyValue::new();
+        r_req.key = to_string(req.key);
+        r_req.value = to_string(req.value);
+        r_req
+    }
+}
+
 impl From<&nri::KeyValue> for NriKeyValue {
     fn from(req: &nri::KeyValue) -> Self {
         let r_req = NriKeyValue {
@@ -843,6 +1258,13 @@ impl From<&nri::KeyValue> for NriKeyValue {
     }
 }
 
+impl Drop for NriKeyValue {
+    fn drop(&mut self) {
+        free_string(self.key);
+        free_string(self.value);
+    }
+}
+
 #[repr(C)]
 pub struct NriLinuxDevice {
     path: *const c_char,
@@ -897,29 +1319,86 @@ impl From<&nri::LinuxDevice> for NriLinuxDevice {
     }
 }
 
+impl Drop for NriLinuxDevice {
+    fn drop(&mut self) {
+        free_string(self.path);
+        free_string(self.type_);
+        free_box(self.file_mode);
+        free_box(self.uid);
+        free_box(self.gid);
+    }
+}
+
 #[repr(C)]
 pub struct NriLinuxContainerAdjustment {
     devices: *const *const NriLinuxDevice,
     devices_len: usize,
     resources: *const NriLinuxResources,
     cgroups_path: *const c_char,
+    oom_score_adj: *const i64,
+    io_priority: *const NriLinuxIoPriority,
+    seccomp_policy: *const NriLinuxSeccomp,
+    namespaces: *const *const NriLinuxNamespace,
+    namespaces_len: usize,
     residual: *const c_void,
 }
 
+impl From<&NriLinuxContainerAdjustment> for nri::LinuxContainerAdjustment {
+    fn from(req: &NriLinuxContainerAdjustment) -> Self {
+        let mut r_req = nri::LinuxContainerAdjustment::new();
+        r_req.devices = double_ptr_to_vec(req.devices, req.devices_len);
+        if !req.resources.is_null() {
+            r_req.resources = MessageField::some(nri::LinuxResources::from(unsafe { req.resources.as_ref() }.unwrap()));
+        }
+        r_req.cgroups_path = to_string(req.cgroups_path);
+        if !req.oom_score_adj.is_null() {
+            let mut oom_score_adj = OptionalInt::new();
+            oom_score_adj.value = unsafe { *req.oom_score_adj };
+            r_req.oom_score_adj = MessageField::some(oom_score_adj);
+        }
+        if !req.io_priority.is_null() {
+            r_req.io_priority = MessageField::some(nri::LinuxIOPriority::from(unsafe { req.io_priority.as_ref() }.unwrap()));
+        }
+        if !req.seccomp_policy.is_null() {
+            r_req.seccomp_policy = MessageField::some(nri::LinuxSeccomp::from(unsafe { req.seccomp_policy.as_ref() }.unwrap()));
+        }
+        r_req.namespaces = double_ptr_to_vec(req.namespaces, req.namespaces_len);
+        r_req
+    }
+}
+
 impl From<&nri::LinuxContainerAdjustment> for NriLinuxContainerAdjustment {
     fn from(req: &nri::LinuxContainerAdjustment) -> Self {
         let (devices, devices_len) = vec_to_double_ptr(&req.devices);
+        let (namespaces, namespaces_len) = vec_to_double_ptr(&req.namespaces);
         let r_req = NriLinuxContainerAdjustment {
-            devices: devices,
-            devices_len: devices_len,
+            devices,
+            devices_len,
             resources: req.resources.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(NriLinuxResources::from(x)))),
             cgroups_path: to_c_char_ptr(req.cgroups_path.as_str()),
+            oom_score_adj: req.oom_score_adj.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(x.value))),
+            io_priority: req.io_priority.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(NriLinuxIoPriority::from(x)))),
+            seccomp_policy: req.seccomp_policy.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(NriLinuxSeccomp::from(x)))),
+            namespaces,
+            namespaces_len,
             residual: std::ptr::null(),
         };
         r_req
     }
 }
 
+impl Drop for NriLinuxContainerAdjustment {
+    fn drop(&mut self) {
+        free_array(self.devices, self.devices_len);
+        free_box(self.resources);
+        free_string(self.cgroups_path);
+        free_box(self.oom_score_adj);
+        free_box(self.io_priority);
+        free_box(self.seccomp_policy);
+        free_array(self.namespaces, self.namespaces_len);
+    }
+}
+
 #[repr(C)]
 pub struct NriContainerAdjustment {
     annotations: *const MapStringString,
@@ -931,30 +1410,75 @@ pub struct NriContainerAdjustment {
     linux: *const NriLinuxContainerAdjustment,
     rlimits: *const *const NriPosixRlimit,
     rlimits_len: usize,
+    cdi_devices: *const *const NriCdiDevice,
+    cdi_devices_len: usize,
+    args: *const *const c_char,
+    args_len: usize,
     residual: *const c_void,
 }
 
+impl From<&NriContainerAdjustment> for nri::ContainerAdjustment {
+    fn from(req: &NriContainerAdjustment) -> Self {
+        let mut r_req = nri::ContainerAdjustment::new();
+        r_req.annotations = match req.annotations.is_null() {
+            true => std::collections::HashMap::new(),
+            false => unsafe { <std::collections::HashMap<String, String>>::from(&*req.annotations) },
+        };
+        r_req.mounts = double_ptr_to_vec(req.mounts, req.mounts_len);
+        r_req.env = double_ptr_to_vec(req.env, req.env_len);
+        if !req.hooks.is_null() {
+            r_req.hooks = MessageField::some(nri::Hooks::from(unsafe { req.hooks.as_ref() }.unwrap()));
+        }
+        if !req.linux.is_null() {
+            r_req.linux = MessageField::some(nri::LinuxContainerAdjustment::from(unsafe { req.linux.as_ref() }.unwrap()));
+        }
+        r_req.rlimits = double_ptr_to_vec(req.rlimits, req.rlimits_len);
+        r_req.CDI_devices = double_ptr_to_vec(req.cdi_devices, req.cdi_devices_len);
+        r_req.args = c_char_ptr_ptr_to_vec(req.args, req.args_len);
+        r_req
+    }
+}
+
 impl From<&nri::ContainerAdjustment> for NriContainerAdjustment {
     fn from(req: &nri::ContainerAdjustment) -> Self {
         let (mounts, mounts_len) = vec_to_double_ptr(&req.mounts);
         let (env, env_len) = vec_to_double_ptr(&req.env);
         let (rlimits, rlimits_len) = vec_to_double_ptr(&req.rlimits);
+        let (cdi_devices, cdi_devices_len) = vec_to_double_ptr(&req.CDI_devices);
+        let (args, args_len) = vec_to_c_char_ptr_ptr(&req.args);
         let r_req = NriContainerAdjustment {
             annotations: Box::into_raw(Box::new(MapStringString::from(&req.annotations))),
-            mounts: mounts,
-            mounts_len: mounts_len,
-            env: env,
-            env_len: env_len,
+            mounts,
+            mounts_len,
+            env,
+            env_len,
             hooks: req.hooks.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(NriHooks::from(x)))),
             linux: req.linux.as_ref().map_or(std::ptr::null(), |x| Box::into_raw(Box::new(NriLinuxContainerAdjustment::from(x)))),
-            rlimits: rlimits,
-            rlimits_len: rlimits_len,
+            rlimits,
+            rlimits_len,
+            cdi_devices,
+            cdi_devices_len,
+            args,
+            args_len,
             residual: std::ptr::null(),
         };
         r_req
     }
 }
 
+impl Drop for NriContainerAdjustment {
+    fn drop(&mut self) {
+        free_box(self.annotations);
+        free_array(self.mounts, self.mounts_len);
+        free_array(self.env, self.env_len);
+        free_box(self.hooks);
+        free_box(self.linux);
+        free_array(self.rlimits, self.rlimits_len);
+        free_array(self.cdi_devices, self.cdi_devices_len);
+        free_strings(self.args, self.args_len);
+    }
+}
+
 #[repr(C)]
 pub struct NriContainerEviction {
     container_id: *const c_char,
@@ -1029,8 +1553,8 @@ impl From<&nri::UpdateContainersRequest> for NriUpdateContainersRequest {
         let r_req = NriUpdateContainersRequest {
             container_updates: update,
             container_updates_len: update_len,
-            evict: evict,
-            evict_len: evict_len,
+            evict,
+            evict_len,
             residual: std::ptr::null(),
         };
         r_req
@@ -1039,27 +1563,13 @@ impl From<&nri::UpdateContainersRequest> for NriUpdateContainersRequest {
 
 impl Drop for NriUpdateContainersRequest {
     fn drop(&mut self) {
-        if !self.container_updates.is_null() {
-            let update = unsafe { std::slice::from_raw_parts(self.container_updates, self.container_updates_len) };
-            for i in 0..self.container_updates_len {
-                if !update[i].is_null() {
-                    let _unused = unsafe { Box::from_raw(update[i] as *mut NriContainerUpdate) };
-                }
-            }
-            let _unused = unsafe { Box::from_raw(self.container_updates as *mut *const NriContainerUpdate) };
-        }
-        if !self.evict.is_null() {
-            let evict = unsafe { std::slice::from_raw_parts(self.evict, self.evict_len) };
-            for i in 0..self.evict_len {
-                if !evict[i].is_null() {
-                    let _unused = unsafe { Box::from_raw(evict[i] as *mut NriContainerEviction) };
-                }
-            }
-            let _unused = unsafe { Box::from_raw(self.evict as *mut *const NriContainerEviction) };
-        }
+        free_array(self.container_updates, self.container_updates_len);
+        free_array(self.evict, self.evict_len);
     }
 }
 
+// Allocated by the runtime with the isula_libutils allocators, the response
+// is only read here and handed back to the runtime to free.
 #[repr(C)]
 pub struct NriUpdateContainersResponse {
     failed: *const *const NriContainerUpdate,
@@ -1075,25 +1585,13 @@ impl From<&NriUpdateContainersResponse> for nri::UpdateContainersResponse {
     }
 }
 
-impl Drop for NriUpdateContainersResponse {
-    fn drop(&mut self) {
-        if !self.failed.is_null() {
-            let failed = unsafe { std::slice::from_raw_parts(self.failed, self.failed_len) };
-            for i in 0..self.failed_len {
-                if !failed[i].is_null() {
-                    let _unused = unsafe { Box::from_raw(failed[i] as *mut NriContainerUpdate) };
-                }
-            }
-            let _unused = unsafe { Box::from_raw(self.failed as *mut *const NriContainerUpdate) };
-        }
-    }
-}
-
 #[repr(C)]
 pub struct NriConfigureRequest {
     config: *const c_char,
     runtime_name: *const c_char,
     runtime_version: *const c_char,
+    registration_timeout: i64,
+    request_timeout: i64,
     residual: *const c_void,
 }
 
@@ -1103,6 +1601,8 @@ impl From<&NriConfigureRequest> for nri::ConfigureRequest {
         r_resp.config = to_string(req.config);
         r_resp.runtime_name = to_string(req.runtime_name);
         r_resp.runtime_version = to_string(req.runtime_version);
+        r_resp.registration_timeout = req.registration_timeout;
+        r_resp.request_timeout = req.request_timeout;
         r_resp
     }
 }
@@ -1129,6 +1629,7 @@ pub struct NriSynchronizeRequest {
     pods_len: usize,
     containers: *const *const NriContainer,
     containers_len: usize,
+    more: u8,
     residual: *const c_void,
 }
 
@@ -1137,6 +1638,7 @@ impl From<&NriSynchronizeRequest> for nri::SynchronizeRequest {
         let mut r_req = nri::SynchronizeRequest::new();
         r_req.pods = double_ptr_to_vec(req.pods, req.pods_len);
         r_req.containers = double_ptr_to_vec(req.containers, req.containers_len);
+        r_req.more = req.more != 0;
         r_req
     }
 }
@@ -1145,6 +1647,7 @@ impl From<&NriSynchronizeRequest> for nri::SynchronizeRequest {
 pub struct NriSynchronizeResponse {
     update: *const *const NriContainerUpdate,
     update_len: usize,
+    more: u8,
     residual: *const c_void,
 }
 
@@ -1152,14 +1655,21 @@ impl From<&nri::SynchronizeResponse> for NriSynchronizeResponse {
     fn from(resp: &nri::SynchronizeResponse) -> Self {
         let (update, update_len) = vec_to_double_ptr(&resp.update);
         let r_resp = NriSynchronizeResponse {
-            update: update,
-            update_len: update_len,
+            update,
+            update_len,
+            more: resp.more as u8,
             residual: std::ptr::null(),
         };
         r_resp
     }
 }
 
+impl Drop for NriSynchronizeResponse {
+    fn drop(&mut self) {
+        free_array(self.update, self.update_len);
+    }
+}
+
 #[repr(C)]
 pub struct NriCreateContainerRequest {
     pod: *const NriPodSandbox,
@@ -1195,11 +1705,11 @@ impl From<&nri::CreateContainerResponse> for NriCreateContainerResponse {
         let (update, update_len) = vec_to_double_ptr(&resp.update);
         let (evict, evict_len) = vec_to_double_ptr(&resp.evict);
         let r_resp = NriCreateContainerResponse {
-            adjust: Box::into_raw(Box::new(NriContainerAdjustment::from(resp.adjust.as_ref().unwrap()))),
-            update: update,
-            update_len: update_len,
-            evict: evict,
-            evict_len: evict_len,
+            adjust: Box::into_raw(Box::new(NriContainerAdjustment::from(resp.adjust.get_or_default()))),
+            update,
+            update_len,
+            evict,
+            evict_len,
             residual: std::ptr::null(),
         };
         r_resp
@@ -1207,6 +1717,14 @@ impl From<&nri::CreateContainerResponse> for NriCreateContainerResponse {
     }
 }
 
+impl Drop for NriCreateContainerResponse {
+    fn drop(&mut self) {
+        free_box(self.adjust);
+        free_array(self.update, self.update_len);
+        free_array(self.evict, self.evict_len);
+    }
+}
+
 #[repr(C)]
 pub struct NriUpdateContainerRequest {
     pod: *const NriPodSandbox,
@@ -1245,16 +1763,23 @@ impl From<&nri::UpdateContainerResponse> for NriUpdateContainerResponse {
         let (update, update_len) = vec_to_double_ptr(&resp.update);
         let (evict, evict_len) = vec_to_double_ptr(&resp.evict);
         let r_resp = NriUpdateContainerResponse {
-            update: update,
-            update_len: update_len,
-            evict: evict,
-            evict_len: evict_len,
+            update,
+            update_len,
+            evict,
+            evict_len,
             residual: std::ptr::null(),
         };
         r_resp
     }
 }
 
+impl Drop for NriUpdateContainerResponse {
+    fn drop(&mut self) {
+        free_array(self.update, self.update_len);
+        free_array(self.evict, self.evict_len);
+    }
+}
+
 #[repr(C)]
 pub struct NriStopContainerRequest {
     pod: *const NriPodSandbox,
@@ -1286,14 +1811,20 @@ impl From<&nri::StopContainerResponse> for NriStopContainerResponse {
     fn from(resp: &nri::StopContainerResponse) -> Self {
         let (update, update_len) = vec_to_double_ptr(&resp.update);
         let r_resp = NriStopContainerResponse {
-            update: update,
-            update_len: update_len,
+            update,
+            update_len,
             residual: std::ptr::null(),
         };
         r_resp
     }
 }
 
+impl Drop for NriStopContainerResponse {
+    fn drop(&mut self) {
+        free_array(self.update, self.update_len);
+    }
+}
+
 #[repr(C)]
 pub struct NriStateChangeEvent {
     event: i32,
@@ -1316,14 +1847,290 @@ impl From<&NriStateChangeEvent> for nri::StateChangeEvent {
     }
 }
 
+#[repr(C)]
+pub struct NriUpdatePodSandboxRequest {
+    pod: *const NriPodSandbox,
+    overhead_linux_resources: *const NriLinuxResources,
+    linux_resources: *const NriLinuxResources,
+    residual: *const c_void,
+}
+
+impl From<&NriUpdatePodSandboxRequest> for nri::UpdatePodSandboxRequest {
+    fn from(req: &NriUpdatePodSandboxRequest) -> Self {
+        let mut r_req = nri::UpdatePodSandboxRequest::new();
+        if !req.pod.is_null() {
+            r_req.pod = MessageField::some(nri::PodSandbox::from(unsafe { req.pod.as_ref() }.unwrap()));
+        }
+        if !req.overhead_linux_resources.is_null() {
+            r_req.overhead_linux_resources = MessageField::some(nri::LinuxResources::from(unsafe { req.overhead_linux_resources.as_ref() }.unwrap()));
+        }
+        if !req.linux_resources.is_null() {
+            r_req.linux_resources = MessageField::some(nri::LinuxResources::from(unsafe { req.linux_resources.as_ref() }.unwrap()));
+        }
+        r_req
+    }
+}
+
+#[repr(C)]
+pub struct NriUpdatePodSandboxResponse {
+    residual: *const c_void,
+}
+
+impl From<&nri::UpdatePodSandboxResponse> for NriUpdatePodSandboxResponse {
+    fn from(_resp: &nri::UpdatePodSandboxResponse) -> Self {
+        NriUpdatePodSandboxResponse {
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+#[repr(C)]
+pub struct NriPluginInstance {
+    name: *const c_char,
+    index: *const c_char,
+    residual: *const c_void,
+}
+
+impl From<&NriPluginInstance> for nri::PluginInstance {
+    fn from(req: &NriPluginInstance) -> Self {
+        let mut r_req = nri::PluginInstance::new();
+        r_req.name = to_string(req.name);
+        r_req.index = to_string(req.index);
+        r_req
+    }
+}
+
+// The owners of the adjusted fields are not passed in from C, they are
+// tracked on the rust side for the plugins consulted through this library.
+#[repr(C)]
+pub struct NriValidateContainerAdjustmentRequest {
+    pod: *const NriPodSandbox,
+    container: *const NriContainer,
+    adjust: *const NriContainerAdjustment,
+    update: *const *const NriContainerUpdate,
+    update_len: usize,
+    plugins: *const *const NriPluginInstance,
+    plugins_len: usize,
+    residual: *const c_void,
+}
+
+impl From<&NriValidateContainerAdjustmentRequest> for nri::ValidateContainerAdjustmentRequest {
+    fn from(req: &NriValidateContainerAdjustmentRequest) -> Self {
+        let mut r_req = nri::ValidateContainerAdjustmentRequest::new();
+        if !req.pod.is_null() {
+            r_req.pod = MessageField::some(nri::PodSandbox::from(unsafe { req.pod.as_ref() }.unwrap()));
+        }
+        if !req.container.is_null() {
+            r_req.container = MessageField::some(nri::Container::from(unsafe { req.container.as_ref() }.unwrap()));
+        }
+        if !req.adjust.is_null() {
+            r_req.adjust = MessageField::some(nri::ContainerAdjustment::from(unsafe { req.adjust.as_ref() }.unwrap()));
+        }
+        r_req.update = double_ptr_to_vec(req.update, req.update_len);
+        r_req.plugins = double_ptr_to_vec(req.plugins, req.plugins_len);
+        r_req
+    }
+}
+
+#[repr(C)]
+pub struct NriValidateContainerAdjustmentResponse {
+    reject: u8,
+    reason: *const c_char,
+    residual: *const c_void,
+}
+
+impl From<&nri::ValidateContainerAdjustmentResponse> for NriValidateContainerAdjustmentResponse {
+    fn from(resp: &nri::ValidateContainerAdjustmentResponse) -> Self {
+        NriValidateContainerAdjustmentResponse {
+            reject: resp.reject as u8,
+            reason: to_c_char_ptr(resp.reason.as_str()),
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriValidateContainerAdjustmentResponse {
+    fn drop(&mut self) {
+        if !self.reason.is_null() {
+            let _unused = unsafe { CString::from_raw(self.reason as *mut c_char) };
+        }
+    }
+}
+
+pub const NRI_PLUGIN_RESULT_OK: c_int = 0;
+pub const NRI_PLUGIN_RESULT_FAILED: c_int = 1;
+pub const NRI_PLUGIN_RESULT_TIMED_OUT: c_int = 2;
+
+#[repr(C)]
+pub struct NriPluginResult {
+    plugin_id: *const c_char,
+    plugin_name: *const c_char,
+    plugin_idx: *const c_char,
+    result: c_int,
+    error: *const c_char,
+    residual: *const c_void,
+}
+
+impl From<&adaptation::PluginResult> for NriPluginResult {
+    fn from(res: &adaptation::PluginResult) -> Self {
+        let (result, error) = match &res.outcome {
+            adaptation::Outcome::Ok => (NRI_PLUGIN_RESULT_OK, std::ptr::null()),
+            adaptation::Outcome::Failed(e) => (NRI_PLUGIN_RESULT_FAILED, to_c_char_ptr(e.as_str())),
+            adaptation::Outcome::TimedOut => (NRI_PLUGIN_RESULT_TIMED_OUT, std::ptr::null()),
+        };
+        NriPluginResult {
+            plugin_id: to_c_char_ptr(res.plugin.id.as_str()),
+            plugin_name: to_c_char_ptr(res.plugin.name.as_str()),
+            plugin_idx: to_c_char_ptr(res.plugin.idx.as_str()),
+            result,
+            error,
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriPluginResult {
+    fn drop(&mut self) {
+        for s in [self.plugin_id, self.plugin_name, self.plugin_idx, self.error] {
+            if !s.is_null() {
+                let _unused = unsafe { CString::from_raw(s as *mut c_char) };
+            }
+        }
+    }
+}
+
+#[repr(C)]
+pub struct NriBroadcastResponse {
+    results: *const *const NriPluginResult,
+    results_len: usize,
+    residual: *const c_void,
+}
+
+impl From<&Vec<adaptation::PluginResult>> for NriBroadcastResponse {
+    fn from(results: &Vec<adaptation::PluginResult>) -> Self {
+        let (results, results_len) = vec_to_double_ptr(results);
+        NriBroadcastResponse {
+            results,
+            results_len,
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriBroadcastResponse {
+    fn drop(&mut self) {
+        free_array(self.results, self.results_len);
+    }
+}
+
+#[repr(C)]
+pub struct NriPluginStatus {
+    plugin_id: *const c_char,
+    plugin_name: *const c_char,
+    plugin_idx: *const c_char,
+    state: c_int,
+    events: i32,
+    timeouts: plugin::RpcTimeouts,
+    mux_closed: u8,
+    // Nanoseconds since the epoch, like the container timestamps.
+    connected_at: i64,
+    residual: *const c_void,
+}
+
+impl From<&plugin::PluginStatus> for NriPluginStatus {
+    fn from(status: &plugin::PluginStatus) -> Self {
+        let connected_at = status.connected_at.duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as i64);
+        NriPluginStatus {
+            plugin_id: to_c_char_ptr(status.id.as_str()),
+            plugin_name: to_c_char_ptr(status.name.as_str()),
+            plugin_idx: to_c_char_ptr(status.idx.as_str()),
+            state: status.state as c_int,
+            events: status.events,
+            timeouts: status.timeouts,
+            mux_closed: status.mux_closed as u8,
+            connected_at,
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriPluginStatus {
+    fn drop(&mut self) {
+        for s in [self.plugin_id, self.plugin_name, self.plugin_idx] {
+            if !s.is_null() {
+                let _unused = unsafe { CString::from_raw(s as *mut c_char) };
+            }
+        }
+    }
+}
+
+#[repr(C)]
+pub struct NriPluginStatusList {
+    plugins: *const *const NriPluginStatus,
+    plugins_len: usize,
+    residual: *const c_void,
+}
+
+impl From<&Vec<plugin::PluginStatus>> for NriPluginStatusList {
+    fn from(statuses: &Vec<plugin::PluginStatus>) -> Self {
+        let (plugins, plugins_len) = vec_to_double_ptr(statuses);
+        NriPluginStatusList {
+            plugins,
+            plugins_len,
+            residual: std::ptr::null(),
+        }
+    }
+}
+
+impl Drop for NriPluginStatusList {
+    fn drop(&mut self) {
+        free_array(self.plugins, self.plugins_len);
+    }
+}
+
 pub type NriRuntimeRegisterCallback = extern "C" fn(*const c_char, *const NriRegisterPluginRequest) -> c_int;
 pub type NriRuntimeUpdateContainersCallback = extern "C" fn(*const c_char, *const NriUpdateContainersRequest, *mut *mut NriUpdateContainersResponse) -> c_int;
+pub type NriRuntimePluginClosedCallback = extern "C" fn(*const c_char, *const c_char);
+pub type NriRuntimeFreeUpdateContainersResponseCallback = extern "C" fn(*mut NriUpdateContainersResponse);
 
 #[derive(Clone)]
 #[repr(C)]
 pub struct NriRuntimeCallbacks {
     pub register_plugin: Option<NriRuntimeRegisterCallback>,
     pub update_containers: Option<NriRuntimeUpdateContainersCallback>,
+    pub plugin_closed: Option<NriRuntimePluginClosedCallback>,
+    pub free_update_containers_response: Option<NriRuntimeFreeUpdateContainersResponseCallback>,
+}
+
+// Called with the fd of an external plugin connection and the pid and uid of
+// the peer.
+pub type NriExternalConnectCallback = extern "C" fn(c_int, libc::pid_t, libc::uid_t) -> c_int;
+
+#[repr(C)]
+pub struct NriExternalAllowList {
+    pub uids: *const libc::uid_t,
+    pub uids_len: usize,
+    pub gids: *const libc::gid_t,
+    pub gids_len: usize,
+    pub exe_paths: *const *const c_char,
+    pub exe_paths_len: usize,
+}
+
+fn slice_to_vec<T: Copy>(ptr: *const T, len: usize) -> Vec<T> {
+    if ptr.is_null() || len == 0 {
+        return Vec::new();
+    }
+    unsafe { std::slice::from_raw_parts(ptr, len) }.to_vec()
 }
 
-pub type NriExternalConnectCallback = extern "C" fn(c_int) -> c_int;
+impl From<&NriExternalAllowList> for auth::AllowList {
+    fn from(allow_list: &NriExternalAllowList) -> Self {
+        auth::AllowList {
+            uids: slice_to_vec(allow_list.uids, allow_list.uids_len),
+            gids: slice_to_vec(allow_list.gids, allow_list.gids_len),
+            exe_paths: c_char_ptr_ptr_to_vec(allow_list.exe_paths, allow_list.exe_paths_len)
+                .into_iter().map(std::path::PathBuf::from).collect(),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// config resolves the configuration of a plugin from the drop-in config
+// directory, like upstream NRI: <idx>-<name>.conf if present, else
+// <name>.conf, else an empty configuration. The configurations read are
+// cached until they are reloaded.
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::nri::error::{Error, Result};
+
+struct Configs {
+    dir: Option<PathBuf>,
+    // Read from dir, keyed by idx-name.
+    configs: HashMap<String, String>,
+}
+
+lazy_static! {
+    // The directory and the configurations read from it change together, so
+    // that no configuration of a previous directory is cached for the new one.
+    static ref CONFIGS: RwLock<Configs> = RwLock::new(Configs { dir: None, configs: HashMap::new() });
+}
+
+// set_config_dir sets the drop-in config directory, an empty one disables
+// the lookup, and drops the configurations read from the previous one.
+pub fn set_config_dir(dir: &str) {
+    let mut configs = CONFIGS.write().unwrap();
+    configs.dir = if dir.is_empty() { None } else { Some(PathBuf::from(dir)) };
+    configs.configs.clear();
+}
+
+// plugin_config returns the configuration of the plugin idx-name.
+pub fn plugin_config(idx: &str, name: &str) -> Result<String> {
+    let key = format!("{}-{}", idx, name);
+    if let Some(config) = CONFIGS.read().unwrap().configs.get(&key) {
+        return Ok(config.clone());
+    }
+    let mut configs = CONFIGS.write().unwrap();
+    if let Some(config) = configs.configs.get(&key) {
+        return Ok(config.clone());
+    }
+    let config = read_config(configs.dir.as_ref(), idx, name)?;
+    configs.configs.insert(key, config.clone());
+    Ok(config)
+}
+
+// reload rereads the configuration of every plugin looked up so far, so
+// that plugins configured afterwards get the current one.
+pub fn reload() -> Result<()> {
+    let mut configs = CONFIGS.write().unwrap();
+    let mut reloaded = HashMap::new();
+    for key in configs.configs.keys() {
+        let (idx, name) = key.split_once('-').unwrap();
+        reloaded.insert(key.clone(), read_config(configs.dir.as_ref(), idx, name)?);
+    }
+    configs.configs = reloaded;
+    Ok(())
+}
+
+fn read_config(dir: Option<&PathBuf>, idx: &str, name: &str) -> Result<String> {
+    let dir = match dir {
+        Some(dir) => dir,
+        None => return Ok(String::new()),
+    };
+    for file in [format!("{}-{}.conf", idx, name), format!("{}.conf", name)] {
+        let path = dir.join(file);
+        match fs::read_to_string(&path) {
+            Ok(config) => return Ok(config),
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(Error::IOError(format!("read {} error: {}", path.display(), e))),
+        }
+    }
+    Ok(String::new())
+}
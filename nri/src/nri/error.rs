@@ -20,6 +20,9 @@ pub enum Error {
     TtrpcError(String),
     Other(String),
     IOError(String),
+    Conflict(String),
+    InvalidState(String),
+    Timeout(String),
 }
 
 impl fmt::Display for Error {
@@ -30,6 +33,9 @@ impl fmt::Display for Error {
             Self::WithoutInit(ref s) => write!(f, "connection has not been established: {}", s),
             Self::IOError(ref s) => write!(f, "io error: {}", s),
             Self::TtrpcError(ref s) => write!(f, "ttrpc error: {}", s),
+            Self::Conflict(ref s) => write!(f, "conflict: {}", s),
+            Self::InvalidState(ref s) => write!(f, "invalid state: {}", s),
+            Self::Timeout(ref s) => write!(f, "timeout: {}", s),
         }
     }
 }
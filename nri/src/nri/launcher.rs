@@ -0,0 +1,198 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// launcher starts the pre-installed plugins found in a plugin directory, the
+// way upstream NRI does: every executable named NN-name is started with one
+// end of a socket pair as fd 3 and
+//   NRI_PLUGIN_NAME=name
+//   NRI_PLUGIN_IDX=NN
+//   NRI_PLUGIN_SOCKET=3
+// in its environment, and the other end is connected as the plugin NN-name.
+// The children are reaped as they exit and killed on shutdown.
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fs;
+use std::os::fd::{AsRawFd, IntoRawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::nri::error::{Error, Result};
+use crate::nri::plugin;
+use crate::nri::stub::{PLUGIN_IDX_ENV, PLUGIN_NAME_ENV, PLUGIN_SOCKET_ENV};
+
+const PLUGIN_SOCKET_FD: i32 = 3;
+
+struct Launched {
+    pid: u32,
+    // Set by the reaper under the lock before it reaps the plugin.
+    exited: Arc<Mutex<bool>>,
+    reaper: JoinHandle<()>,
+}
+
+impl Launched {
+    // signal sends sig to the plugin unless it exited. The plugin is only
+    // reaped once marked exited, so its pid cannot be reused by another
+    // process while the lock is held.
+    fn signal(&self, sig: libc::c_int) {
+        let exited = self.exited.lock().unwrap();
+        if !*exited {
+            unsafe { libc::kill(self.pid as libc::pid_t, sig) };
+        }
+    }
+}
+
+// wait_exited waits for the process to exit, leaving it to be reaped.
+fn wait_exited(pid: u32) {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    while unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT) } < 0 {
+        if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+            break;
+        }
+    }
+}
+
+lazy_static! {
+    static ref LAUNCHED: Mutex<HashMap<String, Launched>> = Mutex::new(HashMap::new());
+}
+
+// parse_plugin_name splits NN-name into its index and name.
+pub fn parse_plugin_name(file_name: &str) -> Option<(&str, &str)> {
+    let (idx, name) = file_name.split_once('-')?;
+    if idx.len() != 2 || !idx.bytes().all(|b| b.is_ascii_digit()) || name.is_empty() {
+        return None;
+    }
+    Some((idx, name))
+}
+
+// launch_plugins starts every plugin of the directory in index order and
+// returns the ids of the plugins started. A plugin failing to start is
+// logged and skipped.
+pub fn launch_plugins(dir: &str, timeout: i64) -> Result<Vec<String>> {
+    let mut plugins: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.metadata().is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| parse_plugin_name(name).is_some())
+        .collect();
+    plugins.sort();
+
+    let mut launched = Vec::new();
+    for plugin_id in plugins {
+        match launch_plugin(&Path::new(dir).join(&plugin_id), &plugin_id, timeout) {
+            Ok(()) => launched.push(plugin_id),
+            Err(e) => println!("isula_rust_extensions::launch plugin {} failed: {}", plugin_id, e),
+        }
+    }
+    Ok(launched)
+}
+
+fn launch_plugin(path: &Path, plugin_id: &str, timeout: i64) -> Result<()> {
+    let (idx, name) = parse_plugin_name(plugin_id)
+        .ok_or_else(|| Error::InvalidArgument(format!("invalid plugin name {}", plugin_id)))?;
+
+    let mut launched = LAUNCHED.lock().unwrap();
+    if launched.get(plugin_id).is_some_and(|l| !l.reaper.is_finished()) {
+        return Err(Error::Other(format!("plugin {} already running", plugin_id)));
+    }
+
+    let (local, peer) = UnixStream::pair()
+        .map_err(|e| Error::IOError(format!("create socket pair error: {}", e)))?;
+    let peer_fd = peer.as_raw_fd();
+
+    let mut cmd = Command::new(path);
+    cmd.env(PLUGIN_NAME_ENV, name)
+        .env(PLUGIN_IDX_ENV, idx)
+        .env(PLUGIN_SOCKET_ENV, PLUGIN_SOCKET_FD.to_string());
+    if let Some(dir) = path.parent() {
+        cmd.current_dir(dir);
+    }
+    // Hand the peer end to the plugin as fd 3. dup2 clears close-on-exec on
+    // the new fd, unless it is the same fd, so clear it by hand then.
+    unsafe {
+        cmd.pre_exec(move || {
+            if peer_fd == PLUGIN_SOCKET_FD {
+                let flags = libc::fcntl(peer_fd, libc::F_GETFD);
+                if flags < 0 || libc::fcntl(peer_fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            } else if libc::dup2(peer_fd, PLUGIN_SOCKET_FD) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = cmd.spawn()
+        .map_err(|e| Error::IOError(format!("start {} error: {}", path.display(), e)))?;
+    drop(peer);
+
+    let pid = child.id();
+    println!("isula_rust_extensions::launched plugin {} with pid {}", plugin_id, pid);
+    let reaped_id = plugin_id.to_string();
+    let exited = Arc::new(Mutex::new(false));
+    let reaped = exited.clone();
+    let reaper = thread::spawn(move || {
+        wait_exited(pid);
+        *reaped.lock().unwrap() = true;
+        match child.wait() {
+            Ok(status) => println!("isula_rust_extensions::plugin {} (pid {}) exited: {}", reaped_id, pid, status),
+            Err(e) => println!("isula_rust_extensions::wait plugin {} (pid {}) error: {}", reaped_id, pid, e),
+        }
+    });
+    launched.insert(plugin_id.to_string(), Launched { pid, exited, reaper });
+    drop(launched);
+
+    // A plugin failing to connect is left to exit on its own when its end of
+    // the socket pair is closed.
+    plugin::connect(&plugin_id.to_string(), local.into_raw_fd(), plugin::RpcTimeouts::uniform(timeout))
+}
+
+// stop_launched_plugins disconnects and terminates the launched plugins,
+// killing the ones still running once the grace period is over, and reaps
+// all of them.
+pub fn stop_launched_plugins(grace: Duration) {
+    let launched: Vec<(String, Launched)> = LAUNCHED.lock().unwrap().drain().collect();
+    for (plugin_id, l) in &launched {
+        let _ = plugin::disconnect(plugin_id);
+        l.signal(libc::SIGTERM);
+    }
+
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline && launched.iter().any(|(_, l)| !l.reaper.is_finished()) {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    for (plugin_id, l) in launched {
+        if !l.reaper.is_finished() {
+            println!("isula_rust_extensions::plugin {} did not exit in {:?}, killing it", plugin_id, grace);
+            l.signal(libc::SIGKILL);
+        }
+        let _ = l.reaper.join();
+    }
+}
+
+// launched_plugins returns the launched plugins still running.
+pub fn launched_plugins() -> Vec<(String, u32)> {
+    let launched = LAUNCHED.lock().unwrap();
+    let mut running: Vec<(String, u32)> = launched.iter()
+        .filter(|(_, l)| !l.reaper.is_finished())
+        .map(|(id, l)| (id.clone(), l.pid))
+        .collect();
+    running.sort();
+    running
+}
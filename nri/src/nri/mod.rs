@@ -15,3 +15,11 @@ pub mod plugin;
 pub mod mux;
 pub mod error;
 pub mod c_transfer;
+pub mod adaptation;
+pub mod owners;
+pub mod launcher;
+pub mod config;
+pub mod server;
+pub mod auth;
+pub mod stub;
+pub mod stats;
@@ -1,4 +1,3 @@
-
 // Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
 //
 // isula-rust-extensions is licensed under Mulan PSL v2.
@@ -11,18 +10,426 @@
 // NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
 // See the Mulan PSL v2 for more details.
 
+use lazy_static::lazy_static;
+use nix::errno::Errno;
+use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
 use std::collections::HashMap;
-use std::io::{Write, Read};
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
 use std::os::unix::net::UnixStream;
-use std::sync::{Mutex, Arc, Once};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once, Weak};
 use std::thread;
 
-
 use crate::nri::error::{Result, Error};
+use serde::Serialize;
 
 
 const RESERVED_CONN_ID: ConnId = 0;
-type ConnId = u32;
+// The trunk is registered with the reactor under the reserved conn id.
+const TRUNK_CONN_ID: ConnId = RESERVED_CONN_ID;
+pub type ConnId = u32;
+type MuxId = u32;
+
+// Conns of the plugin service, served by the plugin, and of the runtime
+// service, served by the runtime, as defined by upstream NRI.
+pub const PLUGIN_SERVICE_CONN: ConnId = 1;
+pub const RUNTIME_SERVICE_CONN: ConnId = 2;
+
+const HEADER_LEN: usize = 8;
+// Read buffer of the reactor, shared by all the muxes. Data read from a conn
+// is forwarded as frames of at most this size.
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+// Once this much data is pending for a socket, the mux stops reading the
+// data bound for it until it drains.
+const HIGH_WATER_MARK: usize = 1024 * 1024;
+const MAX_EVENTS: usize = 64;
+// Default limit of the data of a frame, twice the largest ttrpc message.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+static MAX_FRAME_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_FRAME_SIZE);
+
+// set_max_frame_size limits the data of the frames read from the trunks, a
+// mux receiving a larger frame is closed. Frames written to the trunks are
+// kept within the limit too.
+pub fn set_max_frame_size(size: usize) -> Result<()> {
+    if size == 0 {
+        return Err(Error::InvalidArgument("max frame size must not be 0".to_string()));
+    }
+    MAX_FRAME_SIZE.store(size, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn max_frame_size() -> usize {
+    MAX_FRAME_SIZE.load(Ordering::Relaxed)
+}
+
+// MuxStats counts the frames going through a mux. Dropped frames include
+// the frames for unknown conns and the frame rejected when closing the mux
+// on a bad header. Bytes are counted on the trunk, frame headers included.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MuxStats {
+    pub frames_received: u64,
+    pub frames_sent: u64,
+    pub dropped_frames: u64,
+    pub unknown_conn_frames: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FrameError {
+    // The frame header is malformed or oversize, the frame is dropped.
+    BadHeader(String),
+    // The frame could not be delivered.
+    Deliver(String),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::BadHeader(ref s) => write!(f, "{}", s),
+            Self::Deliver(ref s) => write!(f, "{}", s),
+        }
+    }
+}
+
+// FrameDecoder splits the data read from a trunk into frames. A header is
+// checked as soon as it is read, so that no more than one frame of data is
+// ever buffered.
+#[derive(Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> FrameDecoder {
+        FrameDecoder { buffer: Vec::with_capacity(READ_BUFFER_SIZE) }
+    }
+
+    // feed hands every whole frame to deliver, and keeps the data of the
+    // last one if incomplete. It fails on a malformed or oversize header, or
+    // on deliver failing, after which the decoder must not be fed again.
+    pub fn feed<F>(&mut self, data: &[u8], max_frame_size: usize, mut deliver: F) -> std::result::Result<(), FrameError>
+    where
+        F: FnMut(ConnId, &[u8]) -> std::result::Result<(), String>,
+    {
+        self.buffer.extend_from_slice(data);
+        let mut offset = 0;
+        let mut res = Ok(());
+        while self.buffer.len() - offset >= HEADER_LEN {
+            let hdr = &self.buffer[offset..offset + HEADER_LEN];
+            let cid = u32::from_be_bytes(hdr[0..4].try_into().unwrap());
+            let cnt = u32::from_be_bytes(hdr[4..8].try_into().unwrap()) as usize;
+            if cid == RESERVED_CONN_ID {
+                res = Err(FrameError::BadHeader(format!("malformed frame header: reserved conn id {}", cid)));
+                break;
+            }
+            if cnt > max_frame_size {
+                res = Err(FrameError::BadHeader(format!("frame of {} bytes for conn {} exceeds the max frame size {}",
+                    cnt, cid, max_frame_size)));
+                break;
+            }
+            if self.buffer.len() - offset - HEADER_LEN < cnt {
+                break;
+            }
+            let data = &self.buffer[offset + HEADER_LEN..offset + HEADER_LEN + cnt];
+            offset += HEADER_LEN + cnt;
+            if let Err(e) = deliver(cid, data) {
+                res = Err(FrameError::Deliver(e));
+                break;
+            }
+        }
+        self.buffer.drain(..offset);
+        res
+    }
+
+    // buffered returns the size of the data of the incomplete frame.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+// CloseHandler is called once the mux is closed, with the reason.
+pub type CloseHandler = Box<dyn FnOnce(&str) + Send>;
+
+// Reactor serves the trunks and connections of all the muxes from a single
+// thread. Each socket is registered with a token made of the mux id and the
+// conn id, and is only read from or written to by the reactor thread. If
+// waiting for events fails for another reason than a signal, the reactor
+// stops: all the muxes are closed and no new one is accepted.
+struct Reactor {
+    epoll: Epoll,
+    muxes: Mutex<HashMap<MuxId, Weak<Mux>>>,
+    // Why the reactor stopped, set under the lock of muxes.
+    stopped: Mutex<Option<String>>,
+}
+
+lazy_static! {
+    static ref REACTOR: Arc<Reactor> = Reactor::start();
+}
+
+static NEXT_MUX_ID: AtomicU32 = AtomicU32::new(1);
+
+fn token(mux: MuxId, conn: ConnId) -> u64 {
+    (mux as u64) << 32 | conn as u64
+}
+
+impl Reactor {
+    fn start() -> Arc<Reactor> {
+        let reactor = Arc::new(Reactor {
+            epoll: Epoll::new(EpollCreateFlags::EPOLL_CLOEXEC).expect("create epoll error"),
+            muxes: Mutex::new(HashMap::new()),
+            stopped: Mutex::new(None),
+        });
+        let r = reactor.clone();
+        thread::Builder::new()
+            .name("nri-mux".to_string())
+            .spawn(move || r.run())
+            .expect("start mux reactor error");
+        reactor
+    }
+
+    fn run(&self) {
+        let mut events = [EpollEvent::empty(); MAX_EVENTS];
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
+        loop {
+            let cnt = match self.epoll.wait(&mut events, EpollTimeout::NONE) {
+                Ok(cnt) => cnt,
+                Err(Errno::EINTR) => continue,
+                Err(e) => {
+                    self.stop(format!("epoll wait error: {}", e));
+                    return;
+                }
+            };
+            for event in &events[..cnt] {
+                let mux_id = (event.data() >> 32) as MuxId;
+                let conn_id = event.data() as ConnId;
+                let mux = self.muxes.lock().unwrap().get(&mux_id).and_then(|mux| mux.upgrade());
+                if let Some(mux) = mux {
+                    mux.handle(conn_id, event.events(), &mut buffer);
+                }
+            }
+        }
+    }
+
+    fn stop(&self, reason: String) {
+        let muxes: Vec<Arc<Mux>> = {
+            let muxes = self.muxes.lock().unwrap();
+            *self.stopped.lock().unwrap() = Some(reason.clone());
+            muxes.values().filter_map(|mux| mux.upgrade()).collect()
+        };
+        for mux in muxes {
+            mux.close_from_reactor(format!("mux reactor stopped: {}", reason));
+        }
+    }
+}
+
+// Socket is a non-blocking socket of a mux, with the data pending to be
+// written to it.
+struct Socket {
+    stream: UnixStream,
+    pending: Vec<u8>,
+    interest: EpollFlags,
+}
+
+impl Socket {
+    fn new(stream: UnixStream) -> Socket {
+        Socket {
+            stream,
+            pending: Vec::new(),
+            interest: EpollFlags::empty(),
+        }
+    }
+
+    // read returns None if there is nothing to read, Some(0) at EOF.
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<Option<usize>> {
+        loop {
+            match self.stream.read(buffer) {
+                Ok(cnt) => return Ok(Some(cnt)),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // flush writes as much of the pending data as the socket takes.
+    fn flush(&mut self) -> std::io::Result<()> {
+        let mut written = 0;
+        while written < self.pending.len() {
+            match self.stream.write(&self.pending[written..]) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(cnt) => written += cnt,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        self.pending.drain(..written);
+        Ok(())
+    }
+
+    fn set_interest(&mut self, token: u64, interest: EpollFlags) {
+        if self.interest != interest
+            && REACTOR.epoll.modify(&self.stream, &mut EpollEvent::new(interest, token)).is_ok() {
+            self.interest = interest;
+        }
+    }
+}
+
+fn is_readable(flags: EpollFlags) -> bool {
+    flags.intersects(EpollFlags::EPOLLIN | EpollFlags::EPOLLHUP | EpollFlags::EPOLLERR)
+}
+
+struct State {
+    started: bool,
+    trunk: Socket,
+    // Taken out of the reactor after hanging up while the connections lag
+    // behind, HUP and ERR being reported whatever the interest.
+    trunk_parked: bool,
+    decoder: FrameDecoder,
+    conns: HashMap<ConnId, Socket>,
+    stats: MuxStats,
+}
+
+impl State {
+    fn handle_trunk(&mut self, flags: EpollFlags, buffer: &mut [u8]) -> std::result::Result<(), String> {
+        if flags.contains(EpollFlags::EPOLLOUT) {
+            self.trunk.flush().map_err(|e| format!("trunk write error: {}", e))?;
+        }
+        let hangup = flags.intersects(EpollFlags::EPOLLHUP | EpollFlags::EPOLLERR);
+        if !self.started {
+            return if hangup { Err("trunk closed before start".to_string()) } else { Ok(()) };
+        }
+        if self.conns_lagging() {
+            // The rest is read once the connections caught up.
+            if hangup {
+                let _unused = REACTOR.epoll.delete(&self.trunk.stream);
+                self.trunk_parked = true;
+            }
+            return Ok(());
+        }
+        if !is_readable(flags) {
+            return Ok(());
+        }
+        match self.trunk.read(buffer) {
+            Ok(None) => Ok(()),
+            Ok(Some(0)) => Err("trunk closed".to_string()),
+            Ok(Some(cnt)) => {
+                self.stats.bytes_received += cnt as u64;
+                self.forward_frames(&buffer[..cnt])
+            },
+            Err(e) => Err(format!("trunk read error: {}", e)),
+        }
+    }
+
+    // forward_frames hands the data of the whole frames read from the trunk
+    // to their connections.
+    fn forward_frames(&mut self, data: &[u8]) -> std::result::Result<(), String> {
+        let conns = &mut self.conns;
+        let stats = &mut self.stats;
+        let res = self.decoder.feed(data, max_frame_size(), |cid, data| {
+            stats.frames_received += 1;
+            match conns.get_mut(&cid) {
+                Some(conn) => {
+                    conn.pending.extend_from_slice(data);
+                    conn.flush().map_err(|e| format!("conn {} write error: {}", cid, e))
+                },
+                None => {
+                    println!("isula_rust_extensions::mux: conn {} not found", cid);
+                    stats.unknown_conn_frames += 1;
+                    stats.dropped_frames += 1;
+                    Ok(())
+                },
+            }
+        });
+        if let Err(FrameError::BadHeader(_)) = res {
+            self.stats.dropped_frames += 1;
+        }
+        res.map_err(|e| e.to_string())
+    }
+
+    fn handle_conn(&mut self, id: ConnId, flags: EpollFlags, buffer: &mut [u8]) -> std::result::Result<(), String> {
+        let conn = match self.conns.get_mut(&id) {
+            Some(conn) => conn,
+            None => return Ok(()),
+        };
+        if flags.contains(EpollFlags::EPOLLOUT) {
+            conn.flush().map_err(|e| format!("conn {} write error: {}", id, e))?;
+        }
+        if !is_readable(flags) {
+            return Ok(());
+        }
+        let max = buffer.len().min(max_frame_size());
+        match conn.read(&mut buffer[..max]) {
+            Ok(None) => Ok(()),
+            Ok(Some(0)) => {
+                self.remove_conn(id);
+                Ok(())
+            },
+            Ok(Some(cnt)) => {
+                let pending = &mut self.trunk.pending;
+                pending.extend_from_slice(&id.to_be_bytes());
+                pending.extend_from_slice(&(cnt as u32).to_be_bytes());
+                pending.extend_from_slice(&buffer[..cnt]);
+                self.stats.frames_sent += 1;
+                self.stats.bytes_sent += (HEADER_LEN + cnt) as u64;
+                self.trunk.flush().map_err(|e| format!("trunk write error: {}", e))
+            },
+            Err(e) => {
+                println!("isula_rust_extensions::mux: conn {} read error: {}", id, e);
+                self.remove_conn(id);
+                Ok(())
+            },
+        }
+    }
+
+    fn remove_conn(&mut self, id: ConnId) {
+        if let Some(conn) = self.conns.remove(&id) {
+            let _unused = REACTOR.epoll.delete(&conn.stream);
+        }
+    }
+
+    fn conns_lagging(&self) -> bool {
+        self.conns.values().any(|conn| conn.pending.len() >= HIGH_WATER_MARK)
+    }
+
+    // update_interest makes the reactor wait for the sockets with pending
+    // data to be writable, and for the others to be readable unless the data
+    // read would go to a socket already lagging behind.
+    fn update_interest(&mut self, mux: MuxId) {
+        let conns_lagging = self.conns_lagging();
+        let trunk_lagging = self.trunk.pending.len() >= HIGH_WATER_MARK;
+
+        let mut interest = EpollFlags::empty();
+        if self.started && !conns_lagging {
+            interest |= EpollFlags::EPOLLIN;
+        }
+        if !self.trunk.pending.is_empty() {
+            interest |= EpollFlags::EPOLLOUT;
+        }
+        if !self.trunk_parked {
+            self.trunk.set_interest(token(mux, TRUNK_CONN_ID), interest);
+        } else if !conns_lagging
+            && REACTOR.epoll.add(&self.trunk.stream, EpollEvent::new(interest, token(mux, TRUNK_CONN_ID))).is_ok() {
+            self.trunk.interest = interest;
+            self.trunk_parked = false;
+        }
+
+        for (id, conn) in self.conns.iter_mut() {
+            let mut interest = EpollFlags::empty();
+            if !trunk_lagging {
+                interest |= EpollFlags::EPOLLIN;
+            }
+            if !conn.pending.is_empty() {
+                interest |= EpollFlags::EPOLLOUT;
+            }
+            conn.set_interest(token(mux, *id), interest);
+        }
+    }
+}
 
 // Mux is implemented as a simple multiplexer that forwards data between a trunk
 // connection and multiple connections. The trunk connection is used to receive
@@ -33,126 +440,169 @@ type ConnId = u32;
 //   - 4 bytes: data length
 //   - data
 // The mux forwards the data to the connection with the specified id.
+// All the muxes are served by one reactor thread, see Reactor.
 pub struct Mux {
-    trunk: UnixStream,
-    write_lock: Mutex<()>,
-    conns: Mutex<HashMap<ConnId, UnixStream>>,
+    id: MuxId,
+    state: Mutex<State>,
     close_once: Once,
+    close_handler: Mutex<Option<CloseHandler>>,
+    // Why the mux was closed by the reactor before a close handler was set.
+    unhandled_close: Mutex<Option<String>>,
 }
 
 impl Mux {
-    pub fn new(trunk: UnixStream) -> Mux {
-        Mux {
-            trunk: trunk,
-            write_lock: Mutex::new(()),
-            conns: Mutex::new(HashMap::new()),
+    // new registers the trunk with the reactor. Nothing is read from it until
+    // the mux is started.
+    pub fn new(trunk: UnixStream) -> Result<Arc<Mux>> {
+        trunk.set_nonblocking(true)?;
+        let mux = Arc::new(Mux {
+            id: NEXT_MUX_ID.fetch_add(1, Ordering::Relaxed),
+            state: Mutex::new(State {
+                started: false,
+                trunk: Socket::new(trunk),
+                trunk_parked: false,
+                decoder: FrameDecoder::new(),
+                conns: HashMap::new(),
+                stats: MuxStats::default(),
+            }),
             close_once: Once::new(),
+            close_handler: Mutex::new(None),
+            unhandled_close: Mutex::new(None),
+        });
+
+        {
+            let mut muxes = REACTOR.muxes.lock().unwrap();
+            if let Some(reason) = REACTOR.stopped.lock().unwrap().as_ref() {
+                return Err(Error::Other(format!("mux reactor stopped: {}", reason)));
+            }
+            muxes.insert(mux.id, Arc::downgrade(&mux));
         }
+        REACTOR.epoll.add(&mux.state.lock().unwrap().trunk.stream,
+            EpollEvent::new(EpollFlags::empty(), token(mux.id, TRUNK_CONN_ID)))
+            .map_err(|e| Error::IOError(format!("register trunk error: {}", e)))?;
+        Ok(mux)
     }
 
-    // add conn to mux with id, and start a new thread to read data from the conn
-    // and constantly write data with conn id and data length to the trunk
-    pub fn add_conn(self: Arc<Self>, id: ConnId, mut stream: UnixStream) -> Result<()> {
+    // add conn to mux with id, the data read from the conn is written with
+    // conn id and data length to the trunk
+    pub fn add_conn(&self, id: ConnId, stream: UnixStream) -> Result<()> {
         if id == RESERVED_CONN_ID {
             return Err(Error::InvalidArgument("conn id is reserved".to_string()));
         }
+        stream.set_nonblocking(true)?;
 
-        {
-            let mut conns = self.conns.lock()
-                .map_err(|e| Error::Other(format!("lock error: {}", e)))?;
-            if conns.contains_key(&id) {
-                return Err(Error::InvalidArgument("conn id already exists".to_string()));
-            }
-            conns.insert(id, stream.try_clone()?);
-        }
-
-        thread::spawn(move || {
-            let mut buffer = [0; 1024];
-            loop {
-                match stream.read(&mut buffer) {
-                    Ok(cnt) => {
-                        if cnt == 0 {
-                            // Connection closed
-                            break;
-                        }
-                        // println!("Conn {} Read {} bytes", id, cnt);
-                        let mut hdr = [0; 8];
-                        hdr[0..4].copy_from_slice(&id.to_be_bytes());
-                        hdr[4..8].copy_from_slice(&(cnt as u32).to_be_bytes());
-
-                        let _unused = self.write_lock.lock().unwrap();
-                        let mut trunk = self.trunk.try_clone().unwrap();
-                        if trunk.write_all(hdr.as_slice())
-                            .and_then(|_| trunk.write_all(&buffer[0..cnt])).is_err() {
-                            println!("isula_rust_extensions::conn_reader: trunk write error");
-                            break;
-                        }
-                    },
-                    Err(e) => {
-                        println!("isula_rust_extensions::conn_reader: conn {} Read error: {}", id, e);
-                        break;
-                    },
-                }
+        let mut state = self.state.lock()
+            .map_err(|e| Error::Other(format!("lock error: {}", e)))?;
+        if self.is_closed() {
+            return Err(Error::Other("mux is closed".to_string()));
+        }
+        if state.conns.contains_key(&id) {
+            return Err(Error::InvalidArgument("conn id already exists".to_string()));
+        }
+        REACTOR.epoll.add(&stream, EpollEvent::new(EpollFlags::empty(), token(self.id, id)))
+            .map_err(|e| Error::IOError(format!("register conn {} error: {}", id, e)))?;
+        state.conns.insert(id, Socket::new(stream));
+        state.update_interest(self.id);
+        Ok(())
+    }
+
+    // start forwarding the data read from the trunk to the connections
+    pub fn start(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.started = true;
+        state.update_interest(self.id);
+    }
+
+    fn handle(&self, conn_id: ConnId, flags: EpollFlags, buffer: &mut [u8]) {
+        let res = {
+            let mut state = self.state.lock().unwrap();
+            if self.is_closed() {
+                return;
             }
-            // println!("isula_rust_extensions::conn_reader: conn {} quit", id);
-        });
+            let res = if conn_id == TRUNK_CONN_ID {
+                state.handle_trunk(flags, buffer)
+            } else {
+                state.handle_conn(conn_id, flags, buffer)
+            };
+            state.update_interest(self.id);
+            res
+        };
 
-        Ok(())
+        if let Err(reason) = res {
+            self.close_from_reactor(reason);
+        }
     }
 
+    fn close_from_reactor(&self, reason: String) {
+        println!("isula_rust_extensions::mux: {}", reason);
+        // The handler may take a while, it must not hold up the other
+        // muxes.
+        if self.shutdown() {
+            let mut handler = self.close_handler.lock().unwrap();
+            match handler.take() {
+                Some(handler) => {
+                    thread::spawn(move || handler(&reason));
+                },
+                None => *self.unhandled_close.lock().unwrap() = Some(reason),
+            }
+        }
+    }
 
-    // trunk reader start a new thread to read data from trunk and forward it to the
-    // corresponding connection
-    pub fn trunk_reader(self: Arc<Self>) {
-        thread::spawn(move || {
-            const HEADER_LEN: usize = 8;
-            loop {
-                let mut hdr: [u8; HEADER_LEN] = [0; HEADER_LEN];
-                let mut trunk = self.trunk.try_clone().unwrap();
-                if let Err(e) = trunk.read_exact(&mut hdr) {
-                    if self.close_once.is_completed() {
-                        break;
-                    }
-                    self.close();
-                    println!("isula_rust_extensions::trunk_reader: trunk read error: {}", e);
-                    break;
-                }
-                // println!("Trunk read header: {:?}", hdr);
-                let cid = u32::from_be_bytes(hdr[0..4].try_into().unwrap());
-                let cnt = u32::from_be_bytes(hdr[4..8].try_into().unwrap());
-                let mut buffer = vec![0; cnt as usize];
-                if let Err(e) = trunk.read_exact(&mut buffer) {
-                    self.close();
-                    println!("isula_rust_extensions::trunk_reader: trunk read error: {}", e);
-                    break;
-                }
-                // println!("Trunk read buffer: {:?}", buffer);
-
-                if let Some(stream) = self.clone().conns.lock().unwrap().get_mut(&cid) {
-                    if let Err(e) = stream.write_all(&buffer) {
-                        self.close();
-                        println!("isula_rust_extensions::trunk_reader: conn {} write error: {}", cid, e);
-                        break;
-                    }
-                } else {
-                    println!("isula_rust_extensions::trunk_reader: conn {} not found", cid);
-                }
+    // set_close_handler sets the handler called once the mux is closed. If
+    // the trunk was closed already, it is called at once on a thread of its
+    // own, like when the reactor closes the mux.
+    pub fn set_close_handler(&self, handler: CloseHandler) {
+        let mut close_handler = self.close_handler.lock().unwrap();
+        match self.unhandled_close.lock().unwrap().take() {
+            Some(reason) => {
+                thread::spawn(move || handler(&reason));
+            },
+            None => *close_handler = Some(handler),
+        }
+    }
+
+    pub fn close(&self) {
+        self.close_with_reason("closed by runtime");
+    }
+
+    // The close handler runs after the mux is closed, outside of close_once,
+    // so that it may drop the last reference to the owner of the mux.
+    pub fn close_with_reason(&self, reason: &str) {
+        if self.shutdown() {
+            let handler = self.close_handler.lock().unwrap().take();
+            if let Some(handler) = handler {
+                handler(reason);
             }
-            // println!("isula_rust_extensions::trunk_reader: quit");
-        });
+        }
     }
 
-    pub fn close(self: Arc<Self>) {
+    // shutdown deregisters and closes the connections and shuts the trunk
+    // down, it returns whether the mux was closed by this call.
+    fn shutdown(&self) -> bool {
+        let mut closed = false;
         self.close_once.call_once(|| {
-            let conns = self.conns.lock().unwrap();
-            for (_, stream) in conns.iter() {
-                let _unused = stream.shutdown(std::net::Shutdown::Write);
+            let mut state = self.state.lock().unwrap();
+            for (_, conn) in state.conns.drain() {
+                let _unused = REACTOR.epoll.delete(&conn.stream);
             }
-            let _unused = self.trunk.shutdown(std::net::Shutdown::Both);
+            let _unused = REACTOR.epoll.delete(&state.trunk.stream);
+            let _unused = state.trunk.stream.shutdown(std::net::Shutdown::Both);
+            closed = true;
         });
+        closed
+    }
+
+    pub fn stats(&self) -> MuxStats {
+        self.state.lock().unwrap().stats
     }
 
     pub fn is_closed(&self) -> bool {
         self.close_once.is_completed()
     }
 }
+
+impl Drop for Mux {
+    fn drop(&mut self) {
+        REACTOR.muxes.lock().unwrap().remove(&self.id);
+    }
+}
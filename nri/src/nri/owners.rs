@@ -0,0 +1,95 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::fmt::Write;
+
+use protobuf::Enum;
+
+use crate::nri::error::{Error, Result};
+use crate::protocols::nri::{self, Field};
+
+// Owners records which plugin set each field of the containers adjusted or
+// updated during one dispatch. A field claimed by one plugin cannot be set
+// or removed by another one, like in upstream NRI. Hooks are accumulated
+// rather than set, so they are recorded for every plugin adding some.
+#[derive(Clone, Debug, Default)]
+pub struct Owners {
+    owners: nri::OwningPlugins,
+}
+
+impl Owners {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn claim(&mut self, id: &str, field: Field, plugin: &str) -> Result<()> {
+        let owners = self.owners.owners.entry(id.to_string()).or_default();
+        match owners.simple.get(&field.value()) {
+            Some(owner) if owner != plugin => Err(Error::Conflict(format!(
+                "plugins {:?} and {:?} both tried to set {:?} of container {}", owner, plugin, field, id))),
+            Some(_) => Ok(()),
+            None => {
+                owners.simple.insert(field.value(), plugin.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    pub fn claim_key(&mut self, id: &str, field: Field, key: &str, plugin: &str) -> Result<()> {
+        let owners = self.owners.owners.entry(id.to_string()).or_default()
+            .compound.entry(field.value()).or_default();
+        match owners.owners.get(key) {
+            Some(owner) if owner != plugin => Err(Error::Conflict(format!(
+                "plugins {:?} and {:?} both tried to set {:?} {:?} of container {}", owner, plugin, field, key, id))),
+            Some(_) => Ok(()),
+            None => {
+                owners.owners.insert(key.to_string(), plugin.to_string());
+                Ok(())
+            }
+        }
+    }
+
+    pub fn accumulate(&mut self, id: &str, field: Field, plugin: &str) {
+        let owners = self.owners.owners.entry(id.to_string()).or_default();
+        let owner = owners.simple.entry(field.value()).or_default();
+        if !owner.split(',').any(|o| o == plugin) {
+            if !owner.is_empty() {
+                owner.push(',');
+            }
+            owner.push_str(plugin);
+        }
+    }
+
+    // dump renders the record one field per line, sorted, for debugging.
+    pub fn dump(&self) -> String {
+        let mut lines = Vec::new();
+        for (id, owners) in &self.owners.owners {
+            for (field, owner) in &owners.simple {
+                lines.push(format!("{} {}: {}", id, field_name(*field), owner));
+            }
+            for (field, compound) in &owners.compound {
+                for (key, owner) in &compound.owners {
+                    lines.push(format!("{} {}[{}]: {}", id, field_name(*field), key, owner));
+                }
+            }
+        }
+        lines.sort();
+        lines.iter().fold(String::new(), |mut out, line| {
+            let _ = writeln!(out, "{}", line);
+            out
+        })
+    }
+}
+
+fn field_name(field: i32) -> String {
+    Field::from_i32(field).map_or(field.to_string(), |f| format!("{:?}", f))
+}
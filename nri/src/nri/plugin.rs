@@ -11,43 +11,326 @@
 // See the Mulan PSL v2 for more details.
 
 use lazy_static::lazy_static;
-use std::os::linux::net::SocketAddrExt;
 use std::os::unix::fs::{FileTypeExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 use std::fs::{self, remove_file, Permissions};
+use std::sync::mpsc;
 use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use crate::nri::error::{Result, Error};
 use crate::nri::c_transfer::{self, NriUpdateContainersResponse};
 use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
-use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
-use std::sync::{Arc, Mutex, RwLock};
-use crate::nri::mux;
-use ttrpc::Server;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use crate::nri::{adaptation, auth, config, mux, stats};
+use crate::nri::mux::{PLUGIN_SERVICE_CONN, RUNTIME_SERVICE_CONN};
+use crate::nri::server::{self, Server};
 use crate::protocols::{nri, nri_ttrpc};
 
 pub struct Plugin {
     mux: Arc<mux::Mux>,
     client: Arc<nri_ttrpc::PluginClient>,
-    timeout: i64
+    timeouts: RwLock<RpcTimeouts>,
+    // Event subscription returned by Configure, 0 until configured.
+    events: AtomicI32,
+    // Name and index the plugin registered itself with.
+    registration: RwLock<Option<(String, String)>>,
+    state: Mutex<PluginState>,
+    // Number of calls to the plugin in flight, waited for on shutdown.
+    calls: (Mutex<usize>, Condvar),
+    connected_at: SystemTime,
+    stats: stats::CallStats,
 }
 
-impl Drop for Plugin {
+// Call marks a call to the plugin in flight for as long as it lives.
+struct Call<'a>(&'a Plugin);
+
+impl Drop for Call<'_> {
     fn drop(&mut self) {
-        self.mux.clone().close();
+        let (calls, cond) = &self.0.calls;
+        *calls.lock().unwrap() -= 1;
+        cond.notify_all();
+    }
+}
+
+// PluginState follows the upstream plugin lifecycle: a connected plugin
+// registers itself, then gets configured and synchronized, and only then
+// receives pod and container events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub enum PluginState {
+    Connected = 0,
+    Registered = 1,
+    Configured = 2,
+    Synchronized = 3,
+    Closed = 4,
+}
+
+const ACTIVE_STATES: &[PluginState] = &[PluginState::Connected, PluginState::Registered,
+    PluginState::Configured, PluginState::Synchronized];
+
+// Plugins that do not register within this time are disconnected.
+pub const DEFAULT_REGISTRATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Time plugins get to acknowledge Shutdown and finish the calls in flight
+// when the runtime service is destroyed.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+const DEFAULT_RPC_TIMEOUT: i64 = Duration::from_secs(2).as_nanos() as i64;
+
+// Synchronize carries every container of the runtime, it gets longer.
+pub const DEFAULT_RPC_TIMEOUTS: RpcTimeouts = RpcTimeouts {
+    configure: DEFAULT_RPC_TIMEOUT,
+    synchronize: Duration::from_secs(30).as_nanos() as i64,
+    shutdown: DEFAULT_RPC_TIMEOUT,
+    create_container: DEFAULT_RPC_TIMEOUT,
+    update_container: DEFAULT_RPC_TIMEOUT,
+    stop_container: DEFAULT_RPC_TIMEOUT,
+    update_pod_sandbox: DEFAULT_RPC_TIMEOUT,
+    state_change: DEFAULT_RPC_TIMEOUT,
+    validate_container_adjustment: DEFAULT_RPC_TIMEOUT,
+};
+
+// RpcTimeouts are the timeouts of the calls to a plugin, per RPC, in ns. A
+// zero timeout waits forever, like a zero ttrpc timeout does.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RpcTimeouts {
+    pub configure: i64,
+    pub synchronize: i64,
+    pub shutdown: i64,
+    pub create_container: i64,
+    pub update_container: i64,
+    pub stop_container: i64,
+    pub update_pod_sandbox: i64,
+    pub state_change: i64,
+    pub validate_container_adjustment: i64,
+}
+
+impl Default for RpcTimeouts {
+    fn default() -> Self {
+        DEFAULT_RPC_TIMEOUTS
+    }
+}
+
+impl RpcTimeouts {
+    // uniform applies one timeout to every RPC, the way nri_plugin_connect
+    // always did.
+    pub fn uniform(timeout: i64) -> Self {
+        RpcTimeouts {
+            configure: timeout,
+            synchronize: timeout,
+            shutdown: timeout,
+            create_container: timeout,
+            update_container: timeout,
+            stop_container: timeout,
+            update_pod_sandbox: timeout,
+            state_change: timeout,
+            validate_container_adjustment: timeout,
+        }
+    }
+
+    fn get(&self, rpc: Rpc) -> i64 {
+        match rpc {
+            Rpc::Configure => self.configure,
+            Rpc::Synchronize => self.synchronize,
+            Rpc::Shutdown => self.shutdown,
+            Rpc::CreateContainer => self.create_container,
+            Rpc::UpdateContainer => self.update_container,
+            Rpc::StopContainer => self.stop_container,
+            Rpc::UpdatePodSandbox => self.update_pod_sandbox,
+            Rpc::StateChange => self.state_change,
+            Rpc::ValidateContainerAdjustment => self.validate_container_adjustment,
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let timeouts = [self.configure, self.synchronize, self.shutdown, self.create_container,
+            self.update_container, self.stop_container, self.update_pod_sandbox, self.state_change,
+            self.validate_container_adjustment];
+        if timeouts.iter().any(|t| *t < 0) {
+            return Err(Error::InvalidArgument(format!("negative timeout in {:?}", self)));
+        }
+        Ok(())
     }
 }
 
-const PLUGIN_SERVICE_CONN: u32 = 1;
-const RUNTIME_SERVICE_CONN: u32 = 2;
+// Rpc is an RPC of the plugin service, with a timeout of its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rpc {
+    Configure,
+    Synchronize,
+    Shutdown,
+    CreateContainer,
+    UpdateContainer,
+    StopContainer,
+    UpdatePodSandbox,
+    StateChange,
+    ValidateContainerAdjustment,
+}
+
+impl Rpc {
+    // name is the name of the RPC in the call stats and errors.
+    fn name(&self) -> &'static str {
+        match self {
+            Rpc::Configure => "configure",
+            Rpc::Synchronize => "synchronize",
+            Rpc::Shutdown => "shutdown",
+            Rpc::CreateContainer => "create_container",
+            Rpc::UpdateContainer => "update_container",
+            Rpc::StopContainer => "stop_container",
+            Rpc::UpdatePodSandbox => "update_pod_sandbox",
+            Rpc::StateChange => "state_change",
+            Rpc::ValidateContainerAdjustment => "validate_container_adjustment",
+        }
+    }
+}
+
+// A registered plugin, as seen by the multi-plugin dispatcher.
+#[derive(Clone, Debug)]
+pub struct PluginInfo {
+    pub id: String,
+    pub name: String,
+    pub idx: String,
+}
+
+impl PluginInfo {
+    // Plugins are identified by index and name, like upstream does.
+    pub fn full_name(&self) -> String {
+        format!("{}-{}", self.idx, self.name)
+    }
+}
+
+// A connected plugin, as listed for introspection. Name and index are empty
+// until the plugin registers.
+#[derive(Clone, Debug)]
+pub struct PluginStatus {
+    pub id: String,
+    pub name: String,
+    pub idx: String,
+    pub state: PluginState,
+    pub events: i32,
+    pub timeouts: RpcTimeouts,
+    pub mux_closed: bool,
+    pub connected_at: SystemTime,
+}
+
+impl Plugin {
+    pub fn state(&self) -> PluginState {
+        if self.mux.is_closed() {
+            return PluginState::Closed;
+        }
+        *self.state.lock().unwrap()
+    }
+
+    fn check_state(&self, plugin_id: &String, expected: &[PluginState]) -> Result<()> {
+        let state = self.state();
+        if !expected.contains(&state) {
+            return Err(Error::InvalidState(format!("plugin {} is {:?}, expected {:?}", plugin_id, state, expected)));
+        }
+        Ok(())
+    }
+
+    fn set_state(&self, state: PluginState) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    fn is_subscribed(&self, event: nri::Event) -> bool {
+        self.events.load(Ordering::Acquire) & event_mask(event) != 0
+    }
+
+    // begin_call refuses new calls once the runtime service shuts down. The
+    // flag is checked under the lock shutdown waits on, so a call is either
+    // refused or waited for.
+    fn begin_call(&self) -> Result<Call<'_>> {
+        let mut calls = self.calls.0.lock().unwrap();
+        if SHUTTING_DOWN.load(Ordering::SeqCst) {
+            return Err(Error::InvalidState("runtime service shutting down".to_string()));
+        }
+        *calls += 1;
+        Ok(Call(self))
+    }
+
+    // call makes an RPC to the plugin within the timeout of the RPC, and
+    // records it in the call stats.
+    fn call<T>(&self, rpc: Rpc,
+        f: impl FnOnce(&nri_ttrpc::PluginClient, ttrpc::context::Context) -> ttrpc::Result<T>) -> Result<T> {
+        let timeout = self.timeouts.read().unwrap().get(rpc);
+        self.call_with_timeout(rpc, timeout, f)
+    }
+
+    // A call failing once its deadline passed timed out, waiting for the
+    // response on the client side, or running on the plugin side.
+    fn call_with_timeout<T>(&self, rpc: Rpc, timeout: i64,
+        f: impl FnOnce(&nri_ttrpc::PluginClient, ttrpc::context::Context) -> ttrpc::Result<T>) -> Result<T> {
+        let start = Instant::now();
+        let res = f(&self.client, ttrpc::context::with_timeout(timeout));
+        let elapsed = start.elapsed();
+        let outcome = match &res {
+            Ok(_) => stats::CallOutcome::Ok,
+            Err(ttrpc::Error::RpcStatus(status)) if status.code() == ttrpc::Code::DEADLINE_EXCEEDED =>
+                stats::CallOutcome::Timeout,
+            Err(_) if timeout > 0 && elapsed >= Duration::from_nanos(timeout as u64) => stats::CallOutcome::Timeout,
+            Err(_) => stats::CallOutcome::Error,
+        };
+        self.stats.record(rpc.name(), elapsed, outcome);
+        res.map_err(|e| match outcome {
+            stats::CallOutcome::Timeout => Error::Timeout(format!("{} timed out after {:?}: {}",
+                rpc.name().replace('_', " "), Duration::from_nanos(timeout as u64), e)),
+            _ => Error::TtrpcError(format!("{} error: {}", rpc.name().replace('_', " "), e)),
+        })
+    }
+
+    // wait_calls waits for the calls in flight to finish, until deadline.
+    fn wait_calls(&self, deadline: Instant) -> bool {
+        let (calls, cond) = &self.calls;
+        let mut calls = calls.lock().unwrap();
+        while *calls > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            calls = cond.wait_timeout(calls, deadline - now).unwrap().0;
+        }
+        true
+    }
+}
+
+// Bit of an event in ConfigureResponse.events, as defined by upstream NRI.
+pub fn event_mask(event: nri::Event) -> i32 {
+    match event {
+        nri::Event::UNKNOWN | nri::Event::LAST => 0,
+        _ => 1 << (event as i32 - 1),
+    }
+}
+
+pub const VALID_EVENTS: i32 = (1 << (nri::Event::LAST as i32 - 1)) - 1;
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        self.mux.close();
+    }
+}
 
 lazy_static!{
     static ref PLUGINS: RwLock<HashMap<String, (Arc<Plugin>, Server)>> = RwLock::new(HashMap::new());
     static ref RUNTIME_CALLBACKS: RwLock<c_transfer::NriRuntimeCallbacks> = RwLock::new(
-        c_transfer::NriRuntimeCallbacks { register_plugin: None, update_containers: None });
+        c_transfer::NriRuntimeCallbacks {
+            register_plugin: None,
+            update_containers: None,
+            plugin_closed: None,
+            free_update_containers_response: None,
+        });
     static ref EXTERNAL_CONNECT_LISTENER: Mutex<Option<UnixListener>> = Mutex::new(None);
+    static ref REGISTRATION_TIMEOUT: RwLock<Duration> = RwLock::new(DEFAULT_REGISTRATION_TIMEOUT);
 }
 
+// Set while the runtime service shuts down, no plugin connects and no new
+// call is made meanwhile.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
 struct NriRuntimeService {
     plugin_id: String,
 }
@@ -62,17 +345,28 @@ impl nri_ttrpc::Runtime for NriRuntimeService {
         let callbacks = RUNTIME_CALLBACKS.read().
             map_err(|e| ttrpc::Error::Others(format!("lock error: {}", e)))?.clone();
         if let Some(register_plugin) = callbacks.register_plugin {
+            // The callback may configure the plugin right away, so the plugin
+            // has to be registered before it runs.
+            let plugin = plugin_get(&self.plugin_id, &[PluginState::Connected])
+                .map_err(|e| ttrpc::Error::Others(format!("register plugin for {} failed: {}", self.plugin_id, e)))?;
+            *plugin.registration.write().unwrap() = Some((_req.plugin_name.clone(), _req.plugin_idx.clone()));
+            plugin.set_state(PluginState::Registered);
+
             let c_plugin_id = std::ffi::CString::new(self.plugin_id.clone())
                 .map(|s| s.into_raw())
                 .unwrap_or(std::ptr::null_mut());
             let c_req = Box::into_raw(Box::new(c_transfer::NriRegisterPluginRequest::from(&_req)));
-            if register_plugin(c_plugin_id, c_req) != 0 {
+            let ret = register_plugin(c_plugin_id, c_req);
+            let _unused = unsafe { Box::from_raw(c_req) };
+            if !c_plugin_id.is_null() {
+                let _unused = unsafe { std::ffi::CString::from_raw(c_plugin_id) };
+            }
+            if ret != 0 {
+                plugin.set_state(PluginState::Connected);
+                *plugin.registration.write().unwrap() = None;
                 return Err(ttrpc::Error::Others(format!("register plugin for {} failed", self.plugin_id)));
             }
-            let rep = nri::Empty::new();
-            let _unused = unsafe { Box::from_raw(c_plugin_id) };
-            let _unused = unsafe { Box::from_raw(c_req) };
-            return Ok(rep)
+            return Ok(nri::Empty::new())
         }
         return Err(ttrpc::Error::Others("register plugin callback not registered".to_string()));
     }
@@ -92,14 +386,25 @@ impl nri_ttrpc::Runtime for NriRuntimeService {
                 .unwrap_or(std::ptr::null_mut());
             let c_req = Box::into_raw(Box::new(c_transfer::NriUpdateContainersRequest::from(&_req)));
             let mut c_resp: *mut NriUpdateContainersResponse = std::ptr::null_mut();
-            if update_containers(c_plugin_id, c_req, &mut c_resp) != 0 {
+            let ret = update_containers(c_plugin_id, c_req, &mut c_resp);
+            let _unused = unsafe { Box::from_raw(c_req) };
+            if !c_plugin_id.is_null() {
+                let _unused = unsafe { std::ffi::CString::from_raw(c_plugin_id) };
+            }
+            // The response is allocated by the runtime, it is read here and
+            // given back to the runtime to free.
+            let resp = match c_resp.is_null() {
+                true => nri::UpdateContainersResponse::new(),
+                false => nri::UpdateContainersResponse::from(unsafe { &*c_resp }),
+            };
+            if !c_resp.is_null() {
+                if let Some(free_update_containers_response) = callbacks.free_update_containers_response {
+                    free_update_containers_response(c_resp);
+                }
+            }
+            if ret != 0 {
                 return Err(ttrpc::Error::Others(format!("update containers for {} failed", self.plugin_id)));
             }
-            let resp = nri::UpdateContainersResponse::from(unsafe { &*c_resp });
-
-            let _unused = unsafe { Box::from_raw(c_plugin_id) };
-            let _unused = unsafe { Box::from_raw(c_req) };
-            let _unused = unsafe { Box::from_raw(c_resp) };
             return Ok(resp);
         }
         return Err(ttrpc::Error::Others("update containers callback not registered".to_string()));
@@ -150,11 +455,61 @@ pub fn runtime_service_init(callbacks: c_transfer::NriRuntimeCallbacks) -> Resul
     Ok(())
 }
 
-pub fn runtime_service_destroy() {
-    let mut plugins = PLUGINS.write().unwrap();
-    for (_unused, (_, server)) in plugins.drain() {
+// runtime_service_destroy shuts all the plugins down in order: no external
+// plugin is accepted anymore, every plugin is sent Shutdown, all in
+// parallel, and the calls in flight are waited for, all within timeout.
+// Then the connections are closed. It returns the plugins which did not
+// acknowledge Shutdown in time, sorted.
+pub fn runtime_service_destroy(timeout: Duration) -> Vec<String> {
+    let deadline = Instant::now() + timeout;
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+    external_service_shutdown();
+
+    // Plugins going away from here on are not reported as closed, and
+    // servers are shut down without holding the lock, their handlers take it.
+    let plugins: Vec<_> = PLUGINS.write().unwrap().drain().collect();
+
+    let (ack_tx, ack_rx) = mpsc::channel();
+    for (plugin_id, (plugin, _)) in plugins.iter() {
+        if !ACTIVE_STATES.contains(&plugin.state()) {
+            continue;
+        }
+        let (plugin_id, plugin, ack_tx) = (plugin_id.clone(), plugin.clone(), ack_tx.clone());
+        thread::spawn(move || {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let res = plugin.call_with_timeout(Rpc::Shutdown, timeout.as_nanos() as i64,
+                |client, ctx| client.shutdown(ctx, &nri::Empty::new()));
+            if let Err(e) = &res {
+                println!("isula_rust_extensions::plugin {} shutdown error: {}", plugin_id, e);
+            }
+            let _unused = ack_tx.send((plugin_id, res.is_ok()));
+        });
+    }
+    drop(ack_tx);
+
+    let mut acked = Vec::new();
+    while let Ok((plugin_id, ok)) = ack_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        if ok {
+            acked.push(plugin_id);
+        }
+    }
+
+    let mut unacked = Vec::new();
+    for (plugin_id, (plugin, server)) in plugins {
+        if !plugin.wait_calls(deadline) {
+            println!("isula_rust_extensions::plugin {} calls still in flight at shutdown", plugin_id);
+        }
+        plugin.set_state(PluginState::Closed);
+        plugin.mux.close();
         server.shutdown();
+        if !acked.contains(&plugin_id) {
+            unacked.push(plugin_id);
+        }
     }
+    unacked.sort();
+
+    SHUTTING_DOWN.store(false, Ordering::SeqCst);
+    unacked
 }
 
 // *RUNTIME_SERVER.lock().unwrap() = Some(server);
@@ -164,13 +519,17 @@ pub fn runtime_service_destroy() {
 // plugin writes data to the peer fd in the format of hdr{connId, cnt} + ttrpc data.
 // After we read the data in the local fd, we should parse it first and then do the ttrpc call.
 // For ttrpc implemetation:
-//   for Runtime Service: we use one unix socket to listen to the requests for per plugin.
-//       We have a connection to the unix socket for each plugin to write and read to the service.
+//   for Runtime Service: we create a socket pair for each plugin too.
+//       One end is a conn of the mux, the runtime service is served over the other end.
 //   for Plugin Client: we create a socket pair for each plugin to write and read.
 //       One end is to receive data from container runtime and transfer to ttrpc data
 //       The other end is to handle these data and send to plugin.
-pub fn connect(plugin_id: &String, local_fd: RawFd, timeout: i64) -> Result<()> {
+pub fn connect(plugin_id: &String, local_fd: RawFd, timeouts: RpcTimeouts) -> Result<()> {
     let trunk_stream = unsafe { UnixStream::from_raw_fd(local_fd) };
+    if SHUTTING_DOWN.load(Ordering::SeqCst) {
+        return Err(Error::InvalidState("runtime service shutting down".to_string()));
+    }
+    timeouts.validate()?;
 
     let mut plugins = PLUGINS.write()
         .map_err(|e| Error::Other(format!("lock error: {}", e)))?;
@@ -178,54 +537,187 @@ pub fn connect(plugin_id: &String, local_fd: RawFd, timeout: i64) -> Result<()>
     // socket1 will be added to conn & socket2 will be used to create a client
     let (socket1, socket2) = UnixStream::pair()
         .map_err(|e| Error::IOError(format!("create socket pair error: {}", e)))?;
+    // runtime1 will be added to conn & runtime2 will serve the runtime service
+    let (runtime1, runtime2) = UnixStream::pair()
+        .map_err(|e| Error::IOError(format!("create socket pair error: {}", e)))?;
 
     // Register one runtime service of container runtime for each plugin.
-    let runtime_socket_addr = ("/var/run/nri/").to_string() + plugin_id + ".sock";
     let nri = Arc::new(NriRuntimeService {
         plugin_id: plugin_id.clone()
     });
     let nriservice = nri_ttrpc::create_runtime(nri);
 
-    // for runtime service, we use an abstract unix socket
-    let mut server = Server::new()
-        .bind(("unix://@".to_string() + &runtime_socket_addr).as_str())
-        .map_err(|e| Error::Other(format!("Bind error: {}", e)))?
-        .register_service(nriservice);
-
     let plugin = Plugin {
-        mux: Arc::new(mux::Mux::new(trunk_stream)),
+        mux: mux::Mux::new(trunk_stream)?,
         client: Arc::new(nri_ttrpc::PluginClient::new(ttrpc::Client::new(socket2.into_raw_fd())
             .map_err(|e| Error::TtrpcError(format!("create client error: {}", e)))?)),
-        timeout: timeout
+        timeouts: RwLock::new(timeouts),
+        events: AtomicI32::new(0),
+        registration: RwLock::new(None),
+        state: Mutex::new(PluginState::Connected),
+        calls: (Mutex::new(0), Condvar::new()),
+        connected_at: SystemTime::now(),
+        stats: stats::CallStats::default(),
     };
 
-    plugin.mux.clone().add_conn(PLUGIN_SERVICE_CONN, socket1)?;
-
-    server.start().map_err(|e| Error::Other(format!("start error: {}", e)))?;
+    plugin.mux.add_conn(PLUGIN_SERVICE_CONN, socket1)?;
+    plugin.mux.add_conn(RUNTIME_SERVICE_CONN, runtime1)?;
+    let server = server::serve(runtime2, nriservice)?;
 
-    if SocketAddr::from_abstract_name(runtime_socket_addr.as_bytes())
-        .is_ok_and(|addr| UnixStream::connect_addr(&addr)
-            .is_ok_and(|stream| plugin.mux.clone().add_conn(RUNTIME_SERVICE_CONN, stream)
-                .is_ok())) {
-        plugin.mux.clone().trunk_reader();
-        plugins.insert(plugin_id.clone(), (Arc::new(plugin), server));
-    } else {
-        server.shutdown();
-        return Err(Error::IOError("connect runtime service error".to_string()));
-    }
+    let plugin = Arc::new(plugin);
+    let closed_id = plugin_id.clone();
+    let closed_plugin = Arc::downgrade(&plugin);
+    plugin.mux.set_close_handler(Box::new(move |reason| {
+        if let Some(plugin) = closed_plugin.upgrade() {
+            close_plugin(&closed_id, &plugin, reason);
+        }
+    }));
+    plugin.mux.start();
+    watch_registration(plugin_id, &plugin);
+    plugins.insert(plugin_id.clone(), (plugin, server));
 
     Ok(())
 }
 
 pub fn disconnect(plugin_id: &String) -> Result<()> {
-    let mut plugins = PLUGINS.write()
-        .map_err(|e| Error::Other(format!("lock error: {}", e)))?;
-    if let Some((_, server)) = plugins.remove(plugin_id) {
+    let removed = PLUGINS.write()
+        .map_err(|e| Error::Other(format!("lock error: {}", e)))?
+        .remove(plugin_id);
+    if let Some((plugin, server)) = removed {
+        plugin.set_state(PluginState::Closed);
         server.shutdown();
     }
     Ok(())
 }
 
+pub fn state(plugin_id: &String) -> Result<PluginState> {
+    let plugins = PLUGINS.read().map_err(|e| Error::Other(format!("lock error: {}", e)))?;
+    match plugins.get(plugin_id) {
+        Some((plugin, _)) => Ok(plugin.state()),
+        None => Err(Error::Other("client not found".to_string())),
+    }
+}
+
+// list returns all the connected plugins, sorted by id.
+pub fn list() -> Result<Vec<PluginStatus>> {
+    let plugins = PLUGINS.read().map_err(|e| Error::Other(format!("lock error: {}", e)))?;
+    let mut statuses: Vec<PluginStatus> = plugins.iter()
+        .map(|(id, (plugin, _))| {
+            let (name, idx) = plugin.registration.read().unwrap().clone().unwrap_or_default();
+            PluginStatus {
+                id: id.clone(),
+                name,
+                idx,
+                state: plugin.state(),
+                events: plugin.events.load(Ordering::Acquire),
+                timeouts: *plugin.timeouts.read().unwrap(),
+                mux_closed: plugin.mux.is_closed(),
+                connected_at: plugin.connected_at,
+            }
+        })
+        .collect();
+    statuses.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(statuses)
+}
+
+// stats returns the call and mux stats of all the connected plugins, sorted
+// by id.
+pub fn stats() -> Result<Vec<stats::PluginStats>> {
+    let plugins = PLUGINS.read().map_err(|e| Error::Other(format!("lock error: {}", e)))?;
+    let mut stats: Vec<stats::PluginStats> = plugins.iter()
+        .map(|(id, (plugin, _))| {
+            let (name, idx) = plugin.registration.read().unwrap().clone().unwrap_or_default();
+            stats::PluginStats {
+                id: id.clone(),
+                name,
+                idx,
+                mux: plugin.mux.stats(),
+                rpcs: plugin.stats.snapshot(),
+            }
+        })
+        .collect();
+    stats.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(stats)
+}
+
+// set_rpc_timeouts changes the timeouts of the calls to a connected plugin,
+// for the calls made afterwards.
+pub fn set_rpc_timeouts(plugin_id: &String, timeouts: RpcTimeouts) -> Result<()> {
+    timeouts.validate()?;
+    let plugins = PLUGINS.read().map_err(|e| Error::Other(format!("lock error: {}", e)))?;
+    match plugins.get(plugin_id) {
+        Some((plugin, _)) => {
+            *plugin.timeouts.write().unwrap() = timeouts;
+            Ok(())
+        },
+        None => Err(Error::Other("client not found".to_string())),
+    }
+}
+
+pub fn mux_stats(plugin_id: &String) -> Result<mux::MuxStats> {
+    let plugins = PLUGINS.read().map_err(|e| Error::Other(format!("lock error: {}", e)))?;
+    match plugins.get(plugin_id) {
+        Some((plugin, _)) => Ok(plugin.mux.stats()),
+        None => Err(Error::Other("client not found".to_string())),
+    }
+}
+
+pub fn set_registration_timeout(timeout: Duration) {
+    *REGISTRATION_TIMEOUT.write().unwrap() = timeout;
+}
+
+// watch_registration disconnects the plugin if it is still only connected
+// once the registration timeout expires. A zero timeout waits forever.
+fn watch_registration(plugin_id: &str, plugin: &Arc<Plugin>) {
+    let timeout = *REGISTRATION_TIMEOUT.read().unwrap();
+    if timeout.is_zero() {
+        return;
+    }
+    let plugin_id = plugin_id.to_string();
+    let plugin = Arc::downgrade(plugin);
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        let plugin = match plugin.upgrade() {
+            Some(plugin) => plugin,
+            None => return,
+        };
+        if plugin.state() != PluginState::Connected {
+            return;
+        }
+        println!("isula_rust_extensions::plugin {} did not register within {:?}, disconnecting", plugin_id, timeout);
+        close_plugin(&plugin_id, &plugin, "registration timeout");
+    });
+}
+
+// close_plugin drops a plugin that went away without the runtime asking
+// for it, and lets the runtime know through the plugin_closed callback.
+// Plugins disconnected by the runtime are not in PLUGINS anymore and are
+// not reported.
+fn close_plugin(plugin_id: &String, plugin: &Arc<Plugin>, reason: &str) {
+    // The id may have been reused by a new connection in the meantime.
+    let removed = {
+        let mut plugins = PLUGINS.write().unwrap();
+        match plugins.get(plugin_id) {
+            Some((current, _)) if Arc::ptr_eq(current, plugin) => plugins.remove(plugin_id),
+            _ => None,
+        }
+    };
+    let server = match removed {
+        Some((_, server)) => server,
+        None => return,
+    };
+    plugin.set_state(PluginState::Closed);
+    server.shutdown();
+    println!("isula_rust_extensions::plugin {} closed: {}", plugin_id, reason);
+
+    let callbacks = RUNTIME_CALLBACKS.read().unwrap().clone();
+    if let Some(plugin_closed) = callbacks.plugin_closed {
+        let c_plugin_id = std::ffi::CString::new(plugin_id.clone()).unwrap_or_default();
+        let c_reason = std::ffi::CString::new(reason).unwrap_or_default();
+        plugin_closed(c_plugin_id.as_ptr(), c_reason.as_ptr());
+    }
+}
+
 pub fn external_service_start(socket_addr: &String, callback: Option<c_transfer::NriExternalConnectCallback>) -> Result<()> {
     if (*EXTERNAL_CONNECT_LISTENER.lock().unwrap()).is_some() {
         return Err(Error::Other("external service already started".to_string()));
@@ -252,8 +744,16 @@ pub fn external_service_start(socket_addr: &String, callback: Option<c_transfer:
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
+                    // Rejected peers are closed before the runtime hears of them.
+                    let cred = match auth::authorize(&stream) {
+                        Ok(cred) => cred,
+                        Err(e) => {
+                            println!("isula_rust_extensions::external_service rejected connection: {}", e);
+                            continue;
+                        }
+                    };
                     let fd = stream.into_raw_fd();
-                    if callback.unwrap()(fd) != 0 {
+                    if callback.unwrap()(fd, cred.pid, cred.uid) != 0 {
                         unsafe {libc::close(fd)};
                         println!("isula_rust_extensions::external_service connect callback failed");
                     }
@@ -274,69 +774,160 @@ pub fn external_service_shutdown() {
     }
 }
 
-fn plugin_get(plugin_id: &String) -> Result<Arc<Plugin>> {
+fn plugin_get(plugin_id: &String, expected: &[PluginState]) -> Result<Arc<Plugin>> {
     let plugins = PLUGINS.read().map_err(|e| Error::Other(format!("lock error: {}", e)))?;
     if plugins.contains_key(plugin_id) {
         let plugin = plugins.get(plugin_id).unwrap().0.clone();
         if plugin.mux.is_closed() {
             return Err(Error::Other("plugin connection closed".to_string()));
         }
+        plugin.check_state(plugin_id, expected)?;
         Ok(plugin)
     } else {
         Err(Error::Other("client not found".to_string()))
     }
 }
 
+// registered_plugins returns the plugins ready for events, that is the
+// synchronized ones, in the order they are to be invoked: ascending index,
+// then name.
+pub fn registered_plugins() -> Result<Vec<PluginInfo>> {
+    let plugins = PLUGINS.read().map_err(|e| Error::Other(format!("lock error: {}", e)))?;
+    let mut infos: Vec<PluginInfo> = plugins.iter()
+        .filter(|(_, (plugin, _))| plugin.state() == PluginState::Synchronized)
+        .filter_map(|(id, (plugin, _))| {
+            plugin.registration.read().unwrap().as_ref().map(|(name, idx)| PluginInfo {
+                id: id.clone(),
+                name: name.clone(),
+                idx: idx.clone(),
+            })
+        })
+        .collect();
+    infos.sort_by(|a, b| (&a.idx, &a.name).cmp(&(&b.idx, &b.name)));
+    Ok(infos)
+}
+
 pub fn configure(plugin_id: &String, req: &nri::ConfigureRequest) -> Result<nri::ConfigureResponse> {
-    let plugin = plugin_get(plugin_id)?;
-    let res = plugin.client
-        .configure(ttrpc::context::with_timeout(plugin.timeout), req)
-        .map_err(|e| Error::TtrpcError(format!("configure error: {}", e)))?;
+    let plugin = plugin_get(plugin_id, &[PluginState::Registered])?;
+    let _call = plugin.begin_call()?;
+    // A configuration given by the runtime wins over the drop-in one.
+    let mut req = req.clone();
+    if req.config.is_empty() {
+        if let Some((name, idx)) = plugin.registration.read().unwrap().clone() {
+            req.config = config::plugin_config(&idx, &name)?;
+        }
+    }
+    let res = plugin.call(Rpc::Configure, |client, ctx| client.configure(ctx, &req))?;
+    if res.events & !VALID_EVENTS != 0 {
+        return Err(Error::InvalidArgument(format!("plugin {} subscribed for invalid events 0x{:x}",
+            plugin_id, res.events)));
+    }
+    // Like upstream, a plugin subscribing for nothing gets every event.
+    let events = if res.events == 0 { VALID_EVENTS } else { res.events };
+    plugin.events.store(events, Ordering::Release);
+    plugin.set_state(PluginState::Configured);
     Ok(res)
 }
 
+pub fn subscribed_events(plugin_id: &String) -> Result<i32> {
+    let plugin = plugin_get(plugin_id, ACTIVE_STATES)?;
+    Ok(plugin.events.load(Ordering::Acquire))
+}
+
+pub fn is_subscribed(plugin_id: &String, event: nri::Event) -> Result<bool> {
+    let plugin = plugin_get(plugin_id, ACTIVE_STATES)?;
+    Ok(plugin.is_subscribed(event))
+}
+
 pub fn synchronize(plugin_id: &String, req: &nri::SynchronizeRequest) -> Result<nri::SynchronizeResponse> {
-    let plugin = plugin_get(plugin_id)?;
-    let res = plugin.client
-        .synchronize(ttrpc::context::with_timeout(plugin.timeout), req)
-        .map_err(|e| Error::TtrpcError(format!("synchronize error: {}", e)))?;
+    // A large state is synchronized in several parts, all but the last one
+    // with more set.
+    let plugin = plugin_get(plugin_id, &[PluginState::Configured])?;
+    let _call = plugin.begin_call()?;
+    let res = plugin.call(Rpc::Synchronize, |client, ctx| client.synchronize(ctx, req))?;
+    if !req.more {
+        plugin.set_state(PluginState::Synchronized);
+    }
     Ok(res)
 }
 
 pub fn shutdown(plugin_id: &String) -> Result<()> {
-    let plugin = plugin_get(plugin_id)?;
-    plugin.client.shutdown(ttrpc::context::with_timeout(plugin.timeout), &nri::Empty::new())
-        .map_err(|e| Error::TtrpcError(format!("shutdown error: {}", e)))?;
+    let plugin = plugin_get(plugin_id, ACTIVE_STATES)?;
+    let _call = plugin.begin_call()?;
+    plugin.call(Rpc::Shutdown, |client, ctx| client.shutdown(ctx, &nri::Empty::new()))?;
     Ok(())
 }
 
 pub fn create_container(plugin_id: &String, req: &nri::CreateContainerRequest) -> Result<nri::CreateContainerResponse> {
-    let plugin = plugin_get(plugin_id)?;
-    let res = plugin.client
-        .create_container(ttrpc::context::with_timeout(plugin.timeout), req)
-        .map_err(|e| Error::TtrpcError(format!("create container error: {}", e)))?;
+    let plugin = plugin_get(plugin_id, &[PluginState::Synchronized])?;
+    let _call = plugin.begin_call()?;
+    if !plugin.is_subscribed(nri::Event::CREATE_CONTAINER) {
+        let mut res = nri::CreateContainerResponse::new();
+        res.adjust = protobuf::MessageField::some(nri::ContainerAdjustment::new());
+        return Ok(res);
+    }
+    let res = plugin.call(Rpc::CreateContainer, |client, ctx| client.create_container(ctx, req))?;
     Ok(res)
 }
 
 pub fn update_container(plugin_id: &String, req: &nri::UpdateContainerRequest) -> Result<nri::UpdateContainerResponse> {
-    let plugin = plugin_get(plugin_id)?;
-    let res = plugin.client
-        .update_container(ttrpc::context::with_timeout(plugin.timeout), req)
-        .map_err(|e| Error::TtrpcError(format!("start container error: {}", e)))?;
+    let plugin = plugin_get(plugin_id, &[PluginState::Synchronized])?;
+    let _call = plugin.begin_call()?;
+    if !plugin.is_subscribed(nri::Event::UPDATE_CONTAINER) {
+        return Ok(nri::UpdateContainerResponse::new());
+    }
+    let res = plugin.call(Rpc::UpdateContainer, |client, ctx| client.update_container(ctx, req))?;
     Ok(res)
 }
 
 pub fn stop_container(plugin_id: &String, req: &nri::StopContainerRequest) -> Result<nri::StopContainerResponse> {
-    let plugin = plugin_get(plugin_id)?;
-    let res = plugin.client
-        .stop_container(ttrpc::context::with_timeout(plugin.timeout), req)
-        .map_err(|e| Error::TtrpcError(format!("stop container error: {}", e)))?;
+    let plugin = plugin_get(plugin_id, &[PluginState::Synchronized])?;
+    let _call = plugin.begin_call()?;
+    if !plugin.is_subscribed(nri::Event::STOP_CONTAINER) {
+        return Ok(nri::StopContainerResponse::new());
+    }
+    let res = plugin.call(Rpc::StopContainer, |client, ctx| client.stop_container(ctx, req))?;
+    Ok(res)
+}
+
+pub fn update_pod_sandbox(plugin_id: &String, req: &nri::UpdatePodSandboxRequest) -> Result<nri::UpdatePodSandboxResponse> {
+    let plugin = plugin_get(plugin_id, &[PluginState::Synchronized])?;
+    let _call = plugin.begin_call()?;
+    if !plugin.is_subscribed(nri::Event::UPDATE_POD_SANDBOX) {
+        return Ok(nri::UpdatePodSandboxResponse::new());
+    }
+    let res = plugin.call(Rpc::UpdatePodSandbox, |client, ctx| client.update_pod_sandbox(ctx, req))?;
+    Ok(res)
+}
+
+// PostUpdatePodSandbox is not an RPC of its own, the plugin receives it as a
+// state change event carrying the updated pod.
+pub fn post_update_pod_sandbox(plugin_id: &String, pod: nri::PodSandbox) -> Result<()> {
+    let mut req = nri::StateChangeEvent::new();
+    req.event = nri::Event::POST_UPDATE_POD_SANDBOX.into();
+    req.pod = protobuf::MessageField::some(pod);
+    state_change(plugin_id, &req)
+}
+
+pub fn validate_container_adjustment(plugin_id: &String, req: &nri::ValidateContainerAdjustmentRequest) -> Result<nri::ValidateContainerAdjustmentResponse> {
+    let plugin = plugin_get(plugin_id, &[PluginState::Synchronized])?;
+    let _call = plugin.begin_call()?;
+    if !plugin.is_subscribed(nri::Event::VALIDATE_CONTAINER_ADJUSTMENT) {
+        return Ok(nri::ValidateContainerAdjustmentResponse::new());
+    }
+    let res = plugin.call(Rpc::ValidateContainerAdjustment, |client, ctx| client.validate_container_adjustment(ctx, req))?;
     Ok(res)
 }
 
 pub fn state_change(plugin_id: &String, req: &nri::StateChangeEvent) -> Result<()> {
-    let plugin = plugin_get(plugin_id)?;
-    plugin.client.state_change(ttrpc::context::with_timeout(plugin.timeout), req)
-        .map_err(|e| Error::TtrpcError(format!("state change error: {}", e)))?;
+    adaptation::forget_removed_container(req);
+    let plugin = plugin_get(plugin_id, &[PluginState::Synchronized])?;
+    let _call = plugin.begin_call()?;
+    let event = req.event.enum_value()
+        .map_err(|e| Error::InvalidArgument(format!("unknown event {}", e)))?;
+    if !plugin.is_subscribed(event) {
+        return Ok(());
+    }
+    plugin.call(Rpc::StateChange, |client, ctx| client.state_change(ctx, req))?;
     Ok(())
 }
@@ -0,0 +1,148 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// server serves ttrpc services over an already connected stream. ttrpc's
+// own Server only accepts connections from a listening socket, so the
+// runtime service of a plugin would need one; here it is served over one end
+// of a socket pair whose other end is a conn of the plugin mux instead.
+// Requests are read by one thread and handled by a few worker threads,
+// responses are written by another thread. The reader stops reading while
+// all the workers are busy and the queue of requests is full.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use protobuf::Message;
+use ttrpc::proto::{MESSAGE_HEADER_LENGTH, MESSAGE_LENGTH_MAX, MESSAGE_TYPE_REQUEST};
+use ttrpc::{Code, MessageHeader, MethodHandler, Request, Response, TtrpcContext};
+
+use crate::nri::error::{Error, Result};
+
+pub type Methods = HashMap<String, Box<dyn MethodHandler + Send + Sync>>;
+
+// Requests handled at once by a server, and waiting for a worker.
+const WORKERS: usize = 4;
+const QUEUE_LEN: usize = 64;
+
+pub struct Server {
+    stream: UnixStream,
+    reader: Option<JoinHandle<()>>,
+}
+
+// serve starts serving the methods over the stream.
+pub fn serve(stream: UnixStream, methods: Methods) -> Result<Server> {
+    let mut reader_stream = stream.try_clone()
+        .map_err(|e| Error::IOError(format!("clone stream error: {}", e)))?;
+    let mut writer_stream = stream.try_clone()
+        .map_err(|e| Error::IOError(format!("clone stream error: {}", e)))?;
+    let methods = Arc::new(methods);
+    let (res_tx, res_rx) = mpsc::channel::<(MessageHeader, Vec<u8>)>();
+
+    // Exits once the reader and all the handlers are done.
+    thread::spawn(move || {
+        for (mh, buf) in res_rx {
+            let hdr: Vec<u8> = mh.into();
+            if let Err(e) = writer_stream.write_all(&hdr).and_then(|_| writer_stream.write_all(&buf)) {
+                println!("isula_rust_extensions::server: write response error: {}", e);
+                break;
+            }
+        }
+    });
+
+    // Exit once the reader is done and the queue drained.
+    let (job_tx, job_rx) = crossbeam_channel::bounded::<(String, TtrpcContext, Request)>(QUEUE_LEN);
+    for _ in 0..WORKERS {
+        let (methods, job_rx) = (methods.clone(), job_rx.clone());
+        thread::spawn(move || {
+            for (path, ctx, req) in job_rx {
+                if let Err(e) = methods[&path].handler(ctx, req) {
+                    println!("isula_rust_extensions::server: handle {} error: {:?}", path, e);
+                }
+            }
+        });
+    }
+
+    let reader = thread::spawn(move || {
+        // Dropped when the connection goes away, like in ttrpc.
+        let (_cancel_tx, cancel_rx) = crossbeam_channel::unbounded::<()>();
+        let fd = reader_stream.as_raw_fd();
+        loop {
+            let mut hdr = [0; MESSAGE_HEADER_LENGTH];
+            if reader_stream.read_exact(&mut hdr).is_err() {
+                break;
+            }
+            let mh = MessageHeader::from(hdr);
+            if mh.length as usize > MESSAGE_LENGTH_MAX {
+                println!("isula_rust_extensions::server: message of {} bytes too large", mh.length);
+                break;
+            }
+            let mut buf = vec![0; mh.length as usize];
+            if reader_stream.read_exact(&mut buf).is_err() {
+                break;
+            }
+            if mh.type_ != MESSAGE_TYPE_REQUEST {
+                continue;
+            }
+
+            let req = match Request::parse_from_bytes(&buf) {
+                Ok(req) => req,
+                Err(e) => {
+                    respond_error(&mh, Code::INVALID_ARGUMENT, e.to_string(), &res_tx);
+                    continue;
+                }
+            };
+            let path = format!("/{}/{}", req.service, req.method);
+            if !methods.contains_key(&path) {
+                respond_error(&mh, Code::INVALID_ARGUMENT, format!("{} does not exist", path), &res_tx);
+                continue;
+            }
+            let ctx = TtrpcContext {
+                fd,
+                cancel_rx: cancel_rx.clone(),
+                mh,
+                res_tx: res_tx.clone(),
+                metadata: ttrpc::context::from_pb(&req.metadata),
+                timeout_nano: req.timeout_nano,
+            };
+            if job_tx.send((path, ctx, req)).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(Server {
+        stream,
+        reader: Some(reader),
+    })
+}
+
+fn respond_error(mh: &MessageHeader, code: Code, message: String, res_tx: &mpsc::Sender<(MessageHeader, Vec<u8>)>) {
+    let mut res = Response::new();
+    res.set_status(ttrpc::get_status(code, message));
+    let _unused = ttrpc::response_to_channel(mh.stream_id, res, res_tx.clone());
+}
+
+impl Server {
+    // shutdown closes the connection and waits for the reader to quit.
+    // Handlers still running finish on their own.
+    pub fn shutdown(mut self) {
+        let _unused = self.stream.shutdown(std::net::Shutdown::Both);
+        if let Some(reader) = self.reader.take() {
+            let _unused = reader.join();
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// stats keeps the counters and latencies of the calls made to each plugin,
+// per RPC, to find out which plugin slows the runtime down.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::nri::error::{Error, Result};
+use crate::nri::mux::MuxStats;
+
+// Upper bounds of the latency buckets, the last bucket takes the rest.
+const LATENCY_BUCKETS_MS: [u64; 12] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Bucket {
+    // None for the last, unbounded bucket.
+    pub le_ms: Option<u64>,
+    pub count: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Histogram {
+    pub buckets: Vec<Bucket>,
+    pub sum_us: u64,
+    pub max_us: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        let mut buckets: Vec<Bucket> = LATENCY_BUCKETS_MS.iter()
+            .map(|le_ms| Bucket { le_ms: Some(*le_ms), count: 0 })
+            .collect();
+        buckets.push(Bucket { le_ms: None, count: 0 });
+        Histogram { buckets, sum_us: 0, max_us: 0 }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, latency: Duration) {
+        let bucket = self.buckets.iter_mut()
+            .find(|b| match b.le_ms {
+                Some(le_ms) => latency <= Duration::from_millis(le_ms),
+                None => true,
+            })
+            .unwrap();
+        bucket.count += 1;
+        let us = latency.as_micros() as u64;
+        self.sum_us += us;
+        self.max_us = self.max_us.max(us);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().map(|b| b.count).sum()
+    }
+
+    // quantile returns the upper bound of the bucket holding the q-quantile,
+    // None if it is the unbounded one or there is no sample.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((count as f64 * q).ceil() as u64).max(1);
+        let mut seen = 0;
+        for bucket in &self.buckets {
+            seen += bucket.count;
+            if seen >= rank {
+                return bucket.le_ms;
+            }
+        }
+        None
+    }
+}
+
+// Outcome of a call, timeouts are not counted as errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallOutcome {
+    Ok,
+    Error,
+    Timeout,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RpcStats {
+    pub calls: u64,
+    pub errors: u64,
+    pub timeouts: u64,
+    pub latency: Histogram,
+}
+
+// CallStats are the stats of the calls made to one plugin.
+#[derive(Default)]
+pub struct CallStats {
+    rpcs: Mutex<BTreeMap<&'static str, RpcStats>>,
+}
+
+impl CallStats {
+    pub fn record(&self, rpc: &'static str, latency: Duration, outcome: CallOutcome) {
+        let mut rpcs = self.rpcs.lock().unwrap();
+        let stats = rpcs.entry(rpc).or_default();
+        stats.calls += 1;
+        match outcome {
+            CallOutcome::Ok => (),
+            CallOutcome::Error => stats.errors += 1,
+            CallOutcome::Timeout => stats.timeouts += 1,
+        }
+        stats.latency.observe(latency);
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, RpcStats> {
+        self.rpcs.lock().unwrap().iter()
+            .map(|(rpc, stats)| (rpc.to_string(), stats.clone()))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PluginStats {
+    pub id: String,
+    pub name: String,
+    pub idx: String,
+    pub mux: MuxStats,
+    pub rpcs: BTreeMap<String, RpcStats>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpFormat {
+    Text,
+    Json,
+}
+
+impl TryFrom<i32> for DumpFormat {
+    type Error = Error;
+
+    fn try_from(format: i32) -> Result<Self> {
+        match format {
+            0 => Ok(DumpFormat::Text),
+            1 => Ok(DumpFormat::Json),
+            _ => Err(Error::InvalidArgument(format!("unknown stats format {}", format))),
+        }
+    }
+}
+
+fn format_ms(ms: Option<u64>) -> String {
+    ms.map_or(">5000ms".to_string(), |ms| format!("<={}ms", ms))
+}
+
+pub fn dump(plugins: &[PluginStats], format: DumpFormat) -> Result<String> {
+    if format == DumpFormat::Json {
+        return serde_json::to_string(plugins).map_err(|e| Error::Other(format!("encode stats error: {}", e)));
+    }
+
+    let mut out = String::new();
+    for plugin in plugins {
+        let mux = &plugin.mux;
+        let _ = writeln!(out, "plugin {} ({}-{})", plugin.id, plugin.idx, plugin.name);
+        let _ = writeln!(out, "  mux: frames in {} out {} dropped {} unknown conn {}, bytes in {} out {}",
+            mux.frames_received, mux.frames_sent, mux.dropped_frames, mux.unknown_conn_frames,
+            mux.bytes_received, mux.bytes_sent);
+        for (rpc, stats) in &plugin.rpcs {
+            let latency = &stats.latency;
+            let avg_us = latency.sum_us / latency.count().max(1);
+            let _ = writeln!(out, "  {}: calls {} errors {} timeouts {}, latency avg {}us max {}us p50 {} p90 {} p99 {}",
+                rpc, stats.calls, stats.errors, stats.timeouts, avg_us, latency.max_us,
+                format_ms(latency.quantile(0.5)), format_ms(latency.quantile(0.9)), format_ms(latency.quantile(0.99)));
+        }
+    }
+    Ok(out)
+}
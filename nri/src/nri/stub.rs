@@ -0,0 +1,260 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// stub is the plugin side of NRI, for writing plugins in Rust. A plugin
+// implements the Plugin trait, whose handlers do nothing by default, and
+// runs it with a Stub:
+//
+//   let stub = stub::connect("my-plugin", "50", Arc::new(MyPlugin))?;
+//   println!("plugin stopped: {}", stub.wait());
+//
+// The stub talks to the runtime over the same mux as the runtime side: it
+// serves the plugin service on conn 1 and calls the runtime service on
+// conn 2.
+
+use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::nri::error::{Error, Result};
+use crate::nri::mux::{Mux, PLUGIN_SERVICE_CONN, RUNTIME_SERVICE_CONN};
+use crate::nri::server::{self, Server};
+use crate::protocols::{nri, nri_ttrpc};
+
+pub const PLUGIN_NAME_ENV: &str = "NRI_PLUGIN_NAME";
+pub const PLUGIN_IDX_ENV: &str = "NRI_PLUGIN_IDX";
+pub const PLUGIN_SOCKET_ENV: &str = "NRI_PLUGIN_SOCKET";
+pub const DEFAULT_SOCKET_PATH: &str = "/var/run/nri/nri.sock";
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Plugin is implemented by NRI plugins. The handlers are called by the
+// runtime once the plugin is registered; the ones not implemented do
+// nothing. A plugin subscribing for no events in configure gets all of them.
+pub trait Plugin: Send + Sync {
+    fn configure(&self, _req: &nri::ConfigureRequest) -> Result<nri::ConfigureResponse> {
+        Ok(nri::ConfigureResponse::new())
+    }
+
+    fn synchronize(&self, _req: &nri::SynchronizeRequest) -> Result<nri::SynchronizeResponse> {
+        Ok(nri::SynchronizeResponse::new())
+    }
+
+    fn shutdown(&self) {}
+
+    fn create_container(&self, _req: &nri::CreateContainerRequest) -> Result<nri::CreateContainerResponse> {
+        Ok(nri::CreateContainerResponse::new())
+    }
+
+    fn update_container(&self, _req: &nri::UpdateContainerRequest) -> Result<nri::UpdateContainerResponse> {
+        Ok(nri::UpdateContainerResponse::new())
+    }
+
+    fn stop_container(&self, _req: &nri::StopContainerRequest) -> Result<nri::StopContainerResponse> {
+        Ok(nri::StopContainerResponse::new())
+    }
+
+    fn update_pod_sandbox(&self, _req: &nri::UpdatePodSandboxRequest) -> Result<nri::UpdatePodSandboxResponse> {
+        Ok(nri::UpdatePodSandboxResponse::new())
+    }
+
+    fn state_change(&self, _event: &nri::StateChangeEvent) -> Result<()> {
+        Ok(())
+    }
+
+    fn validate_container_adjustment(&self, _req: &nri::ValidateContainerAdjustmentRequest)
+        -> Result<nri::ValidateContainerAdjustmentResponse> {
+        Ok(nri::ValidateContainerAdjustmentResponse::new())
+    }
+}
+
+// PluginService serves the plugin service with a Plugin.
+struct PluginService {
+    plugin: Arc<dyn Plugin>,
+}
+
+fn to_ttrpc_error(e: Error) -> ttrpc::Error {
+    ttrpc::Error::Others(e.to_string())
+}
+
+impl nri_ttrpc::Plugin for PluginService {
+    fn configure(&self, _ctx: &ttrpc::TtrpcContext, req: nri::ConfigureRequest)
+        -> ttrpc::Result<nri::ConfigureResponse> {
+        self.plugin.configure(&req).map_err(to_ttrpc_error)
+    }
+
+    fn synchronize(&self, _ctx: &ttrpc::TtrpcContext, req: nri::SynchronizeRequest)
+        -> ttrpc::Result<nri::SynchronizeResponse> {
+        self.plugin.synchronize(&req).map_err(to_ttrpc_error)
+    }
+
+    fn shutdown(&self, _ctx: &ttrpc::TtrpcContext, _req: nri::Empty) -> ttrpc::Result<nri::Empty> {
+        self.plugin.shutdown();
+        Ok(nri::Empty::new())
+    }
+
+    fn create_container(&self, _ctx: &ttrpc::TtrpcContext, req: nri::CreateContainerRequest)
+        -> ttrpc::Result<nri::CreateContainerResponse> {
+        self.plugin.create_container(&req).map_err(to_ttrpc_error)
+    }
+
+    fn update_container(&self, _ctx: &ttrpc::TtrpcContext, req: nri::UpdateContainerRequest)
+        -> ttrpc::Result<nri::UpdateContainerResponse> {
+        self.plugin.update_container(&req).map_err(to_ttrpc_error)
+    }
+
+    fn stop_container(&self, _ctx: &ttrpc::TtrpcContext, req: nri::StopContainerRequest)
+        -> ttrpc::Result<nri::StopContainerResponse> {
+        self.plugin.stop_container(&req).map_err(to_ttrpc_error)
+    }
+
+    fn update_pod_sandbox(&self, _ctx: &ttrpc::TtrpcContext, req: nri::UpdatePodSandboxRequest)
+        -> ttrpc::Result<nri::UpdatePodSandboxResponse> {
+        self.plugin.update_pod_sandbox(&req).map_err(to_ttrpc_error)
+    }
+
+    fn state_change(&self, _ctx: &ttrpc::TtrpcContext, req: nri::StateChangeEvent) -> ttrpc::Result<nri::Empty> {
+        self.plugin.state_change(&req).map_err(to_ttrpc_error)?;
+        Ok(nri::Empty::new())
+    }
+
+    fn validate_container_adjustment(&self, _ctx: &ttrpc::TtrpcContext, req: nri::ValidateContainerAdjustmentRequest)
+        -> ttrpc::Result<nri::ValidateContainerAdjustmentResponse> {
+        self.plugin.validate_container_adjustment(&req).map_err(to_ttrpc_error)
+    }
+}
+
+// Stub is a plugin connected and registered to the runtime.
+pub struct Stub {
+    name: String,
+    idx: String,
+    mux: Arc<Mux>,
+    runtime: nri_ttrpc::RuntimeClient,
+    server: Option<Server>,
+    closed: Arc<(Mutex<Option<String>>, Condvar)>,
+}
+
+// connect connects the plugin the way upstream plugins do: through the fd
+// in NRI_PLUGIN_SOCKET, with the name and index in NRI_PLUGIN_NAME and
+// NRI_PLUGIN_IDX, if the runtime started the plugin, else through the
+// default socket with the name and index given.
+pub fn connect(name: &str, idx: &str, plugin: Arc<dyn Plugin>) -> Result<Stub> {
+    match std::env::var(PLUGIN_SOCKET_ENV) {
+        Ok(fd) => {
+            let fd: RawFd = fd.parse()
+                .map_err(|_| Error::InvalidArgument(format!("invalid {} {}", PLUGIN_SOCKET_ENV, fd)))?;
+            let name = std::env::var(PLUGIN_NAME_ENV).unwrap_or(name.to_string());
+            let idx = std::env::var(PLUGIN_IDX_ENV).unwrap_or(idx.to_string());
+            connect_fd(fd, &name, &idx, plugin)
+        },
+        Err(_) => connect_path(DEFAULT_SOCKET_PATH, name, idx, plugin),
+    }
+}
+
+// connect_path connects the plugin through the runtime socket at path.
+pub fn connect_path(path: &str, name: &str, idx: &str, plugin: Arc<dyn Plugin>) -> Result<Stub> {
+    let stream = UnixStream::connect(path)
+        .map_err(|e| Error::IOError(format!("connect {} error: {}", path, e)))?;
+    Stub::start(stream, name, idx, plugin)
+}
+
+// connect_fd connects the plugin through an inherited connection to the
+// runtime, and takes ownership of fd.
+pub fn connect_fd(fd: RawFd, name: &str, idx: &str, plugin: Arc<dyn Plugin>) -> Result<Stub> {
+    let stream = unsafe { UnixStream::from_raw_fd(fd) };
+    Stub::start(stream, name, idx, plugin)
+}
+
+impl Stub {
+    // start serves the plugin over the trunk and registers it.
+    pub fn start(trunk: UnixStream, name: &str, idx: &str, plugin: Arc<dyn Plugin>) -> Result<Stub> {
+        let (plugin1, plugin2) = UnixStream::pair()
+            .map_err(|e| Error::IOError(format!("create socket pair error: {}", e)))?;
+        let (runtime1, runtime2) = UnixStream::pair()
+            .map_err(|e| Error::IOError(format!("create socket pair error: {}", e)))?;
+
+        let mux = Mux::new(trunk)?;
+        mux.add_conn(PLUGIN_SERVICE_CONN, plugin1)?;
+        mux.add_conn(RUNTIME_SERVICE_CONN, runtime1)?;
+        let server = server::serve(plugin2, nri_ttrpc::create_plugin(Arc::new(PluginService { plugin })))?;
+        let runtime = nri_ttrpc::RuntimeClient::new(ttrpc::Client::new(runtime2.into_raw_fd())
+            .map_err(|e| Error::TtrpcError(format!("create client error: {}", e)))?);
+
+        let closed = Arc::new((Mutex::new(None), Condvar::new()));
+        let notify = closed.clone();
+        mux.set_close_handler(Box::new(move |reason| {
+            *notify.0.lock().unwrap() = Some(reason.to_string());
+            notify.1.notify_all();
+        }));
+        mux.start();
+
+        let stub = Stub {
+            name: name.to_string(),
+            idx: idx.to_string(),
+            mux,
+            runtime,
+            server: Some(server),
+            closed,
+        };
+        stub.register()?;
+        Ok(stub)
+    }
+
+    fn register(&self) -> Result<()> {
+        let mut req = nri::RegisterPluginRequest::new();
+        req.plugin_name = self.name.clone();
+        req.plugin_idx = self.idx.clone();
+        self.runtime.register_plugin(request_context(), &req)
+            .map_err(|e| Error::TtrpcError(format!("register plugin {}-{} error: {}", self.idx, self.name, e)))?;
+        Ok(())
+    }
+
+    // update_containers asks the runtime for unsolicited container updates
+    // and evictions, and returns the updates that failed.
+    pub fn update_containers(&self, update: Vec<nri::ContainerUpdate>, evict: Vec<nri::ContainerEviction>)
+        -> Result<Vec<nri::ContainerUpdate>> {
+        let mut req = nri::UpdateContainersRequest::new();
+        req.update = update;
+        req.evict = evict;
+        let res = self.runtime.update_containers(request_context(), &req)
+            .map_err(|e| Error::TtrpcError(format!("update containers error: {}", e)))?;
+        Ok(res.failed)
+    }
+
+    // wait blocks until the connection to the runtime is closed, and returns
+    // the reason.
+    pub fn wait(&self) -> String {
+        let (reason, cond) = &*self.closed;
+        let mut reason = reason.lock().unwrap();
+        while reason.is_none() {
+            reason = cond.wait(reason).unwrap();
+        }
+        reason.clone().unwrap()
+    }
+
+    pub fn close(&self) {
+        self.mux.close_with_reason("closed by plugin");
+    }
+}
+
+impl Drop for Stub {
+    fn drop(&mut self) {
+        self.close();
+        if let Some(server) = self.server.take() {
+            server.shutdown();
+        }
+    }
+}
+
+fn request_context() -> ttrpc::context::Context {
+    ttrpc::context::with_timeout(DEFAULT_REQUEST_TIMEOUT.as_nanos() as i64)
+}
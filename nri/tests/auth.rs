@@ -0,0 +1,91 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// Tests of the allow-list of the external plugin socket. The peers are this
+// test process, and the allow-list is set for the whole process, so the
+// cases run one after the other.
+
+mod common;
+
+use std::fs;
+use std::io::Read;
+use std::os::raw::c_int;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use isula_nri::nri::auth::{self, AllowList};
+use isula_nri::nri::plugin;
+
+use common::*;
+
+fn allow_list(uids: &[libc::uid_t], gids: &[libc::gid_t], exe_paths: &[PathBuf]) -> AllowList {
+    AllowList { uids: uids.to_vec(), gids: gids.to_vec(), exe_paths: exe_paths.to_vec() }
+}
+
+fn authorized(allow_list: AllowList) -> bool {
+    auth::set_allow_list(allow_list);
+    let (stream, _peer) = UnixStream::pair().unwrap();
+    auth::authorize(&stream).is_ok()
+}
+
+static CONNECTED: Mutex<Vec<(libc::pid_t, libc::uid_t)>> = Mutex::new(Vec::new());
+
+extern "C" fn connected(fd: c_int, pid: libc::pid_t, uid: libc::uid_t) -> c_int {
+    CONNECTED.lock().unwrap().push((pid, uid));
+    unsafe { libc::close(fd) };
+    0
+}
+
+#[test]
+fn allow_list_checks() {
+    let (uid, gid, pid) = unsafe { (libc::getuid(), libc::getgid(), libc::getpid()) };
+    let (other_uid, other_gid) = (uid.wrapping_add(1), gid.wrapping_add(1));
+    let exe = fs::read_link("/proc/self/exe").unwrap();
+    let other_exe = PathBuf::from("/nonexistent/plugin");
+
+    // Empty lists let anyone in.
+    auth::set_allow_list(AllowList::default());
+    let (stream, _peer) = UnixStream::pair().unwrap();
+    assert_eq!(auth::authorize(&stream).unwrap(), auth::PeerCred { pid, uid, gid });
+
+    // The uid or the gid has to be listed.
+    assert!(authorized(allow_list(&[uid], &[], &[])));
+    assert!(authorized(allow_list(&[other_uid], &[gid], &[])));
+    assert!(authorized(allow_list(&[], &[gid], &[])));
+    assert!(!authorized(allow_list(&[other_uid], &[], &[])));
+    assert!(!authorized(allow_list(&[], &[other_gid], &[])));
+    assert!(!authorized(allow_list(&[other_uid], &[other_gid], &[])));
+
+    // And the exe as well, if any is listed.
+    assert!(authorized(allow_list(&[], &[], std::slice::from_ref(&exe))));
+    assert!(authorized(allow_list(&[uid], &[], &[other_exe.clone(), exe.clone()])));
+    assert!(!authorized(allow_list(&[], &[], std::slice::from_ref(&other_exe))));
+    assert!(!authorized(allow_list(&[uid], &[], &[other_exe])));
+    assert!(!authorized(allow_list(&[other_uid], &[], &[exe])));
+
+    // Rejected peers are closed before the runtime hears of them.
+    let dir = TempDir::new("auth");
+    let socket = dir.path().join("nri.sock").to_str().unwrap().to_string();
+    plugin::external_service_start(&socket, Some(connected)).unwrap();
+    auth::set_allow_list(allow_list(&[other_uid], &[], &[]));
+    let mut rejected = UnixStream::connect(&socket).unwrap();
+    assert_eq!(rejected.read(&mut [0; 1]).unwrap(), 0);
+    assert!(CONNECTED.lock().unwrap().is_empty());
+
+    auth::set_allow_list(allow_list(&[uid], &[], &[]));
+    let mut accepted = UnixStream::connect(&socket).unwrap();
+    assert_eq!(accepted.read(&mut [0; 1]).unwrap(), 0);
+    assert_eq!(*CONNECTED.lock().unwrap(), vec![(pid, uid)]);
+    plugin::external_service_shutdown();
+    auth::set_allow_list(AllowList::default());
+}
@@ -0,0 +1,294 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// Tests of the nri_free_* functions. The responses are converted to the C
+// structs with every field set and handed to the free functions the way
+// iSulad does. They touch no socket nor thread, so that leaks and bad frees
+// show under Miri:
+//
+//     cargo +nightly miri test --test c_transfer_free
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use protobuf::{EnumOrUnknown, MessageField};
+
+use isula_nri::nri::adaptation::{Outcome, PluginResult};
+use isula_nri::nri::c_transfer;
+use isula_nri::nri::plugin::{PluginInfo, PluginState, PluginStatus, DEFAULT_RPC_TIMEOUTS};
+use isula_nri::protocols::nri;
+use isula_common::isula_data_types::to_c_char_ptr;
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+fn int64(value: i64) -> MessageField<nri::OptionalInt64> {
+    MessageField::some(nri::OptionalInt64 { value, ..Default::default() })
+}
+
+fn uint64(value: u64) -> MessageField<nri::OptionalUInt64> {
+    MessageField::some(nri::OptionalUInt64 { value, ..Default::default() })
+}
+
+fn uint32(value: u32) -> MessageField<nri::OptionalUInt32> {
+    MessageField::some(nri::OptionalUInt32 { value, ..Default::default() })
+}
+
+fn hook(path: &str) -> nri::Hook {
+    nri::Hook {
+        path: path.to_string(),
+        args: strings(&[path, "--check"]),
+        env: strings(&["A=1", "B=2"]),
+        timeout: MessageField::some(nri::OptionalInt { value: 5, ..Default::default() }),
+        ..Default::default()
+    }
+}
+
+fn resources() -> nri::LinuxResources {
+    nri::LinuxResources {
+        memory: MessageField::some(nri::LinuxMemory {
+            limit: int64(1 << 30),
+            reservation: int64(1 << 29),
+            swap: int64(1 << 31),
+            kernel: int64(1 << 20),
+            kernel_tcp: int64(1 << 20),
+            swappiness: uint64(60),
+            disable_oom_killer: MessageField::some(nri::OptionalBool { value: true, ..Default::default() }),
+            use_hierarchy: MessageField::some(nri::OptionalBool { value: false, ..Default::default() }),
+            ..Default::default()
+        }),
+        cpu: MessageField::some(nri::LinuxCPU {
+            shares: uint64(1024),
+            quota: int64(50000),
+            period: uint64(100000),
+            realtime_runtime: int64(1000),
+            realtime_period: uint64(2000),
+            cpus: "0-3".to_string(),
+            mems: "0".to_string(),
+            ..Default::default()
+        }),
+        hugepage_limits: vec![
+            nri::HugepageLimit { page_size: "2MB".to_string(), limit: 1 << 21, ..Default::default() },
+            nri::HugepageLimit { page_size: "1GB".to_string(), limit: 1 << 30, ..Default::default() },
+        ],
+        blockio_class: MessageField::some(nri::OptionalString { value: "slow".to_string(), ..Default::default() }),
+        rdt_class: MessageField::some(nri::OptionalString { value: "gold".to_string(), ..Default::default() }),
+        unified: [("memory.high", "1G"), ("pids.max", "100")].iter()
+            .map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        devices: vec![nri::LinuxDeviceCgroup {
+            allow: true,
+            type_: "c".to_string(),
+            major: int64(1),
+            minor: int64(3),
+            access: "rwm".to_string(),
+            ..Default::default()
+        }],
+        pids: MessageField::some(nri::LinuxPids { limit: 100, ..Default::default() }),
+        ..Default::default()
+    }
+}
+
+fn adjustment() -> nri::ContainerAdjustment {
+    nri::ContainerAdjustment {
+        annotations: [("owner", "test")].iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        mounts: vec![nri::Mount {
+            destination: "/data".to_string(),
+            type_: "bind".to_string(),
+            source: "/srv/data".to_string(),
+            options: strings(&["rbind", "ro"]),
+            ..Default::default()
+        }],
+        env: vec![nri::KeyValue { key: "FOO".to_string(), value: "bar".to_string(), ..Default::default() }],
+        hooks: MessageField::some(nri::Hooks {
+            prestart: vec![hook("/bin/prestart")],
+            create_runtime: vec![hook("/bin/create-runtime")],
+            create_container: vec![hook("/bin/create-container")],
+            start_container: vec![hook("/bin/start-container")],
+            poststart: vec![hook("/bin/poststart")],
+            poststop: vec![hook("/bin/poststop"), hook("/bin/cleanup")],
+            ..Default::default()
+        }),
+        linux: MessageField::some(nri::LinuxContainerAdjustment {
+            devices: vec![nri::LinuxDevice {
+                path: "/dev/null".to_string(),
+                type_: "c".to_string(),
+                major: 1,
+                minor: 3,
+                file_mode: MessageField::some(nri::OptionalFileMode { value: 0o666, ..Default::default() }),
+                uid: uint32(0),
+                gid: uint32(0),
+                ..Default::default()
+            }],
+            resources: MessageField::some(resources()),
+            cgroups_path: "/kubepods/test".to_string(),
+            oom_score_adj: MessageField::some(nri::OptionalInt { value: -100, ..Default::default() }),
+            io_priority: MessageField::some(nri::LinuxIOPriority {
+                class: EnumOrUnknown::new(nri::IOPrioClass::IOPRIO_CLASS_BE),
+                priority: 4,
+                ..Default::default()
+            }),
+            seccomp_policy: MessageField::some(nri::LinuxSeccomp {
+                default_action: "SCMP_ACT_ERRNO".to_string(),
+                default_errno: uint32(1),
+                architectures: strings(&["SCMP_ARCH_X86_64", "SCMP_ARCH_AARCH64"]),
+                flags: strings(&["SECCOMP_FILTER_FLAG_LOG"]),
+                listener_path: "/run/seccomp.sock".to_string(),
+                listener_metadata: "meta".to_string(),
+                syscalls: vec![nri::LinuxSyscall {
+                    names: strings(&["read", "write"]),
+                    action: "SCMP_ACT_ALLOW".to_string(),
+                    errno_ret: uint32(0),
+                    args: vec![nri::LinuxSeccompArg {
+                        index: 0,
+                        value: 1,
+                        value_two: 2,
+                        op: "SCMP_CMP_EQ".to_string(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            namespaces: vec![nri::LinuxNamespace {
+                type_: "network".to_string(),
+                path: "/proc/1/ns/net".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }),
+        rlimits: vec![nri::POSIXRlimit { type_: "RLIMIT_NOFILE".to_string(), hard: 1024, soft: 512, ..Default::default() }],
+        CDI_devices: vec![nri::CDIDevice { name: "vendor.com/gpu=0".to_string(), ..Default::default() }],
+        args: strings(&["/bin/sh", "-c", "true"]),
+        ..Default::default()
+    }
+}
+
+fn updates() -> Vec<nri::ContainerUpdate> {
+    vec![
+        nri::ContainerUpdate {
+            container_id: "ctr-1".to_string(),
+            linux: MessageField::some(nri::LinuxContainerUpdate {
+                resources: MessageField::some(resources()),
+                ..Default::default()
+            }),
+            ignore_failure: true,
+            ..Default::default()
+        },
+        nri::ContainerUpdate { container_id: "ctr-2".to_string(), ..Default::default() },
+    ]
+}
+
+fn evictions() -> Vec<nri::ContainerEviction> {
+    vec![nri::ContainerEviction { container_id: "ctr-3".to_string(), reason: "oom".to_string(), ..Default::default() }]
+}
+
+fn boxed<T>(value: T) -> *const T {
+    Box::into_raw(Box::new(value))
+}
+
+#[test]
+fn free_create_container_response() {
+    let resp = nri::CreateContainerResponse {
+        adjust: MessageField::some(adjustment()),
+        update: updates(),
+        evict: evictions(),
+        ..Default::default()
+    };
+    unsafe { isula_nri::nri_free_create_container_response(boxed(c_transfer::NriCreateContainerResponse::from(&resp))) };
+
+    let empty = nri::CreateContainerResponse::new();
+    unsafe { isula_nri::nri_free_create_container_response(boxed(c_transfer::NriCreateContainerResponse::from(&empty))) };
+}
+
+#[test]
+fn free_adjustment_after_round_trip() {
+    let adjust = adjustment();
+    let c_adjust = c_transfer::NriContainerAdjustment::from(&adjust);
+    assert_eq!(nri::ContainerAdjustment::from(&c_adjust), adjust);
+}
+
+#[test]
+fn free_security_profile_after_round_trip() {
+    let profile = nri::SecurityProfile {
+        profile_type: EnumOrUnknown::new(nri::security_profile::ProfileType::LOCALHOST),
+        localhost_ref: "profiles/strict.json".to_string(),
+        ..Default::default()
+    };
+    let c_profile = c_transfer::NriSecurityProfile::from(&profile);
+    assert_eq!(nri::SecurityProfile::from(&c_profile), profile);
+}
+
+#[test]
+fn free_update_and_stop_container_responses() {
+    let resp = nri::UpdateContainerResponse { update: updates(), evict: evictions(), ..Default::default() };
+    unsafe { isula_nri::nri_free_update_container_response(boxed(c_transfer::NriUpdateContainerResponse::from(&resp))) };
+
+    let resp = nri::StopContainerResponse { update: updates(), ..Default::default() };
+    unsafe { isula_nri::nri_free_stop_container_response(boxed(c_transfer::NriStopContainerResponse::from(&resp))) };
+}
+
+#[test]
+fn free_plugin_call_responses() {
+    let resp = nri::ConfigureResponse { events: 0x7f, ..Default::default() };
+    unsafe { isula_nri::nri_free_configure_response(boxed(c_transfer::NriConfigureResponse::from(&resp))) };
+
+    let resp = nri::SynchronizeResponse { update: updates(), more: true, ..Default::default() };
+    unsafe { isula_nri::nri_free_synchronize_response(boxed(c_transfer::NriSynchronizeResponse::from(&resp))) };
+
+    let resp = nri::UpdatePodSandboxResponse::new();
+    unsafe { isula_nri::nri_free_update_pod_sandbox_response(boxed(c_transfer::NriUpdatePodSandboxResponse::from(&resp))) };
+
+    let resp = nri::ValidateContainerAdjustmentResponse {
+        reject: true,
+        reason: "mounts /data".to_string(),
+        ..Default::default()
+    };
+    unsafe { isula_nri::nri_free_validate_container_adjustment_response(
+        boxed(c_transfer::NriValidateContainerAdjustmentResponse::from(&resp))) };
+}
+
+#[test]
+fn free_broadcast_response_and_plugin_list() {
+    let plugin = |idx: &str| PluginInfo {
+        id: format!("{}-test", idx),
+        name: "test".to_string(),
+        idx: idx.to_string(),
+    };
+    let results = vec![
+        PluginResult { plugin: plugin("10"), outcome: Outcome::Ok },
+        PluginResult { plugin: plugin("20"), outcome: Outcome::Failed("refused".to_string()) },
+        PluginResult { plugin: plugin("30"), outcome: Outcome::TimedOut },
+    ];
+    unsafe { isula_nri::nri_free_broadcast_response(boxed(c_transfer::NriBroadcastResponse::from(&results))) };
+
+    let statuses = vec![PluginStatus {
+        id: "10-test".to_string(),
+        name: "test".to_string(),
+        idx: "10".to_string(),
+        state: PluginState::Synchronized,
+        events: 0x7f,
+        timeouts: DEFAULT_RPC_TIMEOUTS,
+        mux_closed: false,
+        connected_at: UNIX_EPOCH + Duration::from_secs(1),
+    }];
+    unsafe { isula_nri::nri_free_plugin_status_list(boxed(c_transfer::NriPluginStatusList::from(&statuses))) };
+    unsafe { isula_nri::nri_free_plugin_status_list(boxed(c_transfer::NriPluginStatusList::from(&Vec::new()))) };
+}
+
+#[test]
+fn free_null_and_strings() {
+    unsafe { isula_nri::nri_free_configure_response(std::ptr::null()) };
+    unsafe { isula_nri::nri_free_create_container_response(std::ptr::null()) };
+    unsafe { isula_nri::nri_free_broadcast_response(std::ptr::null()) };
+    unsafe { isula_nri::nri_free_string(std::ptr::null()) };
+    unsafe { isula_nri::nri_free_string(to_c_char_ptr("10-test,20-test")) };
+}
@@ -0,0 +1,647 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// Harness of the end-to-end tests of the runtime side. The tests play
+// iSulad: they build requests as the C structs iSulad passes in, call the
+// exported nri_* functions and read the C structs handed back. The other end
+// of the socket pair is a scripted fake plugin speaking the mux framing and
+// the ttrpc plugin service, run by the plugin stub.
+
+#![allow(dead_code)]
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::fd::IntoRawFd;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use isula_nri::nri::c_transfer;
+use isula_nri::nri::error::{Error, Result};
+use isula_nri::nri::plugin::RpcTimeouts;
+use isula_nri::nri::stub::{self, Stub};
+use isula_nri::protocols::nri;
+
+pub const TIMEOUT: i64 = 2_000_000_000;
+
+// Mirrors of the C structs, laid out like iSulad declares them. Only the
+// fields the tests look at are typed, the others are opaque pointers.
+pub mod c {
+    use std::os::raw::{c_char, c_void};
+
+    use isula_nri::nri::plugin::RpcTimeouts;
+
+    #[repr(C)]
+    pub struct MapStringString {
+        pub key: *const *const c_char,
+        pub value: *const *const c_char,
+        pub len: usize,
+    }
+
+    #[repr(C)]
+    pub struct KeyValue {
+        pub key: *const c_char,
+        pub value: *const c_char,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct Container {
+        pub id: *const c_char,
+        pub pod_sandbox_id: *const c_char,
+        pub name: *const c_char,
+        pub state: i32,
+        pub labels: *const MapStringString,
+        pub annotations: *const MapStringString,
+        pub args: *const *const c_char,
+        pub args_len: usize,
+        pub env: *const *const c_char,
+        pub env_len: usize,
+        pub mounts: *const c_void,
+        pub mounts_len: usize,
+        pub hooks: *const c_void,
+        pub linux: *const c_void,
+        pub pid: u32,
+        pub rlimits: *const c_void,
+        pub rlimits_len: usize,
+        pub created_at: i64,
+        pub started_at: i64,
+        pub finished_at: i64,
+        pub exit_code: i32,
+        pub status_reason: *const c_char,
+        pub status_message: *const c_char,
+        pub cdi_devices: *const c_void,
+        pub cdi_devices_len: usize,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct PodSandbox {
+        pub id: *const c_char,
+        pub name: *const c_char,
+        pub uid: *const c_char,
+        pub namespace: *const c_char,
+        pub labels: *const MapStringString,
+        pub annotations: *const MapStringString,
+        pub runtime_handler: *const c_char,
+        pub linux: *const c_void,
+        pub pid: u32,
+        pub ips: *const *const c_char,
+        pub ips_len: usize,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct ContainerUpdate {
+        pub container_id: *const c_char,
+        pub linux: *const c_void,
+        pub ignore_failure: u8,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct ContainerEviction {
+        pub container_id: *const c_char,
+        pub reason: *const c_char,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct ContainerAdjustment {
+        pub annotations: *const MapStringString,
+        pub mounts: *const c_void,
+        pub mounts_len: usize,
+        pub env: *const *const KeyValue,
+        pub env_len: usize,
+        pub hooks: *const c_void,
+        pub linux: *const c_void,
+        pub rlimits: *const c_void,
+        pub rlimits_len: usize,
+        pub cdi_devices: *const c_void,
+        pub cdi_devices_len: usize,
+        pub args: *const *const c_char,
+        pub args_len: usize,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct RegisterPluginRequest {
+        pub plugin_name: *const c_char,
+        pub plugin_idx: *const c_char,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct UpdateContainersRequest {
+        pub container_updates: *const *const ContainerUpdate,
+        pub container_updates_len: usize,
+        pub evict: *const *const ContainerEviction,
+        pub evict_len: usize,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct UpdateContainersResponse {
+        pub failed: *const *const ContainerUpdate,
+        pub failed_len: usize,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct ConfigureRequest {
+        pub config: *const c_char,
+        pub runtime_name: *const c_char,
+        pub runtime_version: *const c_char,
+        pub registration_timeout: i64,
+        pub request_timeout: i64,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct ConfigureResponse {
+        pub events: i32,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct SynchronizeRequest {
+        pub pods: *const *const PodSandbox,
+        pub pods_len: usize,
+        pub containers: *const *const Container,
+        pub containers_len: usize,
+        pub more: u8,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct SynchronizeResponse {
+        pub update: *const *const ContainerUpdate,
+        pub update_len: usize,
+        pub more: u8,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct CreateContainerRequest {
+        pub pod: *const PodSandbox,
+        pub container: *const Container,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct CreateContainerResponse {
+        pub adjust: *const ContainerAdjustment,
+        pub update: *const *const ContainerUpdate,
+        pub update_len: usize,
+        pub evict: *const *const ContainerEviction,
+        pub evict_len: usize,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct UpdateContainerRequest {
+        pub pod: *const PodSandbox,
+        pub container: *const Container,
+        pub linux_resources: *const c_void,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct UpdateContainerResponse {
+        pub update: *const *const ContainerUpdate,
+        pub update_len: usize,
+        pub evict: *const *const ContainerEviction,
+        pub evict_len: usize,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct StopContainerRequest {
+        pub pod: *const PodSandbox,
+        pub container: *const Container,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct StopContainerResponse {
+        pub update: *const *const ContainerUpdate,
+        pub update_len: usize,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct PluginResult {
+        pub plugin_id: *const c_char,
+        pub plugin_name: *const c_char,
+        pub plugin_idx: *const c_char,
+        pub result: i32,
+        pub error: *const c_char,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct BroadcastResponse {
+        pub results: *const *const PluginResult,
+        pub results_len: usize,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct PluginStatus {
+        pub plugin_id: *const c_char,
+        pub plugin_name: *const c_char,
+        pub plugin_idx: *const c_char,
+        pub state: i32,
+        pub events: i32,
+        pub timeouts: RpcTimeouts,
+        pub mux_closed: u8,
+        pub connected_at: i64,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct PluginStatusList {
+        pub plugins: *const *const PluginStatus,
+        pub plugins_len: usize,
+        pub residual: *const c_void,
+    }
+
+    #[repr(C)]
+    pub struct StateChangeEvent {
+        pub event: i32,
+        pub pod: *const PodSandbox,
+        pub container: *const Container,
+        pub residual: *const c_void,
+    }
+}
+
+pub fn zeroed<T>() -> T {
+    // The mirrors are plain integers and pointers, all zero is all unset.
+    unsafe { std::mem::zeroed() }
+}
+
+pub fn string(s: *const c_char) -> String {
+    assert!(!s.is_null());
+    unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_string()
+}
+
+pub fn slice<'a, T>(items: *const *const T, len: usize) -> Vec<&'a T> {
+    if len == 0 {
+        return Vec::new();
+    }
+    assert!(!items.is_null());
+    unsafe { std::slice::from_raw_parts(items, len) }.iter()
+        .map(|item| unsafe { item.as_ref() }.unwrap())
+        .collect()
+}
+
+pub fn strings(items: *const *const c_char, len: usize) -> Vec<String> {
+    if len == 0 {
+        return Vec::new();
+    }
+    unsafe { std::slice::from_raw_parts(items, len) }.iter().map(|s| string(*s)).collect()
+}
+
+pub fn updates(items: *const *const c::ContainerUpdate, len: usize) -> Vec<(String, bool)> {
+    slice(items, len).iter().map(|u| (string(u.container_id), u.ignore_failure != 0)).collect()
+}
+
+pub fn evictions(items: *const *const c::ContainerEviction, len: usize) -> Vec<(String, String)> {
+    slice(items, len).iter().map(|e| (string(e.container_id), string(e.reason))).collect()
+}
+
+// Runtime callbacks, recording what they are called with.
+
+pub static REGISTERED: Mutex<Vec<(String, String, String)>> = Mutex::new(Vec::new());
+// The plugin id, the updated containers with their outcome and the evictions.
+pub type Update = (String, Vec<(String, bool)>, Vec<(String, String)>);
+
+pub static UPDATES: Mutex<Vec<Update>> = Mutex::new(Vec::new());
+pub static CLOSED: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+pub extern "C" fn register_plugin(plugin_id: *const c_char, req: *const c_transfer::NriRegisterPluginRequest) -> c_int {
+    let req = unsafe { &*(req as *const c::RegisterPluginRequest) };
+    REGISTERED.lock().unwrap().push((string(plugin_id), string(req.plugin_name), string(req.plugin_idx)));
+    0
+}
+
+pub extern "C" fn update_containers(plugin_id: *const c_char, req: *const c_transfer::NriUpdateContainersRequest,
+    resp: *mut *mut c_transfer::NriUpdateContainersResponse) -> c_int {
+    let req = unsafe { &*(req as *const c::UpdateContainersRequest) };
+    UPDATES.lock().unwrap().push((string(plugin_id),
+        updates(req.container_updates, req.container_updates_len), evictions(req.evict, req.evict_len)));
+
+    // Like iSulad, the response is allocated with the C allocator, and the
+    // containers it does not know fail.
+    let failed: Vec<_> = slice(req.container_updates, req.container_updates_len).into_iter()
+        .filter(|u| string(u.container_id).starts_with("missing-"))
+        .collect();
+    unsafe {
+        let c_resp = libc::calloc(1, std::mem::size_of::<c::UpdateContainersResponse>()) as *mut c::UpdateContainersResponse;
+        if !failed.is_empty() {
+            let items = libc::calloc(failed.len(), std::mem::size_of::<*const c::ContainerUpdate>())
+                as *mut *const c::ContainerUpdate;
+            for (i, u) in failed.iter().enumerate() {
+                let item = libc::calloc(1, std::mem::size_of::<c::ContainerUpdate>()) as *mut c::ContainerUpdate;
+                (*item).container_id = libc::strdup(u.container_id);
+                (*item).ignore_failure = u.ignore_failure;
+                *items.add(i) = item;
+            }
+            (*c_resp).failed = items;
+            (*c_resp).failed_len = failed.len();
+        }
+        *resp = c_resp as *mut c_transfer::NriUpdateContainersResponse;
+    }
+    0
+}
+
+pub static FREED_UPDATE_RESPONSES: Mutex<usize> = Mutex::new(0);
+
+pub extern "C" fn free_update_containers_response(resp: *mut c_transfer::NriUpdateContainersResponse) {
+    let resp = resp as *mut c::UpdateContainersResponse;
+    unsafe {
+        for u in slice((*resp).failed, (*resp).failed_len) {
+            libc::free(u.container_id as *mut libc::c_void);
+            libc::free(u as *const c::ContainerUpdate as *mut libc::c_void);
+        }
+        libc::free((*resp).failed as *mut libc::c_void);
+        libc::free(resp as *mut libc::c_void);
+    }
+    *FREED_UPDATE_RESPONSES.lock().unwrap() += 1;
+}
+
+pub extern "C" fn plugin_closed(plugin_id: *const c_char, reason: *const c_char) {
+    CLOSED.lock().unwrap().push((string(plugin_id), string(reason)));
+}
+
+pub fn init() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let callbacks = c_transfer::NriRuntimeCallbacks {
+            register_plugin: Some(register_plugin),
+            update_containers: Some(update_containers),
+            plugin_closed: Some(plugin_closed),
+            free_update_containers_response: Some(free_update_containers_response),
+        };
+        assert_eq!(isula_nri::nri_runtime_service_init(callbacks), 0);
+    });
+}
+
+// The fake plugin answers with what the script says and records the calls
+// it gets.
+#[derive(Default)]
+pub struct Script {
+    pub events: i32,
+    pub sync_update: Vec<nri::ContainerUpdate>,
+    pub create: nri::CreateContainerResponse,
+    pub update: nri::UpdateContainerResponse,
+    pub stop: nri::StopContainerResponse,
+    // Time the plugin takes to answer CreateContainer, Shutdown and
+    // StateChange.
+    pub create_delay: Duration,
+    pub shutdown_delay: Duration,
+    pub state_change_delay: Duration,
+    pub state_change_error: Option<String>,
+}
+
+pub struct FakePlugin {
+    pub script: Script,
+    pub calls: Mutex<Vec<String>>,
+    // The requests of the container calls, as the plugin got them.
+    pub created: Mutex<Vec<nri::CreateContainerRequest>>,
+    pub updated: Mutex<Vec<nri::UpdateContainerRequest>>,
+}
+
+impl FakePlugin {
+    fn record(&self, call: String) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+pub fn container_id(container: &protobuf::MessageField<nri::Container>) -> String {
+    container.as_ref().map(|c| c.id.clone()).unwrap_or_default()
+}
+
+impl stub::Plugin for FakePlugin {
+    fn configure(&self, req: &nri::ConfigureRequest) -> Result<nri::ConfigureResponse> {
+        self.record(format!("configure {} {} {}", req.runtime_name, req.runtime_version, req.config));
+        let mut res = nri::ConfigureResponse::new();
+        res.events = self.script.events;
+        Ok(res)
+    }
+
+    fn synchronize(&self, req: &nri::SynchronizeRequest) -> Result<nri::SynchronizeResponse> {
+        let ids: Vec<String> = req.containers.iter().map(|c| c.id.clone()).collect();
+        self.record(format!("synchronize {}", ids.join(",")));
+        let mut res = nri::SynchronizeResponse::new();
+        res.update = self.script.sync_update.clone();
+        Ok(res)
+    }
+
+    fn shutdown(&self) {
+        thread::sleep(self.script.shutdown_delay);
+        self.record("shutdown".to_string());
+    }
+
+    fn create_container(&self, req: &nri::CreateContainerRequest) -> Result<nri::CreateContainerResponse> {
+        thread::sleep(self.script.create_delay);
+        self.record(format!("create_container {}", container_id(&req.container)));
+        self.created.lock().unwrap().push(req.clone());
+        Ok(self.script.create.clone())
+    }
+
+    fn update_container(&self, req: &nri::UpdateContainerRequest) -> Result<nri::UpdateContainerResponse> {
+        self.record(format!("update_container {}", container_id(&req.container)));
+        self.updated.lock().unwrap().push(req.clone());
+        Ok(self.script.update.clone())
+    }
+
+    fn stop_container(&self, req: &nri::StopContainerRequest) -> Result<nri::StopContainerResponse> {
+        self.record(format!("stop_container {}", container_id(&req.container)));
+        Ok(self.script.stop.clone())
+    }
+
+    fn state_change(&self, event: &nri::StateChangeEvent) -> Result<()> {
+        let event = event.event.enum_value().map_err(|e| Error::InvalidArgument(format!("event {}", e)))?;
+        thread::sleep(self.script.state_change_delay);
+        self.record(format!("state_change {:?}", event));
+        match &self.script.state_change_error {
+            Some(e) => Err(Error::Other(e.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct Harness {
+    pub id: CString,
+    pub plugin: Arc<FakePlugin>,
+    pub stub: Stub,
+}
+
+impl Harness {
+    // connect connects a fake plugin under id, the way iSulad connects the
+    // plugins it starts, and waits for it to register.
+    pub fn connect(id: &str, name: &str, idx: &str, script: Script) -> Harness {
+        Harness::start(id, name, idx, script, |id, fd| isula_nri::nri_plugin_connect(id, fd, TIMEOUT))
+    }
+
+    // connect_with_timeouts connects a fake plugin with timeouts per RPC.
+    pub fn connect_with_timeouts(id: &str, name: &str, idx: &str, script: Script, timeouts: &RpcTimeouts) -> Harness {
+        Harness::start(id, name, idx, script,
+            |id, fd| unsafe { isula_nri::nri_plugin_connect_with_timeouts(id, fd, timeouts) })
+    }
+
+    fn start(id: &str, name: &str, idx: &str, script: Script, connect: impl FnOnce(*const c_char, c_int) -> c_int)
+        -> Harness {
+        init();
+        let (local, peer) = UnixStream::pair().unwrap();
+        let plugin = Arc::new(FakePlugin {
+            script,
+            calls: Mutex::new(Vec::new()),
+            created: Mutex::new(Vec::new()),
+            updated: Mutex::new(Vec::new()),
+        });
+        let fake = plugin.clone();
+        let (name, idx) = (name.to_string(), idx.to_string());
+        let registering = thread::spawn(move || Stub::start(peer, &name, &idx, fake).unwrap());
+
+        let id = CString::new(id).unwrap();
+        assert_eq!(connect(id.as_ptr(), local.into_raw_fd()), 0);
+        let stub = registering.join().unwrap();
+        Harness { id, plugin, stub }
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.plugin.calls.lock().unwrap().clone()
+    }
+
+    pub fn state(&self) -> c_int {
+        unsafe { isula_nri::nri_plugin_state(self.id.as_ptr()) }
+    }
+
+    pub fn configure(&self) -> c_int {
+        let runtime_name = CString::new("isulad").unwrap();
+        let runtime_version = CString::new("2.1.5").unwrap();
+        let config = CString::new("key: value").unwrap();
+        let req = c::ConfigureRequest {
+            config: config.as_ptr(),
+            runtime_name: runtime_name.as_ptr(),
+            runtime_version: runtime_version.as_ptr(),
+            registration_timeout: 5000,
+            request_timeout: 2000,
+            residual: std::ptr::null(),
+        };
+        let mut resp: *const c_transfer::NriConfigureResponse = std::ptr::null();
+        let ret = unsafe { isula_nri::nri_plugin_configure(self.id.as_ptr(),
+            &req as *const c::ConfigureRequest as *const c_transfer::NriConfigureRequest, &mut resp) };
+        if ret == 0 {
+            let events = unsafe { (*(resp as *const c::ConfigureResponse)).events };
+            assert_eq!(events, self.plugin.script.events);
+            unsafe { isula_nri::nri_free_configure_response(resp) };
+        }
+        ret
+    }
+
+    pub fn synchronize(&self, containers: &[&str]) -> Vec<(String, bool)> {
+        let ids: Vec<CString> = containers.iter().map(|id| CString::new(*id).unwrap()).collect();
+        let containers: Vec<c::Container> = ids.iter()
+            .map(|id| c::Container { id: id.as_ptr(), ..zeroed() })
+            .collect();
+        let container_ptrs: Vec<*const c::Container> = containers.iter().map(|c| c as *const c::Container).collect();
+        let req = c::SynchronizeRequest {
+            containers: container_ptrs.as_ptr(),
+            containers_len: container_ptrs.len(),
+            ..zeroed()
+        };
+        let mut resp: *const c_transfer::NriSynchronizeResponse = std::ptr::null();
+        assert_eq!(unsafe { isula_nri::nri_plugin_synchronize(self.id.as_ptr(),
+            &req as *const c::SynchronizeRequest as *const c_transfer::NriSynchronizeRequest, &mut resp) }, 0);
+        let c_resp = unsafe { &*(resp as *const c::SynchronizeResponse) };
+        assert_eq!(c_resp.more, 0);
+        let res = updates(c_resp.update, c_resp.update_len);
+        unsafe { isula_nri::nri_free_synchronize_response(resp) };
+        res
+    }
+
+    pub fn state_change(&self, event: nri::Event, container: &str) -> c_int {
+        let id = CString::new(container).unwrap();
+        let container = c::Container { id: id.as_ptr(), ..zeroed() };
+        let req = c::StateChangeEvent { event: event as i32, container: &container, ..zeroed() };
+        unsafe { isula_nri::nri_plugin_state_change(self.id.as_ptr(),
+            &req as *const c::StateChangeEvent as *const c_transfer::NriStateChangeEvent) }
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        isula_nri::nri_plugin_disconnect(self.id.as_ptr());
+    }
+}
+
+pub fn container_update(id: &str, ignore_failure: bool) -> nri::ContainerUpdate {
+    let mut update = nri::ContainerUpdate::new();
+    update.container_id = id.to_string();
+    update.ignore_failure = ignore_failure;
+    update
+}
+
+pub fn container_eviction(id: &str, reason: &str) -> nri::ContainerEviction {
+    let mut evict = nri::ContainerEviction::new();
+    evict.container_id = id.to_string();
+    evict.reason = reason.to_string();
+    evict
+}
+
+// wait_for polls until cond holds or a few seconds are up.
+pub fn wait_for(cond: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if cond() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    cond()
+}
+
+// TempDir is a directory of the test, removed with its content when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("isula-nri-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn write(&self, name: &str, content: &str, mode: u32) -> PathBuf {
+        let path = self.0.join(name);
+        fs::write(&path, content).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// Fuzz tests of the mux frame parser. The inputs come from a seeded PRNG so
+// failures reproduce; NRI_FUZZ_SEED and NRI_FUZZ_ITERATIONS run other or
+// longer campaigns.
+
+use isula_nri::nri::mux::{ConnId, FrameDecoder, FrameError};
+
+const HEADER_LEN: usize = 8;
+const MAX_FRAME_SIZE: usize = 4096;
+
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Rng {
+        let seed = std::env::var("NRI_FUZZ_SEED").ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or(0x5eed_1234_abcd_ef01);
+        println!("seed {}", seed);
+        Rng(seed | 1)
+    }
+
+    // xorshift64*
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+fn iterations() -> usize {
+    std::env::var("NRI_FUZZ_ITERATIONS").ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(2000)
+}
+
+fn encode(cid: ConnId, data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + data.len());
+    frame.extend_from_slice(&cid.to_be_bytes());
+    frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+// Feed data in random chunks, stopping at the first error.
+fn feed_chunks(rng: &mut Rng, decoder: &mut FrameDecoder, data: &[u8],
+    frames: &mut Vec<(ConnId, Vec<u8>)>) -> Result<(), FrameError> {
+    let mut offset = 0;
+    while offset < data.len() {
+        let end = (offset + 1 + rng.below(3 * MAX_FRAME_SIZE)).min(data.len());
+        decoder.feed(&data[offset..end], MAX_FRAME_SIZE, |cid, data| {
+            frames.push((cid, data.to_vec()));
+            Ok(())
+        })?;
+        assert!(decoder.buffered() < HEADER_LEN + MAX_FRAME_SIZE);
+        offset = end;
+    }
+    Ok(())
+}
+
+#[test]
+fn random_input_never_overbuffers() {
+    let mut rng = Rng::new();
+    for _ in 0..iterations() {
+        let len = rng.below(4 * MAX_FRAME_SIZE);
+        let mut data = rng.bytes(len);
+        // Make some headers plausible so that parsing gets past them.
+        if data.len() >= HEADER_LEN && rng.below(2) == 0 {
+            data[0..4].copy_from_slice(&(1 + rng.below(3) as u32).to_be_bytes());
+            data[4..8].copy_from_slice(&(rng.below(MAX_FRAME_SIZE + 1) as u32).to_be_bytes());
+        }
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        if let Err(e) = feed_chunks(&mut rng, &mut decoder, &data, &mut frames) {
+            assert!(matches!(e, FrameError::BadHeader(_)), "{}", e);
+        }
+        for (cid, data) in frames {
+            assert_ne!(cid, 0);
+            assert!(data.len() <= MAX_FRAME_SIZE);
+        }
+    }
+}
+
+#[test]
+fn valid_frames_round_trip() {
+    let mut rng = Rng::new();
+    for _ in 0..iterations() {
+        let mut expected = Vec::new();
+        let mut data = Vec::new();
+        for _ in 0..rng.below(16) {
+            let cid = 1 + rng.below(4) as ConnId;
+            let len = match rng.below(4) {
+                0 => 0,
+                1 => MAX_FRAME_SIZE,
+                _ => rng.below(MAX_FRAME_SIZE),
+            };
+            let payload = rng.bytes(len);
+            data.extend_from_slice(&encode(cid, &payload));
+            expected.push((cid, payload));
+        }
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        feed_chunks(&mut rng, &mut decoder, &data, &mut frames).unwrap();
+        assert_eq!(frames, expected);
+        assert_eq!(decoder.buffered(), 0);
+    }
+}
+
+#[test]
+fn oversize_frame_rejected_on_header() {
+    let mut decoder = FrameDecoder::new();
+    let mut hdr = encode(1, &[]);
+    hdr[4..8].copy_from_slice(&(MAX_FRAME_SIZE as u32 + 1).to_be_bytes());
+    let res = decoder.feed(&hdr, MAX_FRAME_SIZE, |_, _| panic!("oversize frame delivered"));
+    assert!(matches!(res, Err(FrameError::BadHeader(_))));
+
+    let mut decoder = FrameDecoder::new();
+    hdr[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+    let res = decoder.feed(&hdr, MAX_FRAME_SIZE, |_, _| panic!("oversize frame delivered"));
+    assert!(matches!(res, Err(FrameError::BadHeader(_))));
+    assert!(decoder.buffered() <= HEADER_LEN);
+}
+
+#[test]
+fn reserved_conn_id_rejected() {
+    let mut decoder = FrameDecoder::new();
+    let mut delivered = 0;
+    let mut data = encode(1, b"ok");
+    data.extend_from_slice(&encode(0, b"bad"));
+    let res = decoder.feed(&data, MAX_FRAME_SIZE, |_, _| {
+        delivered += 1;
+        Ok(())
+    });
+    assert!(matches!(res, Err(FrameError::BadHeader(_))));
+    assert_eq!(delivered, 1);
+}
+
+#[test]
+fn deliver_error_reported() {
+    let mut decoder = FrameDecoder::new();
+    let res = decoder.feed(&encode(2, b"data"), MAX_FRAME_SIZE, |_, _| Err("conn 2 write error".to_string()));
+    assert_eq!(res, Err(FrameError::Deliver("conn 2 write error".to_string())));
+}
@@ -0,0 +1,112 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// Tests of the mux trunk hanging up: before the mux is started, and while
+// its connections lag behind.
+
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use isula_nri::nri::mux::{Mux, PLUGIN_SERVICE_CONN};
+
+const FRAME_DATA_LEN: usize = 4096;
+
+fn close_channel(mux: &Mux) -> mpsc::Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    mux.set_close_handler(Box::new(move |reason| {
+        let _unused = tx.send(reason.to_string());
+    }));
+    rx
+}
+
+#[test]
+fn hangup_before_start() {
+    let (trunk, peer) = UnixStream::pair().unwrap();
+    let mux = Mux::new(trunk).unwrap();
+    let closed = close_channel(&mux);
+    drop(peer);
+    assert_eq!(closed.recv_timeout(Duration::from_secs(5)).unwrap(), "trunk closed before start");
+    assert!(mux.is_closed());
+
+    // Closed before the handler is set, it is called as it is set.
+    let (trunk, peer) = UnixStream::pair().unwrap();
+    let mux = Mux::new(trunk).unwrap();
+    drop(peer);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !mux.is_closed() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let closed = close_channel(&mux);
+    assert_eq!(closed.recv_timeout(Duration::from_secs(5)).unwrap(), "trunk closed before start");
+}
+
+// write_frames writes frames to the trunk until the mux stops reading, and
+// returns the data of the frames written whole.
+fn write_frames(peer: &mut UnixStream) -> usize {
+    peer.set_nonblocking(true).unwrap();
+    let mut frame = Vec::with_capacity(8 + FRAME_DATA_LEN);
+    frame.extend_from_slice(&PLUGIN_SERVICE_CONN.to_be_bytes());
+    frame.extend_from_slice(&(FRAME_DATA_LEN as u32).to_be_bytes());
+    frame.resize(8 + FRAME_DATA_LEN, 0x5a);
+
+    let mut sent = 0;
+    loop {
+        let mut written = 0;
+        let mut stuck_since = Instant::now();
+        while written < frame.len() {
+            match peer.write(&frame[written..]) {
+                Ok(cnt) => {
+                    written += cnt;
+                    stuck_since = Instant::now();
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    if stuck_since.elapsed() > Duration::from_millis(300) {
+                        return sent;
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                },
+                Err(e) => panic!("write trunk error: {}", e),
+            }
+        }
+        sent += FRAME_DATA_LEN;
+    }
+}
+
+#[test]
+fn hangup_while_lagging() {
+    let (trunk, mut peer) = UnixStream::pair().unwrap();
+    let (conn, mut conn_peer) = UnixStream::pair().unwrap();
+    let mux = Mux::new(trunk).unwrap();
+    mux.add_conn(PLUGIN_SERVICE_CONN, conn).unwrap();
+    let closed = close_channel(&mux);
+    mux.start();
+
+    // Nothing reads the connection, so the mux stops reading the trunk.
+    let sent = write_frames(&mut peer);
+    assert!(sent > 1024 * 1024);
+    drop(peer);
+
+    // The data left in the trunk is not read, nor the mux closed, before the
+    // connection catches up.
+    thread::sleep(Duration::from_millis(200));
+    assert!(!mux.is_closed());
+    assert!(mux.stats().bytes_received < (sent + sent / FRAME_DATA_LEN * 8) as u64);
+
+    // Once it does, the trunk is read to the end and the mux closed.
+    let mut buffer = vec![0; 64 * 1024];
+    while conn_peer.read(&mut buffer).unwrap() > 0 {}
+    assert_eq!(closed.recv_timeout(Duration::from_secs(5)).unwrap(), "trunk closed");
+    assert!(mux.stats().bytes_received >= (sent + sent / FRAME_DATA_LEN * 8) as u64);
+}
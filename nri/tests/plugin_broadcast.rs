@@ -0,0 +1,104 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// End-to-end test of broadcasting state change events. Events go to every
+// plugin of the process, so it runs in a test binary of its own.
+
+mod common;
+
+use std::ffi::CString;
+use std::time::{Duration, Instant};
+
+use isula_nri::nri::{adaptation, c_transfer};
+use isula_nri::nri::plugin::event_mask;
+use isula_nri::protocols::nri;
+
+use common::*;
+
+// The plugin id, "<index>-<name>", the outcome and the error of each plugin.
+type Results = Vec<(String, String, i32, Option<String>)>;
+
+fn broadcast(event: nri::Event, timeout_ms: u64) -> (i32, Results) {
+    let id = CString::new("ctr-0").unwrap();
+    let container = c::Container { id: id.as_ptr(), ..zeroed() };
+    let req = c::StateChangeEvent { event: event as i32, container: &container, ..zeroed() };
+    let mut resp: *const c_transfer::NriBroadcastResponse = std::ptr::null();
+    let ret = unsafe { isula_nri::nri_broadcast_state_change(
+        &req as *const c::StateChangeEvent as *const c_transfer::NriStateChangeEvent, timeout_ms, &mut resp) };
+    assert!(ret >= 0);
+    let c_resp = unsafe { &*(resp as *const c::BroadcastResponse) };
+    let results = slice(c_resp.results, c_resp.results_len).iter()
+        .map(|r| {
+            let error = (!r.error.is_null()).then(|| string(r.error));
+            (string(r.plugin_id), format!("{}-{}", string(r.plugin_idx), string(r.plugin_name)), r.result, error)
+        })
+        .collect();
+    unsafe { isula_nri::nri_free_broadcast_response(resp) };
+    (ret, results)
+}
+
+#[test]
+fn state_change_broadcast() {
+    let fast = Harness::connect("fast", "fast", "10", Script::default());
+    let failing = Harness::connect("failing", "failing", "20", Script {
+        state_change_error: Some("cannot handle it".to_string()),
+        ..Default::default()
+    });
+    let slow = Harness::connect("slow", "slow", "30", Script {
+        state_change_delay: Duration::from_millis(1000),
+        ..Default::default()
+    });
+    let other = Harness::connect("other", "other", "40", Script {
+        events: event_mask(nri::Event::CREATE_CONTAINER),
+        ..Default::default()
+    });
+    // Only registered, gets no events yet.
+    let new = Harness::connect("new", "new", "50", Script::default());
+    for h in [&slow, &failing, &fast, &other] {
+        assert_eq!(h.configure(), 0);
+        h.synchronize(&[]);
+    }
+
+    // The slow plugin does not hold the others up.
+    let start = Instant::now();
+    let (failed, results) = broadcast(nri::Event::RUN_POD_SANDBOX, 300);
+    assert!(start.elapsed() < Duration::from_millis(900));
+    assert_eq!(failed, 2);
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0], ("fast".to_string(), "10-fast".to_string(), c_transfer::NRI_PLUGIN_RESULT_OK, None));
+    assert_eq!(results[1].2, c_transfer::NRI_PLUGIN_RESULT_FAILED);
+    assert!(results[1].3.as_ref().unwrap().contains("cannot handle it"));
+    assert_eq!(results[2], ("slow".to_string(), "30-slow".to_string(), c_transfer::NRI_PLUGIN_RESULT_TIMED_OUT, None));
+    assert_eq!(fast.calls().last().unwrap(), "state_change RUN_POD_SANDBOX");
+    assert!(!other.calls().iter().any(|call| call.starts_with("state_change")));
+    assert!(!new.calls().iter().any(|call| call.starts_with("state_change")));
+
+    // Without a deadline the slow plugin is waited for.
+    let (failed, results) = broadcast(nri::Event::REMOVE_CONTAINER, 0);
+    assert_eq!(failed, 1);
+    assert_eq!(results[2].2, c_transfer::NRI_PLUGIN_RESULT_OK);
+    assert_eq!(slow.calls().last().unwrap(), "state_change REMOVE_CONTAINER");
+
+    // Events to a plugin not keeping up queue up to a bound, and fail past it.
+    let mut results = Vec::new();
+    let start = Instant::now();
+    for _ in 0..adaptation::EVENT_QUEUE_LEN + 2 {
+        results.push(broadcast(nri::Event::RUN_POD_SANDBOX, 1).1);
+    }
+    assert!(start.elapsed() < Duration::from_millis(1000));
+    let slow_results: Vec<_> = results.iter().map(|results| results[2].clone()).collect();
+    assert!(slow_results[..adaptation::EVENT_QUEUE_LEN].iter()
+        .all(|r| r.2 == c_transfer::NRI_PLUGIN_RESULT_TIMED_OUT));
+    let refused = slow_results.last().unwrap();
+    assert_eq!(refused.2, c_transfer::NRI_PLUGIN_RESULT_FAILED);
+    assert!(refused.3.as_ref().unwrap().contains("events queued"), "{:?}", refused);
+}
@@ -0,0 +1,66 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// Tests of the drop-in plugin configurations. The config directory is set
+// for the whole process, so the cases run one after the other.
+
+mod common;
+
+use std::fs;
+
+use isula_nri::nri::config;
+
+use common::*;
+
+#[test]
+fn drop_in_configs() {
+    let dir = TempDir::new("config");
+    dir.write("10-logger.conf", "indexed", 0o644);
+    dir.write("logger.conf", "named", 0o644);
+    dir.write("device-injector.conf", "named with a dash", 0o644);
+    config::set_config_dir(dir.path().to_str().unwrap());
+
+    // <idx>-<name>.conf, then <name>.conf, then nothing.
+    assert_eq!(config::plugin_config("10", "logger").unwrap(), "indexed");
+    assert_eq!(config::plugin_config("20", "logger").unwrap(), "named");
+    assert_eq!(config::plugin_config("05", "device-injector").unwrap(), "named with a dash");
+    assert_eq!(config::plugin_config("30", "other").unwrap(), "");
+
+    // Cached until reloaded.
+    dir.write("10-logger.conf", "indexed, changed", 0o644);
+    fs::remove_file(dir.path().join("logger.conf")).unwrap();
+    dir.write("05-device-injector.conf", "indexed with a dash", 0o644);
+    dir.write("other.conf", "named", 0o644);
+    assert_eq!(config::plugin_config("10", "logger").unwrap(), "indexed");
+    config::reload().unwrap();
+    assert_eq!(config::plugin_config("10", "logger").unwrap(), "indexed, changed");
+    assert_eq!(config::plugin_config("20", "logger").unwrap(), "");
+    assert_eq!(config::plugin_config("05", "device-injector").unwrap(), "indexed with a dash");
+    assert_eq!(config::plugin_config("30", "other").unwrap(), "named");
+
+    // A failed reload keeps the configurations read before.
+    fs::remove_file(dir.path().join("10-logger.conf")).unwrap();
+    fs::create_dir(dir.path().join("10-logger.conf")).unwrap();
+    assert!(config::reload().is_err());
+    assert_eq!(config::plugin_config("10", "logger").unwrap(), "indexed, changed");
+
+    // Another directory drops what was read from the previous one.
+    let other = TempDir::new("config-other");
+    other.write("logger.conf", "other directory", 0o644);
+    config::set_config_dir(other.path().to_str().unwrap());
+    assert_eq!(config::plugin_config("10", "logger").unwrap(), "other directory");
+    assert_eq!(config::plugin_config("30", "other").unwrap(), "");
+
+    // No directory, no lookup.
+    config::set_config_dir("");
+    assert_eq!(config::plugin_config("10", "logger").unwrap(), "");
+}
@@ -0,0 +1,115 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// End-to-end test of the field ownership of container adjustments. Requests
+// go to every plugin of the process, so it runs in a test binary of its own
+// and the cases run one after the other.
+
+mod common;
+
+use std::time::Duration;
+
+use protobuf::MessageField;
+
+use isula_nri::nri::adaptation;
+use isula_nri::nri::error::Error;
+use isula_nri::nri::plugin::event_mask;
+use isula_nri::protocols::nri;
+
+use common::*;
+
+fn connect(name: &str, idx: &str, adjust: nri::ContainerAdjustment) -> Harness {
+    let h = Harness::connect(name, name, idx, Script {
+        events: event_mask(nri::Event::CREATE_CONTAINER) | event_mask(nri::Event::REMOVE_CONTAINER),
+        create: nri::CreateContainerResponse { adjust: MessageField::some(adjust), ..Default::default() },
+        ..Default::default()
+    });
+    assert_eq!(h.configure(), 0);
+    h.synchronize(&[]);
+    h
+}
+
+fn create(id: &str) -> Result<nri::CreateContainerResponse, Error> {
+    let mut req = nri::CreateContainerRequest::new();
+    req.container.mut_or_insert_default().id = id.to_string();
+    adaptation::create_container(&req)
+}
+
+fn remove(id: &str) -> nri::StateChangeEvent {
+    let mut req = nri::StateChangeEvent::new();
+    req.event = nri::Event::REMOVE_CONTAINER.into();
+    req.container.mut_or_insert_default().id = id.to_string();
+    req
+}
+
+fn env(key: &str) -> nri::ContainerAdjustment {
+    let mut adjust = nri::ContainerAdjustment::new();
+    adjust.env.push(nri::KeyValue { key: key.to_string(), value: "1".to_string(), ..Default::default() });
+    adjust
+}
+
+fn mount(destination: &str) -> nri::ContainerAdjustment {
+    let mut adjust = nri::ContainerAdjustment::new();
+    adjust.mounts.push(nri::Mount { destination: destination.to_string(), ..Default::default() });
+    adjust
+}
+
+fn cpuset(cpus: &str) -> nri::ContainerAdjustment {
+    let mut adjust = nri::ContainerAdjustment::new();
+    adjust.linux.mut_or_insert_default().resources.mut_or_insert_default().cpu.mut_or_insert_default().cpus =
+        cpus.to_string();
+    adjust
+}
+
+fn assert_conflict(first: nri::ContainerAdjustment, second: nri::ContainerAdjustment, what: &str) {
+    let _first = connect("first", "10", first);
+    let _second = connect("second", "20", second);
+    match create("ctr-conflict") {
+        Err(Error::Conflict(msg)) => {
+            assert!(msg.contains("\"10-first\"") && msg.contains("\"20-second\""), "{}", msg);
+            assert!(msg.contains(what), "{}", msg);
+        }
+        res => panic!("{} set by both plugins: {:?}", what, res.map(|_| ())),
+    }
+    assert!(adaptation::container_owners("ctr-conflict").is_none());
+}
+
+#[test]
+fn ownership() {
+    assert_conflict(env("FOO"), env("FOO"), "Env \"FOO\"");
+    // Removing an entry owns it as well.
+    assert_conflict(mount("/data"), mount("-/data"), "Mounts \"/data\"");
+    assert_conflict(cpuset("0-1"), cpuset("2-3"), "CPUSetCPUs");
+
+    // The owners are kept until the container is removed, whether the event
+    // is sent to one plugin or broadcast.
+    let envs = connect("env", "10", env("FOO"));
+    let cpus = connect("cpus", "20", cpuset("0-1"));
+    create("ctr-0").unwrap();
+    create("ctr-1").unwrap();
+    let owners = adaptation::container_owners("ctr-0").unwrap().dump();
+    assert!(owners.contains("ctr-0 Env[FOO]: 10-env\n"), "{}", owners);
+    assert!(owners.contains("ctr-0 CPUSetCPUs: 20-cpus\n"), "{}", owners);
+
+    assert_eq!(envs.state_change(nri::Event::REMOVE_CONTAINER, "ctr-0"), 0);
+    assert!(adaptation::container_owners("ctr-0").is_none());
+    adaptation::broadcast_state_change(&remove("ctr-1"), Duration::ZERO).unwrap();
+    assert!(adaptation::container_owners("ctr-1").is_none());
+    assert_eq!(cpus.calls().last().unwrap(), "state_change REMOVE_CONTAINER");
+
+    // A failed creation drops what an earlier container of the id left.
+    create("ctr-2").unwrap();
+    drop(cpus);
+    let _conflicting = connect("conflicting", "30", env("FOO"));
+    assert!(matches!(create("ctr-2"), Err(Error::Conflict(_))));
+    assert!(adaptation::container_owners("ctr-2").is_none());
+}
@@ -0,0 +1,170 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// End-to-end test of dispatching container requests to all plugins and
+// merging their replies. Requests go to every plugin of the process, so it
+// runs in a test binary of its own.
+
+mod common;
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::CString;
+use std::os::raw::c_char;
+
+use protobuf::MessageField;
+
+use isula_nri::nri::c_transfer;
+use isula_nri::nri::plugin::event_mask;
+use isula_nri::protocols::nri;
+
+use common::*;
+
+fn annotations(items: &[(&str, &str)]) -> HashMap<String, String> {
+    items.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn key_value(key: &str, value: &str) -> nri::KeyValue {
+    nri::KeyValue { key: key.to_string(), value: value.to_string(), ..Default::default() }
+}
+
+fn adjusting(annotations: HashMap<String, String>, env: Vec<nri::KeyValue>) -> nri::CreateContainerResponse {
+    let adjust = nri::ContainerAdjustment { annotations, env, ..Default::default() };
+    nri::CreateContainerResponse { adjust: MessageField::some(adjust), ..Default::default() }
+}
+
+fn updating(id: &str, resources: nri::LinuxResources) -> nri::ContainerUpdate {
+    let mut update = container_update(id, false);
+    update.linux.mut_or_insert_default().resources = MessageField::some(resources);
+    update
+}
+
+fn cpu_shares(shares: u64) -> nri::LinuxResources {
+    let mut resources = nri::LinuxResources::new();
+    resources.cpu.mut_or_insert_default().shares =
+        MessageField::some(nri::OptionalUInt64 { value: shares, ..Default::default() });
+    resources
+}
+
+fn memory_limit(limit: i64) -> nri::LinuxResources {
+    let mut resources = nri::LinuxResources::new();
+    resources.memory.mut_or_insert_default().limit =
+        MessageField::some(nri::OptionalInt64 { value: limit, ..Default::default() });
+    resources
+}
+
+fn sorted(map: &HashMap<String, String>) -> BTreeMap<&str, &str> {
+    map.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect()
+}
+
+fn connect(name: &str, idx: &str, create: nri::CreateContainerResponse, update: nri::UpdateContainerResponse)
+    -> Harness {
+    let h = Harness::connect(name, name, idx, Script {
+        events: event_mask(nri::Event::CREATE_CONTAINER) | event_mask(nri::Event::UPDATE_CONTAINER),
+        create,
+        update,
+        ..Default::default()
+    });
+    assert_eq!(h.configure(), 0);
+    h.synchronize(&[]);
+    h
+}
+
+#[test]
+fn dispatch_to_all_plugins() {
+    // Connected out of order: the plugins are called by index, then by name.
+    let beta = connect("beta", "10",
+        adjusting(annotations(&[("beta", "1")]), vec![key_value("BETA", "1")]),
+        nri::UpdateContainerResponse { update: vec![updating("ctr-9", cpu_shares(256))], ..Default::default() });
+    let alpha = connect("alpha", "10",
+        adjusting(annotations(&[("alpha", "1"), ("-drop", "")]), vec![key_value("-DEBUG", "")]),
+        nri::UpdateContainerResponse { update: vec![updating("ctr-0", memory_limit(1 << 30))], ..Default::default() });
+    let zeta = connect("zeta", "05",
+        adjusting(annotations(&[("zeta", "1")]), vec![key_value("ZETA", "1")]),
+        nri::UpdateContainerResponse { update: vec![updating("ctr-0", cpu_shares(512))], ..Default::default() });
+    // Not synchronized, gets no requests.
+    let new = Harness::connect("new", "new", "01", Script::default());
+
+    let id = CString::new("ctr-0").unwrap();
+    let (keep, drop, one) = (CString::new("keep").unwrap(), CString::new("drop").unwrap(), CString::new("1").unwrap());
+    let keys = [keep.as_ptr(), drop.as_ptr()];
+    let values = [one.as_ptr(), one.as_ptr()];
+    let container_annotations = c::MapStringString { key: keys.as_ptr(), value: values.as_ptr(), len: 2 };
+    let (path, debug) = (CString::new("PATH=/bin").unwrap(), CString::new("DEBUG=1").unwrap());
+    let env: [*const c_char; 2] = [path.as_ptr(), debug.as_ptr()];
+    let container = c::Container {
+        id: id.as_ptr(),
+        annotations: &container_annotations,
+        env: env.as_ptr(),
+        env_len: env.len(),
+        ..zeroed()
+    };
+
+    let req = c::CreateContainerRequest { container: &container, ..zeroed() };
+    let mut create_resp: *const c_transfer::NriCreateContainerResponse = std::ptr::null();
+    assert_eq!(unsafe { isula_nri::nri_dispatch_create_container(
+        &req as *const c::CreateContainerRequest as *const c_transfer::NriCreateContainerRequest,
+        &mut create_resp) }, 0);
+
+    // Each plugin sees the container as adjusted by the plugins before it.
+    let seen = |h: &Harness| {
+        let created = h.plugin.created.lock().unwrap();
+        assert_eq!(created.len(), 1);
+        let container = created[0].container.as_ref().unwrap().clone();
+        (sorted(&container.annotations).into_iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>(),
+            container.env)
+    };
+    assert_eq!(seen(&zeta), (vec!["drop=1".to_string(), "keep=1".to_string()],
+        vec!["PATH=/bin".to_string(), "DEBUG=1".to_string()]));
+    assert_eq!(seen(&alpha), (vec!["drop=1".to_string(), "keep=1".to_string(), "zeta=1".to_string()],
+        vec!["PATH=/bin".to_string(), "DEBUG=1".to_string(), "ZETA=1".to_string()]));
+    // Removed by alpha.
+    assert_eq!(seen(&beta), (vec!["alpha=1".to_string(), "keep=1".to_string(), "zeta=1".to_string()],
+        vec!["PATH=/bin".to_string(), "ZETA=1".to_string()]));
+    assert!(new.plugin.created.lock().unwrap().is_empty());
+
+    // The removal markers are kept for the runtime to remove the entries too.
+    let c_resp = unsafe { &*(create_resp as *const c::CreateContainerResponse) };
+    let adjust = nri::ContainerAdjustment::from(
+        unsafe { &*(c_resp.adjust as *const c_transfer::NriContainerAdjustment) });
+    assert_eq!(sorted(&adjust.annotations),
+        BTreeMap::from([("-drop", ""), ("alpha", "1"), ("beta", "1"), ("zeta", "1")]));
+    assert_eq!(adjust.env, vec![key_value("ZETA", "1"), key_value("-DEBUG", ""), key_value("BETA", "1")]);
+    unsafe { isula_nri::nri_free_create_container_response(create_resp) };
+
+    let req = c::UpdateContainerRequest { container: &container, ..zeroed() };
+    let mut update_resp: *const c_transfer::NriUpdateContainerResponse = std::ptr::null();
+    assert_eq!(unsafe { isula_nri::nri_dispatch_update_container(
+        &req as *const c::UpdateContainerRequest as *const c_transfer::NriUpdateContainerRequest,
+        &mut update_resp) }, 0);
+
+    // Each plugin sees the resources as updated by the plugins before it.
+    let seen = |h: &Harness| {
+        let updated = h.plugin.updated.lock().unwrap();
+        assert_eq!(updated.len(), 1);
+        updated[0].linux_resources.clone().into_option()
+    };
+    assert_eq!(seen(&zeta), None);
+    assert_eq!(seen(&alpha), Some(cpu_shares(512)));
+    let mut merged = cpu_shares(512);
+    merged.memory = memory_limit(1 << 30).memory;
+    assert_eq!(seen(&beta), Some(merged.clone()));
+
+    // The resources of the container come first, the updates of the others
+    // after them.
+    let c_resp = unsafe { &*(update_resp as *const c::UpdateContainerResponse) };
+    let update: Vec<nri::ContainerUpdate> = slice(c_resp.update, c_resp.update_len).into_iter()
+        .map(|u| nri::ContainerUpdate::from(
+            unsafe { &*(u as *const c::ContainerUpdate as *const c_transfer::NriContainerUpdate) }))
+        .collect();
+    assert_eq!(update, vec![updating("ctr-0", merged), updating("ctr-9", cpu_shares(256))]);
+    unsafe { isula_nri::nri_free_update_container_response(update_resp) };
+}
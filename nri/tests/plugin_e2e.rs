@@ -0,0 +1,275 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// End-to-end tests of a plugin's lifecycle, driven through the C interface.
+
+mod common;
+
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use isula_nri::nri::c_transfer;
+use isula_nri::nri::plugin::{event_mask, RpcTimeouts};
+use isula_nri::protocols::nri;
+
+use common::*;
+
+#[test]
+fn plugin_lifecycle() {
+    let mut adjust = nri::ContainerAdjustment::new();
+    adjust.annotations.insert("fake/adjusted".to_string(), "true".to_string());
+    let mut env = nri::KeyValue::new();
+    env.key = "FAKE".to_string();
+    env.value = "1".to_string();
+    adjust.env.push(env);
+    adjust.args = vec!["/bin/fake".to_string(), "--flag".to_string()];
+    let mut create = nri::CreateContainerResponse::new();
+    create.adjust = protobuf::MessageField::some(adjust);
+    create.update.push(container_update("ctr-0", true));
+    create.evict.push(container_eviction("ctr-1", "evicted on create"));
+    let mut update = nri::UpdateContainerResponse::new();
+    update.update.push(container_update("ctr-0", false));
+    update.evict.push(container_eviction("ctr-1", "evicted on update"));
+    let mut stop = nri::StopContainerResponse::new();
+    stop.update.push(container_update("ctr-2", false));
+    let script = Script {
+        events: event_mask(nri::Event::CREATE_CONTAINER) | event_mask(nri::Event::UPDATE_CONTAINER)
+            | event_mask(nri::Event::STOP_CONTAINER) | event_mask(nri::Event::REMOVE_CONTAINER),
+        sync_update: vec![container_update("ctr-0", false)],
+        create,
+        update,
+        stop,
+        ..Default::default()
+    };
+
+    let h = Harness::connect("lifecycle", "fake", "10", script);
+    assert!(REGISTERED.lock().unwrap().contains(&("lifecycle".to_string(), "fake".to_string(), "10".to_string())));
+    assert_eq!(h.state(), 1);
+
+    // Container events are refused until the plugin is synchronized.
+    let id = CString::new("ctr-0").unwrap();
+    let container = c::Container { id: id.as_ptr(), ..zeroed() };
+    let req = c::CreateContainerRequest { container: &container, ..zeroed() };
+    let mut create_resp: *const c_transfer::NriCreateContainerResponse = std::ptr::null();
+    assert_eq!(unsafe { isula_nri::nri_plugin_create_container(h.id.as_ptr(),
+        &req as *const c::CreateContainerRequest as *const c_transfer::NriCreateContainerRequest, &mut create_resp) }, -1);
+
+    assert_eq!(h.configure(), 0);
+    assert_eq!(h.state(), 2);
+    let mut events = 0;
+    assert_eq!(unsafe { isula_nri::nri_plugin_subscribed_events(h.id.as_ptr(), &mut events) }, 0);
+    assert_eq!(events, h.plugin.script.events);
+
+    assert_eq!(h.synchronize(&["ctr-0", "ctr-1"]), vec![("ctr-0".to_string(), false)]);
+    assert_eq!(h.state(), 3);
+
+    assert_eq!(unsafe { isula_nri::nri_plugin_create_container(h.id.as_ptr(),
+        &req as *const c::CreateContainerRequest as *const c_transfer::NriCreateContainerRequest, &mut create_resp) }, 0);
+    let c_resp = unsafe { &*(create_resp as *const c::CreateContainerResponse) };
+    let adjust = unsafe { c_resp.adjust.as_ref() }.unwrap();
+    let annotations = unsafe { adjust.annotations.as_ref() }.unwrap();
+    assert_eq!(strings(annotations.key, annotations.len), vec!["fake/adjusted"]);
+    assert_eq!(strings(annotations.value, annotations.len), vec!["true"]);
+    let env: Vec<(String, String)> = slice(adjust.env, adjust.env_len).iter()
+        .map(|kv| (string(kv.key), string(kv.value)))
+        .collect();
+    assert_eq!(env, vec![("FAKE".to_string(), "1".to_string())]);
+    assert_eq!(strings(adjust.args, adjust.args_len), vec!["/bin/fake", "--flag"]);
+    assert_eq!(updates(c_resp.update, c_resp.update_len), vec![("ctr-0".to_string(), true)]);
+    assert_eq!(evictions(c_resp.evict, c_resp.evict_len),
+        vec![("ctr-1".to_string(), "evicted on create".to_string())]);
+    unsafe { isula_nri::nri_free_create_container_response(create_resp) };
+
+    let req = c::UpdateContainerRequest { container: &container, ..zeroed() };
+    let mut update_resp: *const c_transfer::NriUpdateContainerResponse = std::ptr::null();
+    assert_eq!(unsafe { isula_nri::nri_plugin_update_container(h.id.as_ptr(),
+        &req as *const c::UpdateContainerRequest as *const c_transfer::NriUpdateContainerRequest, &mut update_resp) }, 0);
+    let c_resp = unsafe { &*(update_resp as *const c::UpdateContainerResponse) };
+    assert_eq!(updates(c_resp.update, c_resp.update_len), vec![("ctr-0".to_string(), false)]);
+    assert_eq!(evictions(c_resp.evict, c_resp.evict_len),
+        vec![("ctr-1".to_string(), "evicted on update".to_string())]);
+    unsafe { isula_nri::nri_free_update_container_response(update_resp) };
+
+    let req = c::StopContainerRequest { container: &container, ..zeroed() };
+    let mut stop_resp: *const c_transfer::NriStopContainerResponse = std::ptr::null();
+    assert_eq!(unsafe { isula_nri::nri_plugin_stop_container(h.id.as_ptr(),
+        &req as *const c::StopContainerRequest as *const c_transfer::NriStopContainerRequest, &mut stop_resp) }, 0);
+    let c_resp = unsafe { &*(stop_resp as *const c::StopContainerResponse) };
+    assert_eq!(updates(c_resp.update, c_resp.update_len), vec![("ctr-2".to_string(), false)]);
+    unsafe { isula_nri::nri_free_stop_container_response(stop_resp) };
+
+    // Events the plugin did not subscribe for do not reach it.
+    assert_eq!(h.state_change(nri::Event::REMOVE_CONTAINER, "ctr-0"), 0);
+    assert_eq!(h.state_change(nri::Event::START_CONTAINER, "ctr-0"), 0);
+
+    assert_eq!(unsafe { isula_nri::nri_plugin_shutdown(h.id.as_ptr()) }, 0);
+
+    assert_eq!(h.calls(), vec![
+        "configure isulad 2.1.5 key: value",
+        "synchronize ctr-0,ctr-1",
+        "create_container ctr-0",
+        "update_container ctr-0",
+        "stop_container ctr-0",
+        "state_change REMOVE_CONTAINER",
+        "shutdown",
+    ]);
+
+    // The plugin sees the runtime going away, the runtime does not report
+    // plugins it disconnected itself.
+    assert_eq!(isula_nri::nri_plugin_disconnect(h.id.as_ptr()), 0);
+    assert_eq!(h.state(), -1);
+    assert_eq!(h.stub.wait(), "trunk closed");
+    assert!(!CLOSED.lock().unwrap().iter().any(|(id, _)| id == "lifecycle"));
+}
+
+#[test]
+fn unsubscribed_plugin_gets_all_events() {
+    let h = Harness::connect("all-events", "fake", "20", Script::default());
+    assert_eq!(h.configure(), 0);
+    assert!(h.synchronize(&[]).is_empty());
+    assert_eq!(unsafe { isula_nri::nri_plugin_is_subscribed(h.id.as_ptr(), nri::Event::RUN_POD_SANDBOX as i32) }, 1);
+    assert_eq!(h.state_change(nri::Event::START_CONTAINER, "ctr-0"), 0);
+    assert_eq!(h.calls().last().unwrap(), "state_change START_CONTAINER");
+}
+
+#[test]
+fn unsolicited_container_updates() {
+    let h = Harness::connect("unsolicited", "fake", "30", Script::default());
+    let freed = *FREED_UPDATE_RESPONSES.lock().unwrap();
+    let failed = h.stub.update_containers(
+        vec![container_update("ctr-0", false), container_update("missing-1", true)],
+        vec![container_eviction("ctr-2", "out of memory")]).unwrap();
+    assert_eq!(failed, vec![container_update("missing-1", true)]);
+    // The runtime gets its response back to free it.
+    assert_eq!(*FREED_UPDATE_RESPONSES.lock().unwrap(), freed + 1);
+
+    let updates = UPDATES.lock().unwrap();
+    let (_, update, evict) = updates.iter().find(|(id, _, _)| id == "unsolicited").unwrap();
+    assert_eq!(update, &vec![("ctr-0".to_string(), false), ("missing-1".to_string(), true)]);
+    assert_eq!(evict, &vec![("ctr-2".to_string(), "out of memory".to_string())]);
+}
+
+#[test]
+fn plugin_going_away_is_reported() {
+    let h = Harness::connect("going-away", "fake", "40", Script::default());
+    h.stub.close();
+    assert!(wait_for(|| CLOSED.lock().unwrap().iter().any(|(id, _)| id == "going-away")));
+    assert_eq!(h.state(), -1);
+    assert_eq!(h.configure(), -1);
+}
+
+#[test]
+fn connected_plugins_listed() {
+    let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64;
+    let h = Harness::connect("listed", "fake", "50", Script {
+        events: event_mask(nri::Event::CREATE_CONTAINER),
+        ..Default::default()
+    });
+    let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64;
+    assert_eq!(h.configure(), 0);
+
+    let mut list: *const c_transfer::NriPluginStatusList = std::ptr::null();
+    let n = unsafe { isula_nri::nri_plugin_list(&mut list) };
+    let c_list = unsafe { &*(list as *const c::PluginStatusList) };
+    let plugins = slice(c_list.plugins, c_list.plugins_len);
+    assert_eq!(n as usize, plugins.len());
+    let ids: Vec<String> = plugins.iter().map(|p| string(p.plugin_id)).collect();
+    let mut sorted = ids.clone();
+    sorted.sort();
+    assert_eq!(ids, sorted);
+
+    let listed = plugins.iter().find(|p| string(p.plugin_id) == "listed").unwrap();
+    assert_eq!(string(listed.plugin_name), "fake");
+    assert_eq!(string(listed.plugin_idx), "50");
+    assert_eq!(listed.state, 2);
+    assert_eq!(listed.events, event_mask(nri::Event::CREATE_CONTAINER));
+    assert_eq!(listed.timeouts, RpcTimeouts::uniform(TIMEOUT));
+    assert_eq!(listed.mux_closed, 0);
+    assert!(before <= listed.connected_at && listed.connected_at <= after);
+    unsafe { isula_nri::nri_free_plugin_status_list(list) };
+}
+
+#[test]
+fn plugin_stats_dumped() {
+    let h = Harness::connect("stats", "fake", "60", Script::default());
+    assert_eq!(h.configure(), 0);
+    h.synchronize(&[]);
+
+    let mut out: *const c_char = std::ptr::null();
+    assert_eq!(unsafe { isula_nri::nri_dump_stats(1, &mut out) }, 0);
+    let out = unsafe { CString::from_raw(out as *mut c_char) }.into_string().unwrap();
+    let plugins: serde_json::Value = serde_json::from_str(&out).unwrap();
+    let stats = plugins.as_array().unwrap().iter().find(|p| p["id"] == "stats").unwrap();
+    assert_eq!(stats["idx"], "60");
+    assert_eq!(stats["rpcs"]["configure"]["calls"], 1);
+    assert_eq!(stats["rpcs"]["configure"]["errors"], 0);
+    assert_eq!(stats["rpcs"]["synchronize"]["calls"], 1);
+    let buckets = stats["rpcs"]["configure"]["latency"]["buckets"].as_array().unwrap();
+    assert_eq!(buckets.iter().map(|b| b["count"].as_u64().unwrap()).sum::<u64>(), 1);
+    assert!(stats["mux"]["bytes_received"].as_u64().unwrap() > 0);
+    assert!(stats["mux"]["bytes_sent"].as_u64().unwrap() > 0);
+
+    let mut out: *const c_char = std::ptr::null();
+    assert_eq!(unsafe { isula_nri::nri_dump_stats(0, &mut out) }, 0);
+    let out = unsafe { CString::from_raw(out as *mut c_char) }.into_string().unwrap();
+    assert!(out.contains("plugin stats (60-fake)"));
+    assert!(out.contains("  configure: calls 1 errors 0 timeouts 0"));
+
+    let mut out: *const c_char = std::ptr::null();
+    assert_eq!(unsafe { isula_nri::nri_dump_stats(2, &mut out) }, -1);
+}
+
+#[test]
+fn rpc_timeouts_apply_per_rpc() {
+    let mut timeouts = zeroed::<RpcTimeouts>();
+    unsafe { isula_nri::nri_rpc_timeouts_default(&mut timeouts) };
+    assert_eq!(timeouts.synchronize, 30_000_000_000);
+    timeouts.create_container = 100_000_000;
+    let h = Harness::connect_with_timeouts("timeouts", "fake", "70", Script {
+        create_delay: Duration::from_millis(300),
+        ..Default::default()
+    }, &timeouts);
+    assert_eq!(h.configure(), 0);
+    h.synchronize(&[]);
+
+    let create = || {
+        let id = CString::new("ctr-0").unwrap();
+        let container = c::Container { id: id.as_ptr(), ..zeroed() };
+        let req = c::CreateContainerRequest { container: &container, ..zeroed() };
+        let mut resp: *const c_transfer::NriCreateContainerResponse = std::ptr::null();
+        let ret = unsafe { isula_nri::nri_plugin_create_container(h.id.as_ptr(),
+            &req as *const c::CreateContainerRequest as *const c_transfer::NriCreateContainerRequest, &mut resp) };
+        if ret == 0 {
+            unsafe { isula_nri::nri_free_create_container_response(resp) };
+        }
+        ret
+    };
+    // A timeout is told apart from the other errors.
+    assert_eq!(create(), isula_nri::NRI_ERR_TIMEOUT);
+
+    timeouts.create_container = 2_000_000_000;
+    assert_eq!(unsafe { isula_nri::nri_plugin_rpc_timeouts_set(h.id.as_ptr(), &timeouts) }, 0);
+    assert_eq!(create(), 0);
+
+    timeouts.create_container = -1;
+    assert_eq!(unsafe { isula_nri::nri_plugin_rpc_timeouts_set(h.id.as_ptr(), &timeouts) }, -1);
+
+    let mut out: *const c_char = std::ptr::null();
+    assert_eq!(unsafe { isula_nri::nri_dump_stats(1, &mut out) }, 0);
+    let out = unsafe { CString::from_raw(out as *mut c_char) }.into_string().unwrap();
+    let plugins: serde_json::Value = serde_json::from_str(&out).unwrap();
+    let stats = plugins.as_array().unwrap().iter().find(|p| p["id"] == "timeouts").unwrap();
+    assert_eq!(stats["rpcs"]["create_container"]["calls"], 2);
+    assert_eq!(stats["rpcs"]["create_container"]["timeouts"], 1);
+    assert_eq!(stats["rpcs"]["create_container"]["errors"], 0);
+}
@@ -0,0 +1,86 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// Tests of launching the pre-installed plugins of a directory, with shell
+// scripts standing in for the plugins.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use isula_nri::nri::launcher::{self, parse_plugin_name};
+
+use common::*;
+
+// Checks what it is started with, then holds its end of the socket until
+// it is closed or the plugin is terminated.
+const CHECK: &str = r#"#!/bin/sh
+[ "$NRI_PLUGIN_NAME" = check ] && [ "$NRI_PLUGIN_IDX" = 10 ] && [ "$NRI_PLUGIN_SOCKET" = 3 ] || exit 1
+case "$(readlink /proc/self/fd/3)" in
+socket:*) ;;
+*) exit 1 ;;
+esac
+echo ok > checked
+exec cat <&3 >/dev/null
+"#;
+
+// Ignores SIGTERM, so that it has to be killed.
+const STUBBORN: &str = r#"#!/bin/sh
+trap '' TERM
+echo ok > started
+while :; do sleep 0.1; done
+"#;
+
+// Stops the launched plugins even when the test fails, so that none is left
+// behind holding the output of the test.
+struct StopLaunched;
+
+impl Drop for StopLaunched {
+    fn drop(&mut self) {
+        launcher::stop_launched_plugins(Duration::ZERO);
+    }
+}
+
+#[test]
+fn plugin_names() {
+    assert_eq!(parse_plugin_name("10-logger"), Some(("10", "logger")));
+    assert_eq!(parse_plugin_name("05-device-injector"), Some(("05", "device-injector")));
+    for name in ["logger", "1-logger", "100-logger", "1a-logger", "10-", "-logger", "10logger", ""] {
+        assert_eq!(parse_plugin_name(name), None, "{}", name);
+    }
+}
+
+#[test]
+fn launch_and_stop() {
+    let dir = TempDir::new("launcher");
+    dir.write("10-check", CHECK, 0o755);
+    dir.write("20-stubborn", STUBBORN, 0o755);
+    // Not started: not named NN-name, not executable.
+    dir.write("logger", CHECK, 0o755);
+    dir.write("30-disabled", CHECK, 0o644);
+
+    let _stop = StopLaunched;
+    let launched = launcher::launch_plugins(dir.path().to_str().unwrap(), TIMEOUT).unwrap();
+    assert_eq!(launched, vec!["10-check", "20-stubborn"]);
+    assert!(wait_for(|| dir.path().join("checked").exists() && dir.path().join("started").exists()));
+    let running = launcher::launched_plugins();
+    assert_eq!(running.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), vec!["10-check", "20-stubborn"]);
+
+    let start = Instant::now();
+    launcher::stop_launched_plugins(Duration::from_millis(300));
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    assert!(launcher::launched_plugins().is_empty());
+    // Reaped, not left as zombies.
+    for (id, pid) in running {
+        assert_eq!(unsafe { libc::kill(pid as libc::pid_t, 0) }, -1, "{}", id);
+    }
+}
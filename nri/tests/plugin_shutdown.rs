@@ -0,0 +1,79 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// End-to-end test of the runtime service shutdown. It shuts down every
+// plugin of the process, so it runs in a test binary of its own.
+
+mod common;
+
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use isula_nri::nri::c_transfer;
+
+use common::*;
+
+#[test]
+fn runtime_service_shutdown_is_orderly() {
+    let acking = Harness::connect("acking", "fake", "10", Script::default());
+    let slow = Harness::connect("slow", "fake", "20", Script {
+        shutdown_delay: Duration::from_secs(3),
+        ..Default::default()
+    });
+    let busy = Harness::connect("busy", "fake", "30", Script {
+        create_delay: Duration::from_millis(300),
+        ..Default::default()
+    });
+    assert_eq!(busy.configure(), 0);
+    busy.synchronize(&[]);
+
+    let busy_id = busy.id.clone();
+    let creating = thread::spawn(move || {
+        let id = CString::new("ctr-0").unwrap();
+        let container = c::Container { id: id.as_ptr(), ..zeroed() };
+        let req = c::CreateContainerRequest { container: &container, ..zeroed() };
+        let mut resp: *const c_transfer::NriCreateContainerResponse = std::ptr::null();
+        let ret = unsafe { isula_nri::nri_plugin_create_container(busy_id.as_ptr(),
+            &req as *const c::CreateContainerRequest as *const c_transfer::NriCreateContainerRequest, &mut resp) };
+        if ret == 0 {
+            unsafe { isula_nri::nri_free_create_container_response(resp) };
+        }
+        ret
+    });
+    // The plugin takes 300ms to answer, shut down while it does.
+    thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    let mut unacked: *const c_char = std::ptr::null();
+    assert_eq!(unsafe { isula_nri::nri_runtime_service_shutdown(1000, &mut unacked) }, 1);
+    assert!(start.elapsed() < Duration::from_secs(2));
+    let unacked = unsafe { CString::from_raw(unacked as *mut c_char) };
+    assert_eq!(unacked.to_str().unwrap(), "slow");
+
+    // The call in flight was waited for, not cut off.
+    assert_eq!(creating.join().unwrap(), 0);
+    assert!(busy.calls().contains(&"create_container ctr-0".to_string()));
+
+    for h in [&acking, &slow, &busy] {
+        assert_eq!(h.state(), -1);
+        assert_eq!(h.stub.wait(), "trunk closed");
+    }
+    assert!(acking.calls().contains(&"shutdown".to_string()));
+    assert!(busy.calls().contains(&"shutdown".to_string()));
+    assert!(CLOSED.lock().unwrap().is_empty());
+
+    // Plugins connect again once the runtime service is shut down.
+    let again = Harness::connect("again", "fake", "10", Script::default());
+    assert_eq!(again.state(), 1);
+}
@@ -75,14 +75,84 @@ int sandbox_api_stop(ControllerHandle_t chandle, const sandbox_stop_request *req
 
 int sandbox_api_wait(ControllerHandle_t chandle, const sandbox_wait_request *request, sandbox_api_wait_callback callback);
 
+/**
+ * @brief Get the status of a sandbox. The response may come from the status cache, for up to
+ *        1000ms by default, see sandbox_api_status_cache_set_ttl. The cached status of a sandbox
+ *        is dropped when it is started, stopped, updated, shut down or exits.
+ */
 int sandbox_api_status(ControllerHandle_t chandle, const sandbox_status_request *request, sandbox_status_response *response);
 
+/**
+ * @brief Query the status of many sandboxes of one controller concurrently.
+ * @param requests the status requests.
+ * @param responses the responses to fill, one per request.
+ * @param codes the gRPC status code of each request, 0 if its response has been filled.
+ * @param len the number of requests.
+ * @return 0 if the requests have been sent, -1 on invalid arguments or if the controller is unreachable.
+ */
+int sandbox_api_status_many(ControllerHandle_t chandle, const sandbox_status_request **requests,
+                            sandbox_status_response **responses, int *codes, size_t len);
+
+/**
+ * @brief Set how long a status response may be served from the cache, 0 disables the cache.
+ * @param ttl_ms the time to live in milliseconds.
+ */
+void sandbox_api_status_cache_set_ttl(uint64_t ttl_ms);
+
+/**
+ * @brief Shut a sandbox down. Its metrics are not collected anymore, and its cached metrics are
+ *        dropped.
+ */
 int sandbox_api_shutdown(ControllerHandle_t chandle, const sandbox_shutdown_request *request);
 
 int sandbox_api_metrics(ControllerHandle_t chandle, const sandbox_metrics_request *request, sandbox_metrics_response *response);
 
+/**
+ * @brief Update a sandbox.
+ * @param request the update request. Its fields must be labels, extensions, spec, runtime,
+ *        labels.<key> or extensions.<key>. They reach the sandboxer normalized: sorted,
+ *        without duplicates, and without the keys of a map updated as a whole.
+ * @return 0 on success, -1 on an invalid request or if the update failed.
+ */
 int sandbox_api_update(ControllerHandle_t chandle, const sandbox_update_request *request);
 
+/**
+ * @brief Start polling metrics of the registered sandboxes in the background.
+ * @param interval_ms the polling interval in milliseconds.
+ * @return 0 on success, -1 if the collector is already running or the interval is zero.
+ */
+int sandbox_api_metrics_collector_start(uint64_t interval_ms);
+
+/**
+ * @brief Stop polling metrics. The sandboxes stay registered and their last metrics cached.
+ */
+void sandbox_api_metrics_collector_stop(void);
+
+/**
+ * @brief Register a sandbox for the collector to poll its metrics, with the controller of chandle.
+ *        A sandbox registered again is polled with the new request.
+ * @param request the metrics request sent at each interval.
+ * @return 0 on success, -1 if the controller is not connected.
+ */
+int sandbox_api_metrics_register(ControllerHandle_t chandle, const sandbox_metrics_request *request);
+
+/**
+ * @brief Stop polling the metrics of a sandbox and drop its cached metrics. Shutting the sandbox
+ *        down with sandbox_api_shutdown unregisters it too.
+ * @param sandbox_id the sandbox id.
+ * @return 0 on success, -1 if the sandbox is not registered.
+ */
+int sandbox_api_metrics_unregister(const char *sandbox_id);
+
+/**
+ * @brief Get the last metrics collected for a sandbox without calling the sandboxer.
+ * @param sandbox_id the sandbox id.
+ * @param response the cached metrics.
+ * @param age_ms how long ago the metrics were collected, in milliseconds, may be NULL.
+ * @return 0 on success, -1 if no metrics have been collected for the sandbox.
+ */
+int sandbox_api_cached_metrics(const char *sandbox_id, sandbox_metrics_response *response, uint64_t *age_ms);
+
 #ifdef __cplusplus
 }
 #endif
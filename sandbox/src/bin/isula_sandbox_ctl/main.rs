@@ -0,0 +1,194 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// isula-sandbox-ctl talks to a sandboxer socket directly, bypassing iSulad,
+// so that a misbehaving sandbox controller can be debugged in isolation.
+//
+// Usage:
+//   isula-sandbox-ctl --address <socket> <command> [<json> | -]
+//
+// The request body is read from the argument, or from stdin when it is "-"
+// or omitted. The response is printed to stdout as JSON.
+
+mod types;
+
+use std::io::Read;
+use std::process::exit;
+
+use serde::Serialize;
+use isula_sandbox::controller::client::Client;
+use isula_sandbox::controller::client::sandbox::containerd::services::sandbox::v1 as sandbox_services;
+
+use types::*;
+
+const USAGE: &str = "Usage: isula-sandbox-ctl --address <socket> <command> [<json> | -]
+
+Commands:
+  create     {\"sandbox_id\", \"rootfs\", \"options\", \"netns_path\", \"annotations\", \"sandbox\", \"sandboxer\"}
+  start      {\"sandbox_id\", \"sandboxer\"}
+  platform   {\"sandbox_id\", \"sandboxer\"}
+  status     {\"sandbox_id\", \"verbose\", \"sandboxer\"}
+  wait       {\"sandbox_id\", \"sandboxer\"}
+  stop       {\"sandbox_id\", \"timeout_secs\", \"sandboxer\"}
+  shutdown   {\"sandbox_id\", \"sandboxer\"}
+  metrics    {\"sandbox_id\", \"sandboxer\"}
+  update     {\"sandbox_id\", \"sandboxer\", \"sandbox\", \"fields\"}
+
+Timestamps are nanoseconds since the epoch, Any values are base64 encoded.";
+
+const COMMANDS: [&str; 9] = [
+    "create", "start", "platform", "status", "wait", "stop", "shutdown", "metrics", "update",
+];
+
+struct Args {
+    address: String,
+    command: String,
+    body: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut address = None;
+    let mut positional = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            "-a" | "--address" => {
+                address = Some(args.next().ok_or("--address requires a value")?);
+            }
+            _ => match arg.strip_prefix("--address=") {
+                Some(value) => address = Some(value.to_string()),
+                None => positional.push(arg),
+            },
+        }
+    }
+
+    let address = address.ok_or("--address is required")?;
+    let mut positional = positional.into_iter();
+    let command = positional.next().ok_or("command is required")?;
+    if !COMMANDS.contains(&command.as_str()) {
+        return Err(format!("unknown command: {}", command));
+    }
+    let body = positional.next();
+    if positional.next().is_some() {
+        return Err("too many arguments".to_string());
+    }
+    Ok(Args { address, command, body })
+}
+
+fn read_body(body: Option<String>) -> Result<String, String> {
+    match body {
+        Some(body) if body != "-" => Ok(body),
+        _ => {
+            let mut body = String::new();
+            std::io::stdin().read_to_string(&mut body)
+                .map_err(|e| format!("failed to read request from stdin: {}", e))?;
+            Ok(body)
+        }
+    }
+}
+
+fn parse<T: serde::de::DeserializeOwned>(body: &str) -> Result<T, String> {
+    if body.trim().is_empty() {
+        return Err("request body is empty".to_string());
+    }
+    serde_json::from_str(body).map_err(|e| format!("invalid request: {}", e))
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    let out = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("failed to encode response: {}", e))?;
+    println!("{}", out);
+    Ok(())
+}
+
+fn rpc_error(e: tonic::Status) -> String {
+    format!("rpc failed: code: {:?}, message: {}", e.code(), e.message())
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    let body = read_body(args.body)?;
+    let mut client = Client::new(args.address.clone()).await
+        .map_err(|e| format!("failed to connect to {}: {}", args.address, e))?;
+
+    match args.command.as_str() {
+        "create" => {
+            let req: CreateJson = parse(&body)?;
+            let req = sandbox_services::ControllerCreateRequest::try_from(&req)
+                .map_err(|e| format!("invalid request: {}", e))?;
+            let rsp = client.create(req).await.map_err(rpc_error)?;
+            print_json(&CreateResponseJson::from(&rsp))
+        }
+        "start" => {
+            let req: SandboxRefJson = parse(&body)?;
+            let rsp = client.start((&req).into()).await.map_err(rpc_error)?;
+            print_json(&StartResponseJson::from(&rsp))
+        }
+        "platform" => {
+            let req: SandboxRefJson = parse(&body)?;
+            let rsp = client.platform((&req).into()).await.map_err(rpc_error)?;
+            print_json(&PlatformResponseJson::from(&rsp))
+        }
+        "status" => {
+            let req: StatusJson = parse(&body)?;
+            let rsp = client.status((&req).into()).await.map_err(rpc_error)?;
+            print_json(&StatusResponseJson::from(&rsp))
+        }
+        "wait" => {
+            let req: SandboxRefJson = parse(&body)?;
+            let rsp = client.wait((&req).into()).await.map_err(rpc_error)?;
+            print_json(&WaitResponseJson::from(&rsp))
+        }
+        "stop" => {
+            let req: StopJson = parse(&body)?;
+            client.stop((&req).into()).await.map_err(rpc_error)?;
+            print_json(&EmptyJson::default())
+        }
+        "shutdown" => {
+            let req: SandboxRefJson = parse(&body)?;
+            client.shutdown((&req).into()).await.map_err(rpc_error)?;
+            print_json(&EmptyJson::default())
+        }
+        "metrics" => {
+            let req: SandboxRefJson = parse(&body)?;
+            let rsp = client.metrics((&req).into()).await.map_err(rpc_error)?;
+            print_json(&MetricsResponseJson::from(&rsp))
+        }
+        "update" => {
+            let req: UpdateJson = parse(&body)?;
+            let req = sandbox_services::ControllerUpdateRequest::try_from(&req)
+                .map_err(|e| format!("invalid request: {}", e))?;
+            client.update(req).await.map_err(rpc_error)?;
+            print_json(&EmptyJson::default())
+        }
+        command => Err(format!("unknown command: {}", command)),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("isula-sandbox-ctl: {}\n\n{}", e, USAGE);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = run(args).await {
+        eprintln!("isula-sandbox-ctl: {}", e);
+        exit(1);
+    }
+}
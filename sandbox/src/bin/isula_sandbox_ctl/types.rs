@@ -0,0 +1,376 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+// JSON representations of the sandbox controller requests and responses.
+// The prost generated types do not implement serde, so every message used by
+// isula-sandbox-ctl is mirrored here. Timestamps are expressed in nanoseconds
+// since the epoch, the same way as in the C API, and the value of Any is
+// base64 encoded.
+
+use std::collections::HashMap;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Serialize};
+
+use isula_common::isula_data_types::{prost_timestamp_to_u64, u64_to_prost_timestamp};
+use isula_sandbox::controller::client::sandbox::containerd::types as sandbox;
+use isula_sandbox::controller::client::sandbox::containerd::services::sandbox::v1 as sandbox_services;
+
+fn timestamp_to_u64(timestamp: &Option<prost_types::Timestamp>) -> u64 {
+    timestamp.as_ref()
+        .map(prost_timestamp_to_u64)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnyJson {
+    pub type_url: String,
+    pub value: String,
+}
+
+impl TryFrom<&AnyJson> for prost_types::Any {
+    type Error = base64::DecodeError;
+
+    fn try_from(any: &AnyJson) -> Result<Self, Self::Error> {
+        Ok(prost_types::Any {
+            type_url: any.type_url.clone(),
+            value: BASE64.decode(any.value.as_bytes())?,
+        })
+    }
+}
+
+impl From<&prost_types::Any> for AnyJson {
+    fn from(any: &prost_types::Any) -> Self {
+        AnyJson {
+            type_url: any.type_url.clone(),
+            value: BASE64.encode(&any.value),
+        }
+    }
+}
+
+fn any_from_json(any: &Option<AnyJson>) -> Result<Option<prost_types::Any>, base64::DecodeError> {
+    any.as_ref().map(prost_types::Any::try_from).transpose()
+}
+
+fn any_to_json(any: &Option<prost_types::Any>) -> Option<AnyJson> {
+    any.as_ref().map(AnyJson::from)
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MountJson {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub source: String,
+    #[serde(alias = "destination")]
+    pub target: String,
+    pub options: Vec<String>,
+}
+
+impl From<&MountJson> for sandbox::Mount {
+    fn from(mnt: &MountJson) -> Self {
+        sandbox::Mount {
+            r#type: mnt.type_.clone(),
+            source: mnt.source.clone(),
+            target: mnt.target.clone(),
+            options: mnt.options.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RuntimeJson {
+    pub name: String,
+    pub options: Option<AnyJson>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SandboxJson {
+    pub sandbox_id: String,
+    pub runtime: Option<RuntimeJson>,
+    pub spec: Option<AnyJson>,
+    pub labels: HashMap<String, String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub extensions: HashMap<String, AnyJson>,
+    pub sandboxer: String,
+}
+
+impl TryFrom<&SandboxJson> for sandbox::Sandbox {
+    type Error = base64::DecodeError;
+
+    fn try_from(sb: &SandboxJson) -> Result<Self, Self::Error> {
+        let runtime = match sb.runtime.as_ref() {
+            Some(rt) => Some(sandbox::sandbox::Runtime {
+                name: rt.name.clone(),
+                options: any_from_json(&rt.options)?,
+            }),
+            None => None,
+        };
+        let mut extensions = HashMap::new();
+        for (key, value) in sb.extensions.iter() {
+            extensions.insert(key.clone(), prost_types::Any::try_from(value)?);
+        }
+        Ok(sandbox::Sandbox {
+            sandbox_id: sb.sandbox_id.clone(),
+            runtime,
+            spec: any_from_json(&sb.spec)?,
+            labels: sb.labels.clone(),
+            created_at: Some(u64_to_prost_timestamp(sb.created_at)),
+            updated_at: Some(u64_to_prost_timestamp(sb.updated_at)),
+            extensions,
+            sandboxer: sb.sandboxer.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CreateJson {
+    pub sandbox_id: String,
+    pub rootfs: Vec<MountJson>,
+    pub options: Option<AnyJson>,
+    pub netns_path: String,
+    pub annotations: HashMap<String, String>,
+    pub sandbox: Option<SandboxJson>,
+    pub sandboxer: String,
+}
+
+impl TryFrom<&CreateJson> for sandbox_services::ControllerCreateRequest {
+    type Error = base64::DecodeError;
+
+    fn try_from(req: &CreateJson) -> Result<Self, Self::Error> {
+        Ok(sandbox_services::ControllerCreateRequest {
+            sandbox_id: req.sandbox_id.clone(),
+            rootfs: req.rootfs.iter().map(sandbox::Mount::from).collect(),
+            options: any_from_json(&req.options)?,
+            netns_path: req.netns_path.clone(),
+            annotations: req.annotations.clone(),
+            sandbox: req.sandbox.as_ref().map(sandbox::Sandbox::try_from).transpose()?,
+            sandboxer: req.sandboxer.clone(),
+        })
+    }
+}
+
+// Start, Platform, Wait, Shutdown and Metrics only need to identify the sandbox.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SandboxRefJson {
+    pub sandbox_id: String,
+    pub sandboxer: String,
+}
+
+macro_rules! from_sandbox_ref {
+    ($($request:ident),*) => {
+        $(
+            impl From<&SandboxRefJson> for sandbox_services::$request {
+                fn from(req: &SandboxRefJson) -> Self {
+                    sandbox_services::$request {
+                        sandbox_id: req.sandbox_id.clone(),
+                        sandboxer: req.sandboxer.clone(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_sandbox_ref!(
+    ControllerStartRequest,
+    ControllerPlatformRequest,
+    ControllerWaitRequest,
+    ControllerShutdownRequest,
+    ControllerMetricsRequest
+);
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StopJson {
+    pub sandbox_id: String,
+    pub timeout_secs: u32,
+    pub sandboxer: String,
+}
+
+impl From<&StopJson> for sandbox_services::ControllerStopRequest {
+    fn from(req: &StopJson) -> Self {
+        sandbox_services::ControllerStopRequest {
+            sandbox_id: req.sandbox_id.clone(),
+            timeout_secs: req.timeout_secs,
+            sandboxer: req.sandboxer.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct StatusJson {
+    pub sandbox_id: String,
+    pub verbose: bool,
+    pub sandboxer: String,
+}
+
+impl From<&StatusJson> for sandbox_services::ControllerStatusRequest {
+    fn from(req: &StatusJson) -> Self {
+        sandbox_services::ControllerStatusRequest {
+            sandbox_id: req.sandbox_id.clone(),
+            verbose: req.verbose,
+            sandboxer: req.sandboxer.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct UpdateJson {
+    pub sandbox_id: String,
+    pub sandboxer: String,
+    pub sandbox: Option<SandboxJson>,
+    pub fields: Vec<String>,
+}
+
+impl TryFrom<&UpdateJson> for sandbox_services::ControllerUpdateRequest {
+    type Error = base64::DecodeError;
+
+    fn try_from(req: &UpdateJson) -> Result<Self, Self::Error> {
+        Ok(sandbox_services::ControllerUpdateRequest {
+            sandbox_id: req.sandbox_id.clone(),
+            sandboxer: req.sandboxer.clone(),
+            sandbox: req.sandbox.as_ref().map(sandbox::Sandbox::try_from).transpose()?,
+            fields: req.fields.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct EmptyJson {}
+
+#[derive(Debug, Serialize)]
+pub struct CreateResponseJson {
+    pub sandbox_id: String,
+}
+
+impl From<&sandbox_services::ControllerCreateResponse> for CreateResponseJson {
+    fn from(rsp: &sandbox_services::ControllerCreateResponse) -> Self {
+        CreateResponseJson {
+            sandbox_id: rsp.sandbox_id.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StartResponseJson {
+    pub sandbox_id: String,
+    pub pid: u32,
+    pub created_at: u64,
+    pub labels: HashMap<String, String>,
+    pub address: String,
+    pub version: u32,
+}
+
+impl From<&sandbox_services::ControllerStartResponse> for StartResponseJson {
+    fn from(rsp: &sandbox_services::ControllerStartResponse) -> Self {
+        StartResponseJson {
+            sandbox_id: rsp.sandbox_id.clone(),
+            pid: rsp.pid,
+            created_at: timestamp_to_u64(&rsp.created_at),
+            labels: rsp.labels.clone(),
+            address: rsp.address.clone(),
+            version: rsp.version,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PlatformResponseJson {
+    pub os: String,
+    pub architecture: String,
+    pub variant: String,
+}
+
+impl From<&sandbox_services::ControllerPlatformResponse> for PlatformResponseJson {
+    fn from(rsp: &sandbox_services::ControllerPlatformResponse) -> Self {
+        rsp.platform.as_ref()
+            .map(|platform| PlatformResponseJson {
+                os: platform.os.clone(),
+                architecture: platform.architecture.clone(),
+                variant: platform.variant.clone(),
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct WaitResponseJson {
+    pub exit_status: u32,
+    pub exited_at: u64,
+}
+
+impl From<&sandbox_services::ControllerWaitResponse> for WaitResponseJson {
+    fn from(rsp: &sandbox_services::ControllerWaitResponse) -> Self {
+        WaitResponseJson {
+            exit_status: rsp.exit_status,
+            exited_at: timestamp_to_u64(&rsp.exited_at),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusResponseJson {
+    pub sandbox_id: String,
+    pub pid: u32,
+    pub state: String,
+    pub info: HashMap<String, String>,
+    pub created_at: u64,
+    pub exited_at: u64,
+    pub extra: Option<AnyJson>,
+    pub address: String,
+    pub version: u32,
+}
+
+impl From<&sandbox_services::ControllerStatusResponse> for StatusResponseJson {
+    fn from(rsp: &sandbox_services::ControllerStatusResponse) -> Self {
+        StatusResponseJson {
+            sandbox_id: rsp.sandbox_id.clone(),
+            pid: rsp.pid,
+            state: rsp.state.clone(),
+            info: rsp.info.clone(),
+            created_at: timestamp_to_u64(&rsp.created_at),
+            exited_at: timestamp_to_u64(&rsp.exited_at),
+            extra: any_to_json(&rsp.extra),
+            address: rsp.address.clone(),
+            version: rsp.version,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MetricsResponseJson {
+    pub timestamp: u64,
+    pub id: String,
+    pub data: Option<AnyJson>,
+}
+
+impl From<&sandbox_services::ControllerMetricsResponse> for MetricsResponseJson {
+    fn from(rsp: &sandbox_services::ControllerMetricsResponse) -> Self {
+        rsp.metrics.as_ref()
+            .map(|metrics| MetricsResponseJson {
+                timestamp: timestamp_to_u64(&metrics.timestamp),
+                id: metrics.id.clone(),
+                data: any_to_json(&metrics.data),
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Runtime;
+use tokio::task::{JoinHandle, JoinSet};
+
+use crate::controller::client::Client;
+use crate::controller::client::sandbox::containerd::services::sandbox::v1::ControllerMetricsRequest;
+use crate::controller::client::sandbox::containerd::services::sandbox::v1::ControllerMetricsResponse;
+
+struct Target {
+    client: Client,
+    request: ControllerMetricsRequest,
+}
+
+#[derive(Clone)]
+pub struct MetricsSnapshot {
+    pub metrics: ControllerMetricsResponse,
+    pub collected_at: Instant,
+}
+
+impl MetricsSnapshot {
+    pub fn age(&self) -> Duration {
+        self.collected_at.elapsed()
+    }
+}
+
+// MetricsCollector polls Metrics for every registered sandbox at a fixed
+// interval and keeps the latest response of each one, so that stats queries
+// can be answered without a round trip to the sandboxer. A failed poll keeps
+// the previous snapshot, whose age then tells how stale it is.
+pub struct MetricsCollector {
+    targets: Mutex<HashMap<String, Target>>,
+    snapshots: RwLock<HashMap<String, MetricsSnapshot>>,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl MetricsCollector {
+    pub fn new() -> Self {
+        MetricsCollector {
+            targets: Mutex::new(HashMap::new()),
+            snapshots: RwLock::new(HashMap::new()),
+            task: Mutex::new(None),
+        }
+    }
+
+    pub fn register(&self, client: Client, request: ControllerMetricsRequest) {
+        let sandbox_id = request.sandbox_id.clone();
+        self.targets.lock().unwrap().insert(sandbox_id, Target { client, request });
+    }
+
+    pub fn unregister(&self, sandbox_id: &str) -> bool {
+        let mut targets = self.targets.lock().unwrap();
+        self.snapshots.write().unwrap().remove(sandbox_id);
+        targets.remove(sandbox_id).is_some()
+    }
+
+    pub fn snapshot(&self, sandbox_id: &str) -> Option<MetricsSnapshot> {
+        self.snapshots.read().unwrap().get(sandbox_id).cloned()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.task.lock().unwrap().as_ref().is_some_and(|task| !task.is_finished())
+    }
+
+    pub fn start(self: &Arc<Self>, rt: &Runtime, interval: Duration) -> Result<(), String> {
+        if interval.is_zero() {
+            return Err("metrics collector interval must not be zero".to_string());
+        }
+        let mut task = self.task.lock().unwrap();
+        if task.as_ref().is_some_and(|task| !task.is_finished()) {
+            return Err("metrics collector already started".to_string());
+        }
+        let collector = self.clone();
+        *task = Some(rt.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                collector.collect(interval).await;
+            }
+        }));
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+    }
+
+    // store keeps metrics as the latest snapshot of a sandbox, unless the
+    // sandbox has been unregistered while polling.
+    fn store(&self, sandbox_id: String, metrics: ControllerMetricsResponse) {
+        let targets = self.targets.lock().unwrap();
+        if !targets.contains_key(&sandbox_id) {
+            return;
+        }
+        self.snapshots.write().unwrap().insert(sandbox_id, MetricsSnapshot {
+            metrics,
+            collected_at: Instant::now(),
+        });
+    }
+
+    // Poll all registered sandboxes concurrently, each one bounded by the
+    // interval so that a slow sandboxer cannot hold up the next round.
+    async fn collect(self: &Arc<Self>, timeout: Duration) {
+        let mut polls = JoinSet::new();
+        for target in self.targets.lock().unwrap().values() {
+            let mut client = target.client.clone();
+            let request = target.request.clone();
+            polls.spawn(async move {
+                let sandbox_id = request.sandbox_id.clone();
+                let result = tokio::time::timeout(timeout, client.metrics(request)).await;
+                (sandbox_id, result)
+            });
+        }
+
+        while let Some(joined) = polls.join_next().await {
+            let (sandbox_id, result) = match joined {
+                Ok(polled) => polled,
+                Err(e) => {
+                    println!("Sandbox API: Metrics collector task failed, {:?}", e);
+                    continue;
+                }
+            };
+            match result {
+                Ok(Ok(metrics)) => self.store(sandbox_id, metrics),
+                Ok(Err(e)) => {
+                    println!("Sandbox API: Failed to collect metrics, {:?}, {:?}", sandbox_id, e);
+                }
+                Err(_) => {
+                    println!("Sandbox API: Collect metrics timed out, {:?}", sandbox_id);
+                }
+            }
+        }
+    }
+}
+
+impl Default for MetricsCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::client::sandbox::containerd::services::sandbox::v1::controller_client::ControllerClient;
+    use crate::controller::client::sandbox::containerd::types::Metric;
+
+    // A client of a sandboxer that is not listening: every call fails.
+    fn unreachable_client() -> Client {
+        let channel = tonic::transport::Endpoint::from_static("http://127.0.0.1:1").connect_lazy();
+        Client { channel: channel.clone(), client: ControllerClient::new(channel) }
+    }
+
+    fn request(sandbox_id: &str) -> ControllerMetricsRequest {
+        ControllerMetricsRequest { sandbox_id: sandbox_id.to_string(), ..Default::default() }
+    }
+
+    fn metrics(sandbox_id: &str) -> ControllerMetricsResponse {
+        ControllerMetricsResponse {
+            metrics: Some(Metric { id: sandbox_id.to_string(), ..Default::default() }),
+        }
+    }
+
+    #[test]
+    fn snapshots_of_registered_sandboxes() {
+        let rt = Runtime::new().unwrap();
+        let _guard = rt.enter();
+        let collector = MetricsCollector::new();
+
+        collector.store("sb-1".to_string(), metrics("sb-1"));
+        assert!(collector.snapshot("sb-1").is_none());
+
+        collector.register(unreachable_client(), request("sb-1"));
+        collector.store("sb-1".to_string(), metrics("sb-1"));
+        let snapshot = collector.snapshot("sb-1").unwrap();
+        assert_eq!(snapshot.metrics.metrics.as_ref().unwrap().id, "sb-1");
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(snapshot.age() >= Duration::from_millis(20));
+        assert!(collector.snapshot("sb-2").is_none());
+
+        assert!(collector.unregister("sb-1"));
+        assert!(collector.snapshot("sb-1").is_none());
+        assert!(!collector.unregister("sb-1"));
+    }
+
+    #[test]
+    fn failed_poll_keeps_snapshot() {
+        let rt = Runtime::new().unwrap();
+        let collector = Arc::new(MetricsCollector::new());
+        {
+            let _guard = rt.enter();
+            collector.register(unreachable_client(), request("sb-1"));
+        }
+        collector.store("sb-1".to_string(), metrics("sb-1"));
+        let collected_at = collector.snapshot("sb-1").unwrap().collected_at;
+
+        rt.block_on(collector.collect(Duration::from_millis(200)));
+        assert_eq!(collector.snapshot("sb-1").unwrap().collected_at, collected_at);
+    }
+
+    #[test]
+    fn start_and_stop() {
+        let rt = Runtime::new().unwrap();
+        let collector = Arc::new(MetricsCollector::new());
+        assert!(!collector.is_running());
+        assert!(collector.start(&rt, Duration::ZERO).is_err());
+        assert!(!collector.is_running());
+
+        collector.start(&rt, Duration::from_secs(60)).unwrap();
+        assert!(collector.is_running());
+        assert!(collector.start(&rt, Duration::from_secs(60)).is_err());
+
+        collector.stop();
+        collector.stop();
+        assert!(!collector.is_running());
+        collector.start(&rt, Duration::from_secs(60)).unwrap();
+        assert!(collector.is_running());
+        collector.stop();
+    }
+}
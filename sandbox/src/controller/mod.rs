@@ -11,4 +11,6 @@
 // See the Mulan PSL v2 for more details.
 
 #[macro_use]
-pub mod client;
\ No newline at end of file
+pub mod client;
+pub mod collector;
+pub mod status_cache;
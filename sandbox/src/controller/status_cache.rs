@@ -0,0 +1,255 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use crate::controller::client::sandbox::containerd::services::sandbox::v1::ControllerStatusResponse;
+
+pub const DEFAULT_STATUS_CACHE_TTL: Duration = Duration::from_millis(1000);
+
+struct Entry {
+    status: ControllerStatusResponse,
+    verbose: bool,
+    cached_at: Instant,
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<String, Entry>,
+    // Bumped by every invalidation of a sandbox, so that a Status response
+    // requested before the invalidation is not cached after it. A generation
+    // is kept for a TTL after the invalidation only; sandboxes without one
+    // are at the floor, which is raised past every generation dropped.
+    generations: HashMap<String, (u64, Instant)>,
+    next_generation: u64,
+    floor: u64,
+}
+
+impl Entries {
+    fn generation(&self, sandbox_id: &str) -> u64 {
+        self.generations.get(sandbox_id).map(|(generation, _)| *generation).unwrap_or(self.floor)
+    }
+
+    fn raise_floor(&mut self) {
+        self.next_generation += 1;
+        self.floor = self.next_generation;
+    }
+
+    // prune drops the entries and the generations older than the TTL. A
+    // Status response requested before a dropped generation is refused by
+    // the raised floor, so this only costs the caching of slow responses.
+    fn prune(&mut self, ttl: Duration) {
+        self.entries.retain(|_, entry| entry.cached_at.elapsed() < ttl);
+        let len = self.generations.len();
+        self.generations.retain(|_, (_, invalidated_at)| invalidated_at.elapsed() < ttl);
+        if self.generations.len() != len {
+            self.raise_floor();
+        }
+    }
+}
+
+// StatusCache keeps the last Status response of each sandbox for a short
+// time. A verbose response can answer both kinds of requests, a non-verbose
+// one only non-verbose requests. Entries are dropped as soon as the sandbox
+// changes state through this library (start, stop, shutdown, update or the
+// exit reported by Wait), so the TTL only bounds staleness caused by changes
+// made elsewhere. A zero TTL disables the cache.
+//
+// Callers take the generation of a sandbox before sending Status and pass
+// it to insert, which drops the response if the sandbox has been
+// invalidated since.
+pub struct StatusCache {
+    ttl: RwLock<Duration>,
+    entries: RwLock<Entries>,
+}
+
+impl StatusCache {
+    pub fn new(ttl: Duration) -> Self {
+        StatusCache {
+            ttl: RwLock::new(ttl),
+            entries: RwLock::new(Entries::default()),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        *self.ttl.read().unwrap()
+    }
+
+    pub fn set_ttl(&self, ttl: Duration) {
+        *self.ttl.write().unwrap() = ttl;
+        self.entries.write().unwrap().prune(ttl);
+    }
+
+    pub fn get(&self, sandbox_id: &str, verbose: bool) -> Option<ControllerStatusResponse> {
+        let ttl = self.ttl();
+        let entries = self.entries.read().unwrap();
+        let entry = entries.entries.get(sandbox_id)?;
+        if entry.cached_at.elapsed() >= ttl || (verbose && !entry.verbose) {
+            return None;
+        }
+        Some(entry.status.clone())
+    }
+
+    pub fn generation(&self, sandbox_id: &str) -> u64 {
+        self.entries.read().unwrap().generation(sandbox_id)
+    }
+
+    pub fn insert(&self, sandbox_id: &str, generation: u64, verbose: bool, status: &ControllerStatusResponse) {
+        let ttl = self.ttl();
+        if ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.write().unwrap();
+        if entries.generation(sandbox_id) != generation {
+            return;
+        }
+        entries.prune(ttl);
+        entries.entries.insert(sandbox_id.to_string(), Entry {
+            status: status.clone(),
+            verbose,
+            cached_at: Instant::now(),
+        });
+    }
+
+    pub fn invalidate(&self, sandbox_id: &str) {
+        let ttl = self.ttl();
+        let mut entries = self.entries.write().unwrap();
+        entries.prune(ttl);
+        entries.entries.remove(sandbox_id);
+        entries.next_generation += 1;
+        let generation = entries.next_generation;
+        entries.generations.insert(sandbox_id.to_string(), (generation, Instant::now()));
+    }
+
+    // remove forgets a sandbox that has been shut down.
+    pub fn remove(&self, sandbox_id: &str) {
+        let mut entries = self.entries.write().unwrap();
+        entries.entries.remove(sandbox_id);
+        entries.generations.remove(sandbox_id);
+        entries.raise_floor();
+    }
+}
+
+impl Default for StatusCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_STATUS_CACHE_TTL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(state: &str) -> ControllerStatusResponse {
+        ControllerStatusResponse { state: state.to_string(), ..Default::default() }
+    }
+
+    fn state(cache: &StatusCache, verbose: bool) -> Option<String> {
+        cache.get("sb-1", verbose).map(|s| s.state)
+    }
+
+    #[test]
+    fn entries_expire_after_ttl() {
+        let cache = StatusCache::new(Duration::from_millis(50));
+        cache.insert("sb-1", cache.generation("sb-1"), false, &status("running"));
+        assert_eq!(state(&cache, false).as_deref(), Some("running"));
+        assert_eq!(cache.get("sb-2", false), None);
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(state(&cache, false), None);
+    }
+
+    #[test]
+    fn verbose_entries_answer_both() {
+        let cache = StatusCache::default();
+        cache.insert("sb-1", 0, false, &status("running"));
+        assert_eq!(state(&cache, false).as_deref(), Some("running"));
+        assert_eq!(state(&cache, true), None);
+
+        cache.insert("sb-1", 0, true, &status("stopped"));
+        assert_eq!(state(&cache, false).as_deref(), Some("stopped"));
+        assert_eq!(state(&cache, true).as_deref(), Some("stopped"));
+    }
+
+    #[test]
+    fn invalidate_drops_entry() {
+        let cache = StatusCache::default();
+        cache.insert("sb-1", 0, false, &status("running"));
+        cache.invalidate("sb-1");
+        assert_eq!(state(&cache, false), None);
+
+        cache.insert("sb-1", cache.generation("sb-1"), false, &status("stopped"));
+        assert_eq!(state(&cache, false).as_deref(), Some("stopped"));
+    }
+
+    #[test]
+    fn invalidate_racing_insert() {
+        let cache = StatusCache::default();
+        // Status is sent, the sandbox exits, then the response comes back.
+        let generation = cache.generation("sb-1");
+        cache.invalidate("sb-1");
+        cache.insert("sb-1", generation, false, &status("running"));
+        assert_eq!(state(&cache, false), None);
+
+        // Other sandboxes are not affected.
+        cache.insert("sb-2", cache.generation("sb-2"), false, &status("running"));
+        assert!(cache.get("sb-2", false).is_some());
+    }
+
+    #[test]
+    fn remove_forgets_sandbox() {
+        let cache = StatusCache::default();
+        cache.invalidate("sb-1");
+        let generation = cache.generation("sb-1");
+        cache.insert("sb-1", generation, false, &status("running"));
+        cache.remove("sb-1");
+        assert_eq!(state(&cache, false), None);
+        assert!(cache.entries.read().unwrap().generations.is_empty());
+        // A Status response requested before the shutdown is not cached.
+        cache.insert("sb-1", generation, false, &status("running"));
+        assert_eq!(state(&cache, false), None);
+    }
+
+    #[test]
+    fn generations_expire_after_ttl() {
+        let cache = StatusCache::new(Duration::from_millis(50));
+        let generation = cache.generation("sb-1");
+        cache.invalidate("sb-1");
+        cache.invalidate("sb-2");
+        std::thread::sleep(Duration::from_millis(60));
+        cache.invalidate("sb-3");
+        assert_eq!(cache.entries.read().unwrap().generations.len(), 1);
+
+        // Requested before the dropped invalidation, so still not cached.
+        cache.insert("sb-1", generation, false, &status("running"));
+        assert_eq!(state(&cache, false), None);
+        cache.insert("sb-1", cache.generation("sb-1"), false, &status("stopped"));
+        assert_eq!(state(&cache, false).as_deref(), Some("stopped"));
+    }
+
+    #[test]
+    fn zero_ttl_disables_cache() {
+        let cache = StatusCache::default();
+        cache.insert("sb-1", 0, true, &status("running"));
+        cache.set_ttl(Duration::ZERO);
+        assert_eq!(state(&cache, false), None);
+        cache.insert("sb-1", 0, true, &status("running"));
+        assert_eq!(state(&cache, false), None);
+
+        cache.set_ttl(DEFAULT_STATUS_CACHE_TTL);
+        assert_eq!(cache.ttl(), DEFAULT_STATUS_CACHE_TTL);
+        assert_eq!(state(&cache, false), None);
+        cache.insert("sb-1", 0, true, &status("running"));
+        assert_eq!(state(&cache, true).as_deref(), Some("running"));
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright (c) 2024 Huawei Technologies Co.,Ltd. All rights reserved.
+//
+// isula-rust-extensions is licensed under Mulan PSL v2.
+// You can use this software according to the terms and conditions of the Mulan
+// PSL v2.
+// You may obtain a copy of Mulan PSL v2 at:
+//         http://license.coscl.org.cn/MulanPSL2
+// THIS SOFTWARE IS PROVIDED ON AN "AS IS" BASIS, WITHOUT WARRANTIES OF ANY
+// KIND, EITHER EXPRESS OR IMPLIED, INCLUDING BUT NOT LIMITED TO
+// NON-INFRINGEMENT, MERCHANTABILITY OR FIT FOR A PARTICULAR PURPOSE.
+// See the Mulan PSL v2 for more details.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use tonic::Status;
+
+use crate::controller::client::sandbox::containerd::types as sandbox;
+use crate::datatype::sandbox_types::SandboxSandbox;
+
+const LABELS: &str = "labels";
+const EXTENSIONS: &str = "extensions";
+const SPEC: &str = "spec";
+const RUNTIME: &str = "runtime";
+
+// SandboxField is one updatable path of containerd.types.Sandbox, as accepted
+// in the fields of an update request. Labels and extensions can be updated as
+// a whole or per key, in which case the key is everything after the first dot.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SandboxField {
+    Labels,
+    Label(String),
+    Extensions,
+    Extension(String),
+    Spec,
+    Runtime,
+}
+
+impl FromStr for SandboxField {
+    type Err = Status;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let field = match path.split_once('.') {
+            None => match path {
+                LABELS => SandboxField::Labels,
+                EXTENSIONS => SandboxField::Extensions,
+                SPEC => SandboxField::Spec,
+                RUNTIME => SandboxField::Runtime,
+                _ => return Err(Status::invalid_argument(format!("unsupported update field {:?}", path))),
+            },
+            Some((_, "")) => {
+                return Err(Status::invalid_argument(format!("update field {:?} has an empty key", path)));
+            }
+            Some((LABELS, key)) => SandboxField::Label(key.to_string()),
+            Some((EXTENSIONS, key)) => SandboxField::Extension(key.to_string()),
+            Some(_) => return Err(Status::invalid_argument(format!("unsupported update field {:?}", path))),
+        };
+        Ok(field)
+    }
+}
+
+impl fmt::Display for SandboxField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxField::Labels => write!(f, "{}", LABELS),
+            SandboxField::Label(key) => write!(f, "{}.{}", LABELS, key),
+            SandboxField::Extensions => write!(f, "{}", EXTENSIONS),
+            SandboxField::Extension(key) => write!(f, "{}.{}", EXTENSIONS, key),
+            SandboxField::Spec => write!(f, "{}", SPEC),
+            SandboxField::Runtime => write!(f, "{}", RUNTIME),
+        }
+    }
+}
+
+// FieldMask collects the fields of an update request. Adding a key of labels
+// or extensions is a no-op once the whole map is in the mask, and adding the
+// whole map drops the keys collected so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldMask {
+    fields: BTreeSet<SandboxField>,
+}
+
+impl FieldMask {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn parse<S: AsRef<str>>(paths: &[S]) -> Result<Self, Status> {
+        let mut mask = FieldMask::new();
+        for path in paths {
+            mask.add(path.as_ref().parse()?);
+        }
+        Ok(mask)
+    }
+
+    pub fn add(&mut self, field: SandboxField) -> &mut Self {
+        match &field {
+            SandboxField::Label(_) if self.fields.contains(&SandboxField::Labels) => return self,
+            SandboxField::Extension(_) if self.fields.contains(&SandboxField::Extensions) => return self,
+            SandboxField::Labels => self.fields.retain(|f| !matches!(f, SandboxField::Label(_))),
+            SandboxField::Extensions => self.fields.retain(|f| !matches!(f, SandboxField::Extension(_))),
+            _ => {}
+        }
+        self.fields.insert(field);
+        self
+    }
+
+    pub fn labels(&mut self) -> &mut Self {
+        self.add(SandboxField::Labels)
+    }
+
+    pub fn label(&mut self, key: &str) -> &mut Self {
+        self.add(SandboxField::Label(key.to_string()))
+    }
+
+    pub fn extensions(&mut self) -> &mut Self {
+        self.add(SandboxField::Extensions)
+    }
+
+    pub fn extension(&mut self, key: &str) -> &mut Self {
+        self.add(SandboxField::Extension(key.to_string()))
+    }
+
+    pub fn spec(&mut self) -> &mut Self {
+        self.add(SandboxField::Spec)
+    }
+
+    pub fn runtime(&mut self) -> &mut Self {
+        self.add(SandboxField::Runtime)
+    }
+
+    // diff returns the fields that have to be updated to turn old into new.
+    // Labels and extensions are compared per key, so that removed keys are
+    // part of the mask as well.
+    pub fn diff(old: &sandbox::Sandbox, new: &sandbox::Sandbox) -> Self {
+        let mut mask = FieldMask::new();
+        for key in old.labels.keys().chain(new.labels.keys()) {
+            if old.labels.get(key) != new.labels.get(key) {
+                mask.label(key);
+            }
+        }
+        for key in old.extensions.keys().chain(new.extensions.keys()) {
+            if old.extensions.get(key) != new.extensions.get(key) {
+                mask.extension(key);
+            }
+        }
+        if old.spec != new.spec {
+            mask.spec();
+        }
+        if old.runtime != new.runtime {
+            mask.runtime();
+        }
+        mask
+    }
+
+    // diff_c is diff for the sandboxes passed in by iSulad.
+    #[allow(clippy::result_large_err)]
+    pub fn diff_c(old: &SandboxSandbox, new: &SandboxSandbox) -> Result<Self, Status> {
+        Ok(Self::diff(&sandbox::Sandbox::try_from(old)?, &sandbox::Sandbox::try_from(new)?))
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = &SandboxField> {
+        self.fields.iter()
+    }
+
+    pub fn paths(&self) -> Vec<String> {
+        self.fields.iter().map(|f| f.to_string()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tonic::Code;
+
+    fn any(value: &[u8]) -> prost_types::Any {
+        prost_types::Any { type_url: "test".to_string(), value: value.to_vec() }
+    }
+
+    fn labels(items: &[(&str, &str)]) -> HashMap<String, String> {
+        items.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn extensions(items: &[(&str, &[u8])]) -> HashMap<String, prost_types::Any> {
+        items.iter().map(|(k, v)| (k.to_string(), any(v))).collect()
+    }
+
+    #[test]
+    fn parse_keys() {
+        let mask = FieldMask::parse(&["labels.app", "extensions.a.b", "spec", "runtime"]).unwrap();
+        assert_eq!(mask.fields().cloned().collect::<Vec<_>>(), vec![
+            SandboxField::Label("app".to_string()),
+            SandboxField::Extension("a.b".to_string()),
+            SandboxField::Spec,
+            SandboxField::Runtime,
+        ]);
+        assert_eq!(mask.paths(), vec!["labels.app", "extensions.a.b", "spec", "runtime"]);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_paths() {
+        for path in ["status", "labels.", "extensions.", "spec.x", "sandbox_id", ""] {
+            let status = FieldMask::parse(&[path]).unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument, "{}", path);
+        }
+    }
+
+    #[test]
+    fn parse_normalizes() {
+        let mask = FieldMask::parse(&["spec", "labels.b", "labels.a", "labels.b"]).unwrap();
+        assert_eq!(mask.paths(), vec!["labels.a", "labels.b", "spec"]);
+
+        let mask = FieldMask::parse(&["labels.a", "labels", "labels.b", "extensions.x"]).unwrap();
+        assert_eq!(mask.paths(), vec!["labels", "extensions.x"]);
+        assert!(FieldMask::parse::<&str>(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn diff_labels_and_extensions() {
+        let old = sandbox::Sandbox {
+            labels: labels(&[("kept", "1"), ("changed", "1"), ("removed", "1")]),
+            extensions: extensions(&[("kept", b"1"), ("changed", b"1"), ("removed", b"1")]),
+            ..Default::default()
+        };
+        let new = sandbox::Sandbox {
+            labels: labels(&[("kept", "1"), ("changed", "2"), ("added", "1")]),
+            extensions: extensions(&[("kept", b"1"), ("changed", b"2"), ("added", b"1")]),
+            ..Default::default()
+        };
+        assert_eq!(FieldMask::diff(&old, &new).paths(), vec![
+            "labels.added", "labels.changed", "labels.removed",
+            "extensions.added", "extensions.changed", "extensions.removed",
+        ]);
+        assert!(FieldMask::diff(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn diff_spec_and_runtime() {
+        let old = sandbox::Sandbox::default();
+        let new = sandbox::Sandbox {
+            spec: Some(any(b"spec")),
+            runtime: Some(sandbox::sandbox::Runtime { name: "runc".to_string(), options: None }),
+            ..Default::default()
+        };
+        assert_eq!(FieldMask::diff(&old, &new).paths(), vec!["spec", "runtime"]);
+    }
+
+    #[test]
+    fn diff_c_sandboxes() {
+        let old = sandbox::Sandbox { labels: labels(&[("app", "a")]), ..Default::default() };
+        let new = sandbox::Sandbox { labels: labels(&[("app", "b")]), ..Default::default() };
+        let mask = FieldMask::diff_c(&SandboxSandbox::from(&old), &SandboxSandbox::from(&new)).unwrap();
+        assert_eq!(mask.paths(), vec!["labels.app"]);
+    }
+}
@@ -11,4 +11,5 @@
 // See the Mulan PSL v2 for more details.
 
 #[macro_use]
-pub mod sandbox_types;
\ No newline at end of file
+pub mod sandbox_types;
+pub mod field_mask;
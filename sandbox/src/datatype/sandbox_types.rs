@@ -12,14 +12,83 @@
 
 
 use std::os::raw::{c_char, c_void};
+use tonic::Status;
 use isula_common::isula_data_types::{Any, MapStringAny, MapStringString};
-use isula_common::isula_data_types::{to_string, to_c_char_ptr};
-use isula_common::isula_data_types::{vec_to_c_char_ptr_ptr, c_char_ptr_ptr_to_vec};
-use isula_common::isula_data_types::double_ptr_to_vec;
+use isula_common::isula_data_types::{try_to_string, to_c_char_ptr};
+use isula_common::isula_data_types::vec_to_c_char_ptr_ptr;
 use isula_common::isula_data_types::u64_to_prost_timestamp;
 use isula_common::isula_data_types::prost_timestamp_to_u64;
 use crate::controller::client::sandbox::containerd::types as sandbox;
 use crate::controller::client::sandbox::containerd::services::sandbox::v1 as sandbox_services;
+use crate::datatype::field_mask::FieldMask;
+
+// tonic::Status is the error type of every request conversion.
+#[allow(clippy::result_large_err)]
+fn optional_string(ptr: *const c_char, name: &str) -> Result<String, Status> {
+    try_to_string(ptr).map_err(|_| Status::invalid_argument(format!("{} is not valid UTF-8", name)))
+}
+
+#[allow(clippy::result_large_err)]
+fn required_string(ptr: *const c_char, name: &str) -> Result<String, Status> {
+    let value = optional_string(ptr, name)?;
+    if value.is_empty() {
+        return Err(Status::invalid_argument(format!("{} is required", name)));
+    }
+    Ok(value)
+}
+
+#[allow(clippy::result_large_err)]
+fn string_array(ptr: *const *const c_char, len: usize, name: &str) -> Result<Vec<String>, Status> {
+    if ptr.is_null() {
+        if len != 0 {
+            return Err(Status::invalid_argument(format!("{} is null but has {} elements", name, len)));
+        }
+        return Ok(Vec::new());
+    }
+    let slice = unsafe { std::slice::from_raw_parts(ptr, len) };
+    let mut vec = Vec::with_capacity(len);
+    for (i, item) in slice.iter().enumerate() {
+        if item.is_null() {
+            return Err(Status::invalid_argument(format!("{}[{}] is null", name, i)));
+        }
+        vec.push(optional_string(*item, &format!("{}[{}]", name, i))?);
+    }
+    Ok(vec)
+}
+
+#[allow(clippy::result_large_err)]
+fn rootfs_array(ptr: *const *const SandboxMount, len: usize) -> Result<Vec<sandbox::Mount>, Status> {
+    if ptr.is_null() {
+        if len != 0 {
+            return Err(Status::invalid_argument(format!("rootfs is null but has {} elements", len)));
+        }
+        return Ok(Vec::new());
+    }
+    let slice = unsafe { std::slice::from_raw_parts(ptr, len) };
+    let mut vec = Vec::with_capacity(len);
+    for (i, item) in slice.iter().enumerate() {
+        let mnt = match unsafe { item.as_ref() } {
+            Some(mnt) => mnt,
+            None => return Err(Status::invalid_argument(format!("rootfs[{}] is null", i))),
+        };
+        let mnt = sandbox::Mount::try_from(mnt)
+            .map_err(|e| Status::invalid_argument(format!("rootfs[{}]: {}", i, e.message())))?;
+        vec.push(mnt);
+    }
+    Ok(vec)
+}
+
+// The sandbox carried by a request must describe the sandbox the request is for.
+#[allow(clippy::result_large_err)]
+fn check_sandbox_id(r_sandbox: &Option<sandbox::Sandbox>, sandbox_id: &str) -> Result<(), Status> {
+    if let Some(r_sandbox) = r_sandbox {
+        if !r_sandbox.sandbox_id.is_empty() && r_sandbox.sandbox_id != sandbox_id {
+            return Err(Status::invalid_argument(format!(
+                "sandbox.sandbox_id {:?} does not match sandbox_id {:?}", r_sandbox.sandbox_id, sandbox_id)));
+        }
+    }
+    Ok(())
+}
 
 #[repr(C)]
 pub struct SandboxMount {
@@ -31,14 +100,16 @@ pub struct SandboxMount {
     residual: *const c_void,
 }
 
-impl From<&SandboxMount> for sandbox::Mount {
-    fn from(mnt: &SandboxMount) -> Self {
+impl TryFrom<&SandboxMount> for sandbox::Mount {
+    type Error = Status;
+
+    fn try_from(mnt: &SandboxMount) -> Result<Self, Self::Error> {
         let mut r_mnt = sandbox::Mount::default();
-        r_mnt.target = to_string(mnt.destination);
-        r_mnt.r#type = to_string(mnt.type_);
-        r_mnt.source = to_string(mnt.source);
-        r_mnt.options = c_char_ptr_ptr_to_vec(mnt.options, mnt.options_len);
-        r_mnt
+        r_mnt.target = optional_string(mnt.destination, "destination")?;
+        r_mnt.r#type = required_string(mnt.type_, "type")?;
+        r_mnt.source = required_string(mnt.source, "source")?;
+        r_mnt.options = string_array(mnt.options, mnt.options_len, "options")?;
+        Ok(r_mnt)
     }
 }
 
@@ -64,12 +135,14 @@ pub struct SandboxSandboxRuntime {
     residual: *const c_void,
 }
 
-impl From<&SandboxSandboxRuntime> for sandbox::sandbox::Runtime {
-    fn from(req: &SandboxSandboxRuntime) -> Self {
+impl TryFrom<&SandboxSandboxRuntime> for sandbox::sandbox::Runtime {
+    type Error = Status;
+
+    fn try_from(req: &SandboxSandboxRuntime) -> Result<Self, Self::Error> {
         let mut r_req = sandbox::sandbox::Runtime::default();
-        r_req.name = to_string(req.name);
+        r_req.name = optional_string(req.name, "runtime.name")?;
         r_req.options = unsafe {req.options.as_ref()}.map(|prost_any| prost_types::Any::from(prost_any));
-        r_req
+        Ok(r_req)
     }
 }
 
@@ -99,11 +172,15 @@ pub struct SandboxSandbox {
     residual: *const c_void,
 }
 
-impl From<&SandboxSandbox> for sandbox::Sandbox {
-    fn from(sandbox: &SandboxSandbox) -> Self {
+impl TryFrom<&SandboxSandbox> for sandbox::Sandbox {
+    type Error = Status;
+
+    fn try_from(sandbox: &SandboxSandbox) -> Result<Self, Self::Error> {
         let mut r_sandbox = sandbox::Sandbox::default();
-        r_sandbox.sandbox_id = to_string(sandbox.sandbox_id);
-        r_sandbox.runtime = unsafe { sandbox.runtime.as_ref() }.map(|rt| sandbox::sandbox::Runtime::from(&*rt));
+        r_sandbox.sandbox_id = optional_string(sandbox.sandbox_id, "sandbox.sandbox_id")?;
+        r_sandbox.runtime = unsafe { sandbox.runtime.as_ref() }
+            .map(sandbox::sandbox::Runtime::try_from)
+            .transpose()?;
         r_sandbox.spec = unsafe {sandbox.spec.as_ref()}.map(|prost_any| prost_types::Any::from(prost_any));
         r_sandbox.labels = unsafe {sandbox.labels.as_ref()}
             .map(|map| <std::collections::HashMap<String, String>>::from(&*map))
@@ -113,8 +190,8 @@ impl From<&SandboxSandbox> for sandbox::Sandbox {
         r_sandbox.extensions = unsafe {sandbox.extensions.as_ref()}
             .map(|map| <std::collections::HashMap<String, prost_types::Any>>::from(&*map))
             .unwrap_or(std::collections::HashMap::new());
-        r_sandbox.sandboxer = to_string(sandbox.sandboxer);
-        r_sandbox
+        r_sandbox.sandboxer = optional_string(sandbox.sandboxer, "sandbox.sandboxer")?;
+        Ok(r_sandbox)
     }
 }
 
@@ -157,19 +234,24 @@ pub struct SandboxCreateRequest {
     residual: *const c_void,
 }
 
-impl From<&SandboxCreateRequest> for sandbox_services::ControllerCreateRequest {
-    fn from(req: &SandboxCreateRequest) -> Self {
+impl TryFrom<&SandboxCreateRequest> for sandbox_services::ControllerCreateRequest {
+    type Error = Status;
+
+    fn try_from(req: &SandboxCreateRequest) -> Result<Self, Self::Error> {
         let mut r_req = sandbox_services::ControllerCreateRequest::default();
-        r_req.sandbox_id = to_string(req.sandbox_id);
-        r_req.rootfs = double_ptr_to_vec(req.rootfs, req.rootfs_len);
+        r_req.sandbox_id = required_string(req.sandbox_id, "sandbox_id")?;
+        r_req.rootfs = rootfs_array(req.rootfs, req.rootfs_len)?;
         r_req.options = unsafe {req.options.as_ref()}.map(|any| prost_types::Any::from(&*any));
-        r_req.netns_path = to_string(req.netns_path);
+        r_req.netns_path = optional_string(req.netns_path, "netns_path")?;
         r_req.annotations = unsafe {req.annotations.as_ref()}
             .map(|map| <std::collections::HashMap<String, String>>::from(&*map))
             .unwrap_or(std::collections::HashMap::new());
-        r_req.sandbox = unsafe { req.sandbox.as_ref() }.map(|sandbox| sandbox::Sandbox::from(&*sandbox));
-        r_req.sandboxer = to_string(req.sandboxer);
-        r_req
+        r_req.sandbox = unsafe { req.sandbox.as_ref() }
+            .map(sandbox::Sandbox::try_from)
+            .transpose()?;
+        check_sandbox_id(&r_req.sandbox, &r_req.sandbox_id)?;
+        r_req.sandboxer = required_string(req.sandboxer, "sandboxer")?;
+        Ok(r_req)
     }
 }
 
@@ -192,12 +274,14 @@ pub struct SandboxStartRequest {
     residual: *const c_void,
 }
 
-impl From<&SandboxStartRequest> for sandbox_services::ControllerStartRequest {
-    fn from(req: &SandboxStartRequest) -> Self {
+impl TryFrom<&SandboxStartRequest> for sandbox_services::ControllerStartRequest {
+    type Error = Status;
+
+    fn try_from(req: &SandboxStartRequest) -> Result<Self, Self::Error> {
         let mut r_req = sandbox_services::ControllerStartRequest::default();
-        r_req.sandbox_id = to_string(req.sandbox_id);
-        r_req.sandboxer = to_string(req.sandboxer);
-        r_req
+        r_req.sandbox_id = required_string(req.sandbox_id, "sandbox_id")?;
+        r_req.sandboxer = required_string(req.sandboxer, "sandboxer")?;
+        Ok(r_req)
     }
 }
 
@@ -232,12 +316,14 @@ pub struct SandboxPlatformRequest {
     residual: *const c_void,
 }
 
-impl From<&SandboxPlatformRequest> for sandbox_services::ControllerPlatformRequest {
-    fn from(req: &SandboxPlatformRequest) -> Self {
+impl TryFrom<&SandboxPlatformRequest> for sandbox_services::ControllerPlatformRequest {
+    type Error = Status;
+
+    fn try_from(req: &SandboxPlatformRequest) -> Result<Self, Self::Error> {
         let mut r_req = sandbox_services::ControllerPlatformRequest::default();
-        r_req.sandbox_id = to_string(req.sandbox_id);
-        r_req.sandboxer = to_string(req.sandboxer);
-        r_req
+        r_req.sandbox_id = required_string(req.sandbox_id, "sandbox_id")?;
+        r_req.sandboxer = required_string(req.sandboxer, "sandboxer")?;
+        Ok(r_req)
     }
 }
 
@@ -267,13 +353,15 @@ pub struct SandboxStopRequest {
     residual: *const c_void,
 }
 
-impl From<&SandboxStopRequest> for sandbox_services::ControllerStopRequest {
-    fn from(req: &SandboxStopRequest) -> Self {
+impl TryFrom<&SandboxStopRequest> for sandbox_services::ControllerStopRequest {
+    type Error = Status;
+
+    fn try_from(req: &SandboxStopRequest) -> Result<Self, Self::Error> {
         let mut r_req = sandbox_services::ControllerStopRequest::default();
-        r_req.sandbox_id = to_string(req.sandbox_id);
+        r_req.sandbox_id = required_string(req.sandbox_id, "sandbox_id")?;
         r_req.timeout_secs = req.timeout_secs;
-        r_req.sandboxer = to_string(req.sandboxer);
-        r_req
+        r_req.sandboxer = required_string(req.sandboxer, "sandboxer")?;
+        Ok(r_req)
     }
 }
 
@@ -284,12 +372,14 @@ pub struct SandboxWaitRequest {
     residual: *const c_void,
 }
 
-impl From<&SandboxWaitRequest> for sandbox_services::ControllerWaitRequest {
-    fn from(req: &SandboxWaitRequest) -> Self {
+impl TryFrom<&SandboxWaitRequest> for sandbox_services::ControllerWaitRequest {
+    type Error = Status;
+
+    fn try_from(req: &SandboxWaitRequest) -> Result<Self, Self::Error> {
         let mut r_req = sandbox_services::ControllerWaitRequest::default();
-        r_req.sandbox_id = to_string(req.sandbox_id);
-        r_req.sandboxer = to_string(req.sandboxer);
-        r_req
+        r_req.sandbox_id = required_string(req.sandbox_id, "sandbox_id")?;
+        r_req.sandboxer = required_string(req.sandboxer, "sandboxer")?;
+        Ok(r_req)
     }
 }
 
@@ -327,13 +417,15 @@ pub struct SandboxStatusRequest {
     residual: *const c_void,
 }
 
-impl From<&SandboxStatusRequest> for sandbox_services::ControllerStatusRequest {
-    fn from(req: &SandboxStatusRequest) -> Self {
+impl TryFrom<&SandboxStatusRequest> for sandbox_services::ControllerStatusRequest {
+    type Error = Status;
+
+    fn try_from(req: &SandboxStatusRequest) -> Result<Self, Self::Error> {
         let mut r_req = sandbox_services::ControllerStatusRequest::default();
-        r_req.sandbox_id = to_string(req.sandbox_id);
+        r_req.sandbox_id = required_string(req.sandbox_id, "sandbox_id")?;
         r_req.verbose = req.verbose;
-        r_req.sandboxer = to_string(req.sandboxer);
-        r_req
+        r_req.sandboxer = required_string(req.sandboxer, "sandboxer")?;
+        Ok(r_req)
     }
 }
 
@@ -378,12 +470,14 @@ pub struct SandboxShutdownRequest {
     residual: *const c_void,
 }
 
-impl From<&SandboxShutdownRequest> for sandbox_services::ControllerShutdownRequest {
-    fn from(req: &SandboxShutdownRequest) -> Self {
+impl TryFrom<&SandboxShutdownRequest> for sandbox_services::ControllerShutdownRequest {
+    type Error = Status;
+
+    fn try_from(req: &SandboxShutdownRequest) -> Result<Self, Self::Error> {
         let mut r_req = sandbox_services::ControllerShutdownRequest::default();
-        r_req.sandbox_id = to_string(req.sandbox_id);
-        r_req.sandboxer = to_string(req.sandboxer);
-        r_req
+        r_req.sandbox_id = required_string(req.sandbox_id, "sandbox_id")?;
+        r_req.sandboxer = required_string(req.sandboxer, "sandboxer")?;
+        Ok(r_req)
     }
 }
 
@@ -394,12 +488,14 @@ pub struct SandboxMetricsRequest {
     residual: *const c_void,
 }
 
-impl From<&SandboxMetricsRequest> for sandbox_services::ControllerMetricsRequest {
-    fn from(req: &SandboxMetricsRequest) -> Self {
+impl TryFrom<&SandboxMetricsRequest> for sandbox_services::ControllerMetricsRequest {
+    type Error = Status;
+
+    fn try_from(req: &SandboxMetricsRequest) -> Result<Self, Self::Error> {
         let mut r_req = sandbox_services::ControllerMetricsRequest::default();
-        r_req.sandbox_id = to_string(req.sandbox_id);
-        r_req.sandboxer = to_string(req.sandboxer);
-        r_req
+        r_req.sandbox_id = required_string(req.sandbox_id, "sandbox_id")?;
+        r_req.sandboxer = required_string(req.sandboxer, "sandboxer")?;
+        Ok(r_req)
     }
 }
 
@@ -437,13 +533,195 @@ pub struct SandboxUpdateRequest {
     residual: *const c_void,
 }
 
-impl From<&SandboxUpdateRequest> for sandbox_services::ControllerUpdateRequest {
-    fn from(req: &SandboxUpdateRequest) -> Self {
+impl TryFrom<&SandboxUpdateRequest> for sandbox_services::ControllerUpdateRequest {
+    type Error = Status;
+
+    fn try_from(req: &SandboxUpdateRequest) -> Result<Self, Self::Error> {
         let mut r_req = sandbox_services::ControllerUpdateRequest::default();
-        r_req.sandbox_id = to_string(req.sandbox_id);
-        r_req.sandboxer = to_string(req.sandboxer);
-        r_req.sandbox = unsafe{req.sandbox.as_ref()}.map(|sandbox| sandbox::Sandbox::from(&*sandbox));
-        r_req.fields = c_char_ptr_ptr_to_vec(req.fields, req.fields_len);
-        r_req
+        r_req.sandbox_id = required_string(req.sandbox_id, "sandbox_id")?;
+        r_req.sandboxer = required_string(req.sandboxer, "sandboxer")?;
+        r_req.sandbox = match unsafe { req.sandbox.as_ref() } {
+            Some(sandbox) => Some(sandbox::Sandbox::try_from(sandbox)?),
+            None => return Err(Status::invalid_argument("sandbox is required")),
+        };
+        check_sandbox_id(&r_req.sandbox, &r_req.sandbox_id)?;
+        // The sandboxer gets the fields normalized by FieldMask: sorted and deduplicated.
+        let fields = string_array(req.fields, req.fields_len, "fields")?;
+        r_req.fields = FieldMask::parse(&fields)?.paths();
+        Ok(r_req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use tonic::Code;
+
+    const INVALID_UTF8: &[u8] = b"sb\xff\0";
+
+    fn create_request(sandbox_id: *const c_char, sandboxer: *const c_char) -> SandboxCreateRequest {
+        SandboxCreateRequest {
+            sandbox_id,
+            rootfs: std::ptr::null(),
+            rootfs_len: 0,
+            options: std::ptr::null(),
+            netns_path: std::ptr::null(),
+            annotations: std::ptr::null(),
+            sandbox: std::ptr::null(),
+            sandboxer,
+            residual: std::ptr::null(),
+        }
+    }
+
+    fn mount(type_: *const c_char, source: *const c_char, destination: *const c_char,
+             options: &[*const c_char]) -> SandboxMount {
+        SandboxMount {
+            type_,
+            source,
+            destination,
+            options: if options.is_empty() { std::ptr::null() } else { options.as_ptr() },
+            options_len: options.len(),
+            residual: std::ptr::null(),
+        }
+    }
+
+    fn sandbox(sandbox_id: *const c_char) -> SandboxSandbox {
+        SandboxSandbox {
+            sandbox_id,
+            runtime: std::ptr::null(),
+            spec: std::ptr::null(),
+            labels: std::ptr::null(),
+            created_at: 0,
+            updated_at: 0,
+            extensions: std::ptr::null(),
+            sandboxer: std::ptr::null(),
+            residual: std::ptr::null(),
+        }
+    }
+
+    fn create_error(req: &SandboxCreateRequest) -> Status {
+        sandbox_services::ControllerCreateRequest::try_from(req).unwrap_err()
+    }
+
+    #[test]
+    fn create_request_requires_valid_ids() {
+        let id = CString::new("sb-1").unwrap();
+        let sandboxer = CString::new("shim").unwrap();
+        let invalid = INVALID_UTF8.as_ptr() as *const c_char;
+
+        let req = create_request(id.as_ptr(), sandboxer.as_ptr());
+        let r_req = sandbox_services::ControllerCreateRequest::try_from(&req).unwrap();
+        assert_eq!(r_req.sandbox_id, "sb-1");
+        assert_eq!(r_req.sandboxer, "shim");
+
+        for (req, name) in [
+            (create_request(std::ptr::null(), sandboxer.as_ptr()), "sandbox_id"),
+            (create_request(invalid, sandboxer.as_ptr()), "sandbox_id"),
+            (create_request(id.as_ptr(), std::ptr::null()), "sandboxer"),
+            (create_request(id.as_ptr(), invalid), "sandboxer"),
+        ] {
+            let status = create_error(&req);
+            assert_eq!(status.code(), Code::InvalidArgument);
+            assert!(status.message().starts_with(name), "{}", status.message());
+        }
+    }
+
+    #[test]
+    fn create_request_checks_rootfs() {
+        let id = CString::new("sb-1").unwrap();
+        let sandboxer = CString::new("shim").unwrap();
+        let bind = CString::new("bind").unwrap();
+        let source = CString::new("/var/lib/sb-1/rootfs").unwrap();
+        let target = CString::new("/").unwrap();
+        let ro = CString::new("ro").unwrap();
+        let options = [ro.as_ptr()];
+
+        let good = mount(bind.as_ptr(), source.as_ptr(), target.as_ptr(), &options);
+        let rootfs = [&good as *const SandboxMount];
+        let mut req = create_request(id.as_ptr(), sandboxer.as_ptr());
+        req.rootfs = rootfs.as_ptr();
+        req.rootfs_len = rootfs.len();
+        let r_req = sandbox_services::ControllerCreateRequest::try_from(&req).unwrap();
+        assert_eq!(r_req.rootfs.len(), 1);
+        assert_eq!(r_req.rootfs[0].r#type, "bind");
+        assert_eq!(r_req.rootfs[0].source, "/var/lib/sb-1/rootfs");
+        assert_eq!(r_req.rootfs[0].target, "/");
+        assert_eq!(r_req.rootfs[0].options, vec!["ro".to_string()]);
+
+        // A null array claiming elements, a null element, missing type and
+        // source, and a null option are all rejected.
+        let no_type = mount(std::ptr::null(), source.as_ptr(), target.as_ptr(), &[]);
+        let no_source = mount(bind.as_ptr(), std::ptr::null(), target.as_ptr(), &[]);
+        let null_option = [std::ptr::null()];
+        let bad_option = mount(bind.as_ptr(), source.as_ptr(), target.as_ptr(), &null_option);
+        let cases: Vec<(Vec<*const SandboxMount>, usize, &str)> = vec![
+            (Vec::new(), 1, "rootfs is null"),
+            (vec![std::ptr::null()], 1, "rootfs[0] is null"),
+            (vec![&good, &no_type], 2, "rootfs[1]: type"),
+            (vec![&no_source], 1, "rootfs[0]: source"),
+            (vec![&bad_option], 1, "rootfs[0]: options[0] is null"),
+        ];
+        for (rootfs, len, message) in cases {
+            req.rootfs = if rootfs.is_empty() { std::ptr::null() } else { rootfs.as_ptr() };
+            req.rootfs_len = len;
+            let status = create_error(&req);
+            assert_eq!(status.code(), Code::InvalidArgument);
+            assert!(status.message().starts_with(message), "{}", status.message());
+        }
+    }
+
+    #[test]
+    fn create_request_checks_sandbox_id_of_sandbox() {
+        let id = CString::new("sb-1").unwrap();
+        let other = CString::new("sb-2").unwrap();
+        let sandboxer = CString::new("shim").unwrap();
+        let r_sandbox = sandbox(other.as_ptr());
+        let mut req = create_request(id.as_ptr(), sandboxer.as_ptr());
+        req.sandbox = &r_sandbox;
+        assert_eq!(create_error(&req).code(), Code::InvalidArgument);
+    }
+
+    #[test]
+    fn update_request_checks_fields() {
+        let id = CString::new("sb-1").unwrap();
+        let sandboxer = CString::new("shim").unwrap();
+        let r_sandbox = sandbox(id.as_ptr());
+        let paths: Vec<CString> = ["labels.a", "spec"].iter().map(|p| CString::new(*p).unwrap()).collect();
+        let fields: Vec<*const c_char> = paths.iter().map(|p| p.as_ptr()).collect();
+        let mut req = SandboxUpdateRequest {
+            sandbox_id: id.as_ptr(),
+            sandboxer: sandboxer.as_ptr(),
+            sandbox: &r_sandbox,
+            fields: fields.as_ptr(),
+            fields_len: fields.len(),
+            residual: std::ptr::null(),
+        };
+        let r_req = sandbox_services::ControllerUpdateRequest::try_from(&req).unwrap();
+        assert_eq!(r_req.fields, vec!["labels.a".to_string(), "spec".to_string()]);
+
+        for path in ["status", "labels.", "spec.x"] {
+            let path = CString::new(path).unwrap();
+            let fields = [path.as_ptr()];
+            req.fields = fields.as_ptr();
+            req.fields_len = fields.len();
+            let status = sandbox_services::ControllerUpdateRequest::try_from(&req).unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument, "{}", status.message());
+        }
+
+        let fields = [INVALID_UTF8.as_ptr() as *const c_char];
+        req.fields = fields.as_ptr();
+        let status = sandbox_services::ControllerUpdateRequest::try_from(&req).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        req.fields = std::ptr::null();
+        req.fields_len = 1;
+        let status = sandbox_services::ControllerUpdateRequest::try_from(&req).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        req.fields_len = 0;
+        req.sandbox = std::ptr::null();
+        let status = sandbox_services::ControllerUpdateRequest::try_from(&req).unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
     }
 }
@@ -11,18 +11,21 @@
 // See the Mulan PSL v2 for more details.
 
 #![crate_type = "dylib"]
-mod controller;
-mod datatype;
+pub mod controller;
+pub mod datatype;
 use controller::client;
+use controller::collector::MetricsCollector;
+use controller::status_cache::StatusCache;
 use datatype::sandbox_types;
 use tokio::time::{ sleep, Duration };
 use std::os::raw::{c_char, c_int};
 use lazy_static::lazy_static;
 use tokio::runtime::Runtime;
+use tokio::task::JoinSet;
 use async_recursion::async_recursion;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::ffi::CStr;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use isula_common::isula_data_types::{ to_string, to_c_char_ptr };
 
@@ -32,6 +35,7 @@ use controller::client::sandbox::containerd::services::sandbox::v1::ControllerPl
 use controller::client::sandbox::containerd::services::sandbox::v1::ControllerStopRequest;
 use controller::client::sandbox::containerd::services::sandbox::v1::ControllerWaitRequest;
 use controller::client::sandbox::containerd::services::sandbox::v1::ControllerStatusRequest;
+use controller::client::sandbox::containerd::services::sandbox::v1::ControllerStatusResponse;
 use controller::client::sandbox::containerd::services::sandbox::v1::ControllerShutdownRequest;
 use controller::client::sandbox::containerd::services::sandbox::v1::ControllerMetricsRequest;
 use controller::client::sandbox::containerd::services::sandbox::v1::ControllerUpdateRequest;
@@ -48,6 +52,8 @@ lazy_static! {
     };
     static ref RUNTIME_MUTEX: Mutex<i32> = Mutex::new(0);
     static ref RETRY_WAIT_MUTEX: tokio::sync::Mutex<i32> = tokio::sync::Mutex::new(0);
+    static ref METRICS_COLLECTOR: Arc<MetricsCollector> = Arc::new(MetricsCollector::new());
+    static ref STATUS_CACHE: StatusCache = StatusCache::default();
 }
 
 #[repr(C)]
@@ -119,6 +125,24 @@ macro_rules! sandbox_api_execute {
     };
 }
 
+// Convert a C request into its controller request, rejecting invalid requests
+// with InvalidArgument before any RPC is made.
+macro_rules! sandbox_api_request {
+    ($request_type:ident, $req:ident) => {
+        match $req.as_ref().map($request_type::try_from) {
+            Some(Ok(r_req)) => r_req,
+            Some(Err(e)) => {
+                println!("Sandbox API: Invalid {} request, {:?}", stringify!($request_type), e);
+                return -1;
+            }
+            None => {
+                println!("Sandbox API: Invalid {} request, request is null", stringify!($request_type));
+                return -1;
+            }
+        }
+    };
+}
+
 #[no_mangle]
 pub extern "C" fn sandbox_api_build_controller(
     sandboxer: *const c_char,
@@ -146,7 +170,7 @@ pub unsafe extern "C" fn sandbox_api_create(
     rsp: *mut sandbox_types::SandboxCreateResponse,
 ) -> c_int {
     let controller_context = &mut *handle;
-    let r_req = ControllerCreateRequest::from(&*req);
+    let r_req = sandbox_api_request!(ControllerCreateRequest, req);
     println!("Sandbox API: Create request: {:?}", r_req);
     sandbox_api_execute!(controller_context, r_req, rsp, create)
 }
@@ -158,9 +182,12 @@ pub unsafe extern "C" fn sandbox_api_start(
     rsp: *mut sandbox_types::SandboxStartResponse,
 ) -> c_int {
     let controller_context = &mut *handle;
-    let r_req= ControllerStartRequest::from(&*req);
+    let r_req = sandbox_api_request!(ControllerStartRequest, req);
     println!("Sandbox API: Start request: {:?}", r_req);
-    sandbox_api_execute!(controller_context, r_req, rsp, start)
+    let sandbox_id = r_req.sandbox_id.clone();
+    let ret = sandbox_api_execute!(controller_context, r_req, rsp, start);
+    STATUS_CACHE.invalidate(&sandbox_id);
+    ret
 }
 
 #[no_mangle]
@@ -170,7 +197,7 @@ pub unsafe extern "C" fn sandbox_api_platform(
     rsp: *mut sandbox_types::SandboxPlatformResponse,
 ) -> c_int {
     let controller_context = &mut *handle;
-    let r_req = ControllerPlatformRequest::from(&*req);
+    let r_req = sandbox_api_request!(ControllerPlatformRequest, req);
     println!("Sandbox API: Platform request: {:?}", r_req);
     sandbox_api_execute!(controller_context, r_req, rsp, platform)
 }
@@ -181,9 +208,12 @@ pub unsafe extern "C" fn sandbox_api_stop(
     req: *const sandbox_types::SandboxStopRequest,
 ) -> c_int {
     let controller_context = &mut *handle;
-    let r_req = ControllerStopRequest::from(&*req);
+    let r_req = sandbox_api_request!(ControllerStopRequest, req);
     println!("Sandbox API: Stop request: {:?}", r_req);
-    sandbox_api_execute!(controller_context, r_req, stop)
+    let sandbox_id = r_req.sandbox_id.clone();
+    let ret = sandbox_api_execute!(controller_context, r_req, stop);
+    STATUS_CACHE.invalidate(&sandbox_id);
+    ret
 }
 
 #[no_mangle]
@@ -193,9 +223,142 @@ pub unsafe extern "C" fn sandbox_api_status(
     rsp: *mut sandbox_types::SandboxStatusResponse,
 ) -> c_int {
     let controller_context = &mut *handle;
-    let r_req = ControllerStatusRequest::from(&*req);
+    let r_req = sandbox_api_request!(ControllerStatusRequest, req);
     println!("Sandbox API: Status request: {:?}", r_req);
-    sandbox_api_execute!(controller_context, r_req, rsp, status)
+    if let Some(status) = STATUS_CACHE.get(&r_req.sandbox_id, r_req.verbose) {
+        (*rsp).from_controller(&status);
+        return 0;
+    }
+    let sandbox_id = r_req.sandbox_id.clone();
+    let verbose = r_req.verbose;
+    let generation = STATUS_CACHE.generation(&sandbox_id);
+    match controller_context.get_client() {
+        Some(client) => {
+            let _rt_lock = RUNTIME_MUTEX.lock().unwrap();
+            match RT.block_on(client.status(r_req)) {
+                Ok(response) => {
+                    STATUS_CACHE.insert(&sandbox_id, generation, verbose, &response);
+                    (*rsp).from_controller(&response);
+                    0
+                }
+                Err(e) => {
+                    println!("Sandbox API: Failed to execute sandbox API, {:?}", e);
+                    -1
+                }
+            }
+        }
+        None => {
+            println!("Sandbox API: Failed to execute sandbox API, client is None");
+            -1
+        }
+    }
+}
+
+async fn do_status_many(
+    client: client::Client,
+    r_reqs: Vec<Result<ControllerStatusRequest, tonic::Status>>,
+) -> Vec<Result<ControllerStatusResponse, tonic::Status>> {
+    let mut results: Vec<Result<ControllerStatusResponse, tonic::Status>> = Vec::with_capacity(r_reqs.len());
+    let mut tasks = JoinSet::new();
+    for (i, r_req) in r_reqs.into_iter().enumerate() {
+        let r_req = match r_req {
+            Ok(r_req) => r_req,
+            Err(e) => {
+                results.push(Err(e));
+                continue;
+            }
+        };
+        if let Some(status) = STATUS_CACHE.get(&r_req.sandbox_id, r_req.verbose) {
+            results.push(Ok(status));
+            continue;
+        }
+        results.push(Err(tonic::Status::unknown("status not collected")));
+        let mut client = client.clone();
+        let generation = STATUS_CACHE.generation(&r_req.sandbox_id);
+        tasks.spawn(async move {
+            let sandbox_id = r_req.sandbox_id.clone();
+            let verbose = r_req.verbose;
+            let result = client.status(r_req).await;
+            if let Ok(response) = result.as_ref() {
+                STATUS_CACHE.insert(&sandbox_id, generation, verbose, response);
+            }
+            (i, result)
+        });
+    }
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((i, result)) => results[i] = result,
+            Err(e) => println!("Sandbox API: Status task failed, {:?}", e),
+        }
+    }
+    results
+}
+
+// Query the status of many sandboxes of one controller concurrently.
+// codes[i] receives the gRPC code of the i-th query, 0 meaning rsps[i] has
+// been filled. The call itself only fails on invalid arguments or when the
+// controller cannot be reached.
+#[no_mangle]
+pub unsafe extern "C" fn sandbox_api_status_many(
+    handle: ControllerHandle,
+    reqs: *const *const sandbox_types::SandboxStatusRequest,
+    rsps: *const *mut sandbox_types::SandboxStatusResponse,
+    codes: *mut c_int,
+    len: usize,
+) -> c_int {
+    if len == 0 {
+        return 0;
+    }
+    if reqs.is_null() || rsps.is_null() || codes.is_null() {
+        println!("Sandbox API: Invalid status many request, arrays must not be null");
+        return -1;
+    }
+    let controller_context = &mut *handle;
+    let reqs = std::slice::from_raw_parts(reqs, len);
+    let rsps = std::slice::from_raw_parts(rsps, len);
+    let codes = std::slice::from_raw_parts_mut(codes, len);
+    #[allow(clippy::result_large_err)]
+    let r_reqs: Vec<Result<ControllerStatusRequest, tonic::Status>> = reqs.iter()
+        .map(|req| match req.as_ref() {
+            Some(req) => ControllerStatusRequest::try_from(req),
+            None => Err(tonic::Status::invalid_argument("request is null")),
+        })
+        .collect();
+    println!("Sandbox API: Status many request for {} sandboxes", len);
+
+    let client = match controller_context.get_client() {
+        Some(client) => client.clone(),
+        None => {
+            println!("Sandbox API: Failed to execute sandbox API, client is None");
+            return -1;
+        }
+    };
+    let results = {
+        let _rt_lock = RUNTIME_MUTEX.lock().unwrap();
+        RT.block_on(do_status_many(client, r_reqs))
+    };
+    for (i, result) in results.iter().enumerate() {
+        match result {
+            Ok(response) => match rsps[i].as_mut() {
+                Some(rsp) => {
+                    rsp.from_controller(response);
+                    codes[i] = 0;
+                }
+                None => codes[i] = tonic::Code::InvalidArgument as c_int,
+            },
+            Err(e) => {
+                println!("Sandbox API: Failed to get status of item {}, {:?}", i, e);
+                codes[i] = e.code() as c_int;
+            }
+        }
+    }
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn sandbox_api_status_cache_set_ttl(ttl_ms: u64) {
+    STATUS_CACHE.set_ttl(Duration::from_millis(ttl_ms));
+    println!("Sandbox API: Status cache ttl set to {}ms", ttl_ms);
 }
 
 #[no_mangle]
@@ -204,9 +367,13 @@ pub unsafe extern "C" fn sandbox_api_shutdown(
     req: *const sandbox_types::SandboxShutdownRequest,
 ) -> c_int {
     let controller_context = &mut *handle;
-    let r_req = ControllerShutdownRequest::from(&*req);
+    let r_req = sandbox_api_request!(ControllerShutdownRequest, req);
     println!("Sandbox API: Shutdown request: {:?}", r_req);
-    sandbox_api_execute!(controller_context, r_req, shutdown)
+    let sandbox_id = r_req.sandbox_id.clone();
+    let ret = sandbox_api_execute!(controller_context, r_req, shutdown);
+    METRICS_COLLECTOR.unregister(&sandbox_id);
+    STATUS_CACHE.remove(&sandbox_id);
+    ret
 }
 
 #[no_mangle]
@@ -216,7 +383,7 @@ pub unsafe extern "C" fn sandbox_api_metrics(
     rsp: *mut sandbox_types::SandboxMetricsResponse,
 ) -> c_int {
     let controller_context = &mut *handle;
-    let r_req = ControllerMetricsRequest::from(&*req);
+    let r_req = sandbox_api_request!(ControllerMetricsRequest, req);
     println!("Sandbox API: Metrics request: {:?}", r_req);
     sandbox_api_execute!(controller_context, r_req, rsp, metrics)
 }
@@ -227,9 +394,87 @@ pub unsafe extern "C" fn sandbox_api_update(
     req: *const sandbox_types::SandboxUpdateRequest,
 ) -> c_int {
     let controller_context = &mut *handle;
-    let r_req = ControllerUpdateRequest::from(&*req);
+    let r_req = sandbox_api_request!(ControllerUpdateRequest, req);
     println!("Sandbox API: Update request: {:?}", r_req);
-    sandbox_api_execute!(controller_context, r_req, update)
+    let sandbox_id = r_req.sandbox_id.clone();
+    let ret = sandbox_api_execute!(controller_context, r_req, update);
+    STATUS_CACHE.invalidate(&sandbox_id);
+    ret
+}
+
+#[no_mangle]
+pub extern "C" fn sandbox_api_metrics_collector_start(interval_ms: u64) -> c_int {
+    let _rt_lock = RUNTIME_MUTEX.lock().unwrap();
+    match METRICS_COLLECTOR.start(&RT, Duration::from_millis(interval_ms)) {
+        Ok(_) => {
+            println!("Sandbox API: Metrics collector started, interval: {}ms", interval_ms);
+            0
+        }
+        Err(e) => {
+            println!("Sandbox API: Failed to start metrics collector, {:?}", e);
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn sandbox_api_metrics_collector_stop() {
+    METRICS_COLLECTOR.stop();
+    println!("Sandbox API: Metrics collector stopped");
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sandbox_api_metrics_register(
+    handle: ControllerHandle,
+    req: *const sandbox_types::SandboxMetricsRequest,
+) -> c_int {
+    let controller_context = &mut *handle;
+    let r_req = sandbox_api_request!(ControllerMetricsRequest, req);
+    println!("Sandbox API: Metrics register request: {:?}", r_req);
+    match controller_context.get_client() {
+        Some(client) => {
+            METRICS_COLLECTOR.register(client.clone(), r_req);
+            0
+        }
+        None => {
+            println!("Sandbox API: Failed to register sandbox metrics, client is None");
+            -1
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn sandbox_api_metrics_unregister(sandbox_id: *const c_char) -> c_int {
+    let r_sandbox_id = to_string(sandbox_id);
+    if !METRICS_COLLECTOR.unregister(&r_sandbox_id) {
+        println!("Sandbox API: Sandbox metrics not registered, {:?}", r_sandbox_id);
+        return -1;
+    }
+    0
+}
+
+// Return the last metrics collected for the sandbox without blocking on the
+// sandboxer, along with how long ago they were collected.
+#[no_mangle]
+pub unsafe extern "C" fn sandbox_api_cached_metrics(
+    sandbox_id: *const c_char,
+    rsp: *mut sandbox_types::SandboxMetricsResponse,
+    age_ms: *mut u64,
+) -> c_int {
+    if rsp.is_null() {
+        return -1;
+    }
+    let r_sandbox_id = to_string(sandbox_id);
+    match METRICS_COLLECTOR.snapshot(&r_sandbox_id) {
+        Some(snapshot) => {
+            (*rsp).from_controller(&snapshot.metrics);
+            if !age_ms.is_null() {
+                *age_ms = snapshot.age().as_millis() as u64;
+            }
+            0
+        }
+        None => -1,
+    }
 }
 
 pub type SandboxReadyCallback = extern "C" fn(*const c_char);
@@ -293,6 +538,7 @@ fn do_failed_exit_wait (
                                         .unwrap_or_default()
                                         .as_secs() as u64;
     r_rsp.sandbox_id = to_c_char_ptr(sandbox_id.as_str());
+    STATUS_CACHE.invalidate(sandbox_id);
     callback_execute!(sandbox_id, callback, exit, &r_rsp);
     unsafe {
         let _ = CStr::from_ptr(r_rsp.sandbox_id);
@@ -314,6 +560,7 @@ async fn do_wait(
             r_rsp.from_controller(&response);
             r_rsp.sandbox_id = to_c_char_ptr(sandbox_id.as_str());
             println!("Sandbox API: Wait finished successful, {:?}", sandbox_id);
+            STATUS_CACHE.invalidate(&sandbox_id);
             callback_execute!(sandbox_id, callback, exit, &r_rsp);
             unsafe {
                 let _ = CStr::from_ptr(r_rsp.sandbox_id);
@@ -380,7 +627,7 @@ pub unsafe extern "C" fn sandbox_api_wait(
     callback: SandboxWaitCallback,
 ) -> c_int {
     let controller_context = &mut *handle;
-    let r_req = ControllerWaitRequest::from(&*req);
+    let r_req = sandbox_api_request!(ControllerWaitRequest, req);
     println!("Sandbox API: Wait request: {:?}", r_req);
     match controller_context.get_client() {
         Some(client) => {